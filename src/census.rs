@@ -0,0 +1,310 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use near_primitives::{
+    network::PeerId,
+    types::{BlockHeight, NumShards, ShardId},
+    version::ProtocolVersion,
+};
+
+use crate::network_protocol::Handshake;
+
+/// State of the node reported in its handshake
+struct NodeInfo {
+    protocol_version: ProtocolVersion,
+    oldest_supported_version: ProtocolVersion,
+    height: BlockHeight,
+    tracked_shards: Vec<ShardId>,
+    archival: bool,
+}
+
+/// Nodes ready for the upgrade to the protocol version (the ones running a version at least
+/// as new)
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Upgrade {
+    pub version: ProtocolVersion,
+    pub ready: usize,
+}
+
+/// Number of the nodes tracking the shard
+#[derive(Serialize, Debug, PartialEq)]
+pub struct ShardCoverage {
+    pub shard_id: ShardId,
+    pub nodes: usize,
+    pub under_served: bool,
+}
+
+/// Distribution of the head heights of the nodes
+#[derive(Serialize, Debug, PartialEq)]
+pub struct HeightSpread {
+    pub min: BlockHeight,
+    pub p10: BlockHeight,
+    pub median: BlockHeight,
+    pub p90: BlockHeight,
+    pub max: BlockHeight,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Report {
+    pub nodes: usize,
+    pub unreachable: usize,
+    pub archival: usize,
+    /// Nodes by the latest protocol version they run
+    pub protocol_versions: BTreeMap<ProtocolVersion, usize>,
+    pub oldest_supported_versions: BTreeMap<ProtocolVersion, usize>,
+    pub upgrade: Option<Upgrade>,
+    pub shards: Vec<ShardCoverage>,
+    /// Nodes not tracking any shard
+    pub untracked: usize,
+    pub heights: Option<HeightSpread>,
+}
+
+impl Report {
+    /// The report as "section,key,value" rows (e.g. "protocol_version,57,120")
+    pub fn rows(&self) -> Vec<[String; 3]> {
+        let row = |section: &str, key: &dyn ToString, value: &dyn ToString| {
+            [section.into(), key.to_string(), value.to_string()]
+        };
+
+        let mut rows = vec![
+            row("nodes", &"reachable", &self.nodes),
+            row("nodes", &"unreachable", &self.unreachable),
+            row("nodes", &"archival", &self.archival),
+            row("nodes", &"untracked", &self.untracked),
+        ];
+        for (version, nodes) in &self.protocol_versions {
+            rows.push(row("protocol_version", version, nodes));
+        }
+        for (version, nodes) in &self.oldest_supported_versions {
+            rows.push(row("oldest_supported_version", version, nodes));
+        }
+        if let Some(upgrade) = &self.upgrade {
+            rows.push(row("upgrade_ready", &upgrade.version, &upgrade.ready));
+        }
+        for shard in &self.shards {
+            rows.push(row("shard", &shard.shard_id, &shard.nodes));
+        }
+        if let Some(heights) = &self.heights {
+            rows.push(row("height", &"min", &heights.min));
+            rows.push(row("height", &"p10", &heights.p10));
+            rows.push(row("height", &"median", &heights.median));
+            rows.push(row("height", &"p90", &heights.p90));
+            rows.push(row("height", &"max", &heights.max));
+        }
+        rows
+    }
+}
+
+/// Aggregates the handshakes of the reachable nodes (each node counted once, by its last
+/// handshake) into the census report
+pub struct Census {
+    nodes: BTreeMap<PeerId, NodeInfo>,
+    unreachable: usize,
+    num_shards: Option<NumShards>,
+    min_shard_nodes: usize,
+    upgrade_version: Option<ProtocolVersion>,
+}
+
+impl Census {
+    /// The shards below `num_shards` are reported even if no node tracks them, the shards
+    /// tracked by fewer than `min_shard_nodes` are under-served. The upgrade readiness is
+    /// reported for `upgrade_version`, the latest version seen by default.
+    pub fn new(
+        num_shards: Option<NumShards>,
+        min_shard_nodes: usize,
+        upgrade_version: Option<ProtocolVersion>,
+    ) -> Self {
+        Self {
+            nodes: BTreeMap::new(),
+            unreachable: 0,
+            num_shards,
+            min_shard_nodes,
+            upgrade_version,
+        }
+    }
+
+    pub fn record(&mut self, handshake: &Handshake) {
+        let chain_info = &handshake.sender_chain_info;
+        self.nodes.insert(
+            handshake.sender_peer_id.clone(),
+            NodeInfo {
+                protocol_version: handshake.protocol_version,
+                oldest_supported_version: handshake.oldest_supported_version,
+                height: chain_info.height,
+                tracked_shards: chain_info.tracked_shards.clone(),
+                archival: chain_info.archival,
+            },
+        );
+    }
+
+    pub fn record_unreachable(&mut self) {
+        self.unreachable += 1;
+    }
+
+    pub fn report(&self) -> Report {
+        let mut protocol_versions = BTreeMap::new();
+        let mut oldest_supported_versions = BTreeMap::new();
+        let mut shards: BTreeMap<ShardId, usize> = (0..self.num_shards.unwrap_or(0))
+            .map(|id| (id, 0))
+            .collect();
+
+        for node in self.nodes.values() {
+            *protocol_versions.entry(node.protocol_version).or_default() += 1;
+            *oldest_supported_versions
+                .entry(node.oldest_supported_version)
+                .or_default() += 1;
+            for shard_id in &node.tracked_shards {
+                *shards.entry(*shard_id).or_default() += 1;
+            }
+        }
+
+        let upgrade = self
+            .upgrade_version
+            .or_else(|| protocol_versions.keys().next_back().copied())
+            .map(|version| Upgrade {
+                version,
+                ready: self
+                    .nodes
+                    .values()
+                    .filter(|node| node.protocol_version >= version)
+                    .count(),
+            });
+
+        let mut heights: Vec<_> = self.nodes.values().map(|node| node.height).collect();
+        heights.sort_unstable();
+        // Nearest-rank percentile
+        let percentile = |p: usize| heights[(heights.len() * p).div_ceil(100).max(1) - 1];
+        let heights = (!heights.is_empty()).then(|| HeightSpread {
+            min: heights[0],
+            p10: percentile(10),
+            median: percentile(50),
+            p90: percentile(90),
+            max: heights[heights.len() - 1],
+        });
+
+        Report {
+            nodes: self.nodes.len(),
+            unreachable: self.unreachable,
+            archival: self.nodes.values().filter(|node| node.archival).count(),
+            protocol_versions,
+            oldest_supported_versions,
+            upgrade,
+            shards: shards
+                .into_iter()
+                .map(|(shard_id, nodes)| ShardCoverage {
+                    shard_id,
+                    nodes,
+                    under_served: nodes < self.min_shard_nodes,
+                })
+                .collect(),
+            untracked: self
+                .nodes
+                .values()
+                .filter(|node| node.tracked_shards.is_empty())
+                .count(),
+            heights,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{ED25519PublicKey, PublicKey};
+
+    use crate::network_protocol::PeerChainInfo;
+
+    use super::*;
+
+    fn handshake(
+        i: u8,
+        protocol_version: ProtocolVersion,
+        height: BlockHeight,
+        tracked_shards: Vec<ShardId>,
+        archival: bool,
+    ) -> Handshake {
+        let peer_id = |i| PeerId::new(PublicKey::ED25519(ED25519PublicKey([i; 32])));
+        Handshake {
+            protocol_version,
+            oldest_supported_version: protocol_version - 2,
+            sender_peer_id: peer_id(i),
+            target_peer_id: peer_id(0),
+            sender_listen_port: None,
+            sender_chain_info: PeerChainInfo {
+                height,
+                tracked_shards,
+                archival,
+                ..Default::default()
+            },
+            partial_edge_info: Default::default(),
+        }
+    }
+
+    #[test]
+    fn test_census() {
+        let mut census = Census::new(Some(4), 2, None);
+        assert_eq!(census.report().upgrade, None);
+        assert_eq!(census.report().heights, None);
+
+        census.record(&handshake(1, 57, 90, vec![0, 1, 2, 3], true));
+        census.record(&handshake(2, 57, 100, vec![0], false));
+        census.record(&handshake(3, 58, 101, vec![0, 1], false));
+        census.record(&handshake(4, 56, 50, vec![], false));
+        // The node handshaken again is counted once, by the last handshake
+        census.record(&handshake(4, 58, 102, vec![], false));
+        census.record_unreachable();
+
+        let report = census.report();
+        assert_eq!(report.nodes, 4);
+        assert_eq!(report.unreachable, 1);
+        assert_eq!(report.archival, 1);
+        assert_eq!(report.untracked, 1);
+        assert_eq!(report.protocol_versions, BTreeMap::from([(57, 2), (58, 2)]));
+        assert_eq!(
+            report.oldest_supported_versions,
+            BTreeMap::from([(55, 2), (56, 2)])
+        );
+        assert_eq!(
+            report.upgrade,
+            Some(Upgrade {
+                version: 58,
+                ready: 2
+            })
+        );
+        assert_eq!(
+            report
+                .shards
+                .iter()
+                .map(|shard| (shard.shard_id, shard.nodes, shard.under_served))
+                .collect::<Vec<_>>(),
+            vec![(0, 3, false), (1, 2, false), (2, 1, true), (3, 1, true)]
+        );
+        assert_eq!(
+            report.heights,
+            Some(HeightSpread {
+                min: 90,
+                p10: 90,
+                median: 100,
+                p90: 102,
+                max: 102,
+            })
+        );
+        assert!(report
+            .rows()
+            .contains(&["protocol_version".into(), "58".into(), "2".into()]));
+
+        // Readiness for the given version
+        let mut census = Census::new(None, 2, Some(57));
+        census.record(&handshake(1, 57, 90, vec![0], false));
+        census.record(&handshake(2, 56, 90, vec![0], false));
+        let report = census.report();
+        assert_eq!(
+            report.upgrade,
+            Some(Upgrade {
+                version: 57,
+                ready: 1
+            })
+        );
+        assert_eq!(report.shards.len(), 1);
+    }
+}
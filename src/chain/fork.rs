@@ -0,0 +1,76 @@
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+
+use near_primitives::{block::BlockHeader, hash::CryptoHash, network::PeerId, types::BlockHeight};
+
+/// Block the peers following the branch have at the forked height
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Branch {
+    pub hash: CryptoHash,
+    pub peers: Vec<PeerId>,
+}
+
+/// Height the peers disagree on, the largest branch first
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Fork {
+    pub height: BlockHeight,
+    pub branches: Vec<Branch>,
+}
+
+/// Compares the hashes of the headers the peers report at the same heights
+#[derive(Default)]
+pub struct ForkDetector {
+    hashes: BTreeMap<BlockHeight, BTreeMap<CryptoHash, Vec<PeerId>>>,
+}
+
+impl ForkDetector {
+    pub fn record_headers(&mut self, peer_id: &PeerId, headers: &[BlockHeader]) {
+        for header in headers {
+            let peers = self
+                .hashes
+                .entry(header.height())
+                .or_default()
+                .entry(*header.hash())
+                .or_default();
+            if !peers.contains(peer_id) {
+                peers.push(peer_id.clone());
+            }
+        }
+    }
+
+    /// Range of the heights reported by more than one peer (the heights compared), None if
+    /// there are no such heights
+    pub fn compared(&self) -> Option<(BlockHeight, BlockHeight)> {
+        let mut heights = self
+            .hashes
+            .iter()
+            .filter(|(_, hashes)| hashes.values().map(Vec::len).sum::<usize>() > 1)
+            .map(|(height, _)| *height);
+        let first = heights.next()?;
+        Some((first, heights.next_back().unwrap_or(first)))
+    }
+
+    /// The heights with more than one block, the lowest first
+    pub fn forks(&self) -> Vec<Fork> {
+        self.hashes
+            .iter()
+            .filter(|(_, hashes)| hashes.len() > 1)
+            .map(|(height, hashes)| {
+                let mut branches: Vec<Branch> = hashes
+                    .iter()
+                    .map(|(hash, peers)| Branch {
+                        hash: *hash,
+                        peers: peers.clone(),
+                    })
+                    .collect();
+                branches.sort_by_key(|branch| std::cmp::Reverse(branch.peers.len()));
+
+                Fork {
+                    height: *height,
+                    branches,
+                }
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,208 @@
+use std::{collections::BTreeMap, fmt};
+
+use tokio::time::{Duration, Instant};
+
+use near_primitives::{block::BlockHeader, hash::CryptoHash, network::PeerId, types::BlockHeight};
+
+use crate::network_protocol::{BlockResponse, PeerChainInfo, PeerMessage};
+
+/// Change of the state of the peer head
+#[derive(Debug, PartialEq)]
+pub enum Alert {
+    Behind {
+        height: BlockHeight,
+        best_height: BlockHeight,
+    },
+    CaughtUp {
+        height: BlockHeight,
+    },
+    Stalled {
+        height: BlockHeight,
+        stalled_secs: u64,
+    },
+    Resumed {
+        height: BlockHeight,
+    },
+}
+
+impl fmt::Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Behind {
+                height,
+                best_height,
+            } => write!(
+                f,
+                "fell behind at {} ({} blocks behind the best known head {})",
+                height,
+                best_height - height,
+                best_height
+            ),
+            Self::CaughtUp { height } => write!(f, "caught up ({})", height),
+            Self::Stalled {
+                height,
+                stalled_secs,
+            } => write!(f, "head stalled at {} for {} seconds", height, stalled_secs),
+            Self::Resumed { height } => write!(f, "head resumed ({})", height),
+        }
+    }
+}
+
+/// Best known head of the peer
+pub struct PeerHead {
+    pub height: BlockHeight,
+    /// Hash of the head block (not known if the height was reported in the handshake)
+    pub hash: Option<CryptoHash>,
+    pub height_changed_at: Instant,
+    behind: bool,
+    stalled: bool,
+}
+
+/// Tracks the heads of the peers (reported in the handshakes, and the blocks the peers
+/// broadcast) and the best known head overall, detecting the peers that fell behind the
+/// best head by more than the allowed lag, or whose head didn't grow for the stall timeout
+pub struct HeadTracker {
+    heads: BTreeMap<PeerId, PeerHead>,
+    max_lag: BlockHeight,
+    stall_timeout: Duration,
+}
+
+impl HeadTracker {
+    pub fn new(max_lag: BlockHeight, stall_timeout: Duration) -> Self {
+        Self {
+            heads: BTreeMap::new(),
+            max_lag,
+            stall_timeout,
+        }
+    }
+
+    pub fn heads(&self) -> impl Iterator<Item = (&PeerId, &PeerHead)> {
+        self.heads.iter()
+    }
+
+    /// Best known head overall: its height, hash (if known) and the peers having it
+    pub fn best(&self) -> Option<(BlockHeight, Option<CryptoHash>, Vec<&PeerId>)> {
+        let height = self.heads.values().map(|head| head.height).max()?;
+        let peers: Vec<_> = self
+            .heads
+            .iter()
+            .filter(|(_, head)| head.height == height)
+            .collect();
+        let hash = peers.iter().find_map(|(_, head)| head.hash);
+        Some((height, hash, peers.into_iter().map(|(id, _)| id).collect()))
+    }
+
+    /// Records the height the peer reported in the handshake
+    pub fn record_handshake(
+        &mut self,
+        peer_id: &PeerId,
+        chain_info: &PeerChainInfo,
+        now: Instant,
+    ) -> Vec<(PeerId, Alert)> {
+        self.update(peer_id, chain_info.height, None, now);
+        self.check(now)
+    }
+
+    /// Records the block the peer sent (the block response requested or not), other
+    /// messages are ignored
+    pub fn record_message(
+        &mut self,
+        peer_id: &PeerId,
+        msg: &PeerMessage,
+        now: Instant,
+    ) -> Vec<(PeerId, Alert)> {
+        match BlockResponse::try_from(msg) {
+            Ok(BlockResponse(block)) => self.record_block(peer_id, block.header(), now),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    pub fn record_block(
+        &mut self,
+        peer_id: &PeerId,
+        header: &BlockHeader,
+        now: Instant,
+    ) -> Vec<(PeerId, Alert)> {
+        self.update(peer_id, header.height(), Some(*header.hash()), now);
+        self.check(now)
+    }
+
+    /// The head only grows: a lower height (e.g. an older block relayed by the peer) is
+    /// ignored
+    fn update(
+        &mut self,
+        peer_id: &PeerId,
+        height: BlockHeight,
+        hash: Option<CryptoHash>,
+        now: Instant,
+    ) {
+        let head = self.heads.entry(peer_id.clone()).or_insert(PeerHead {
+            height,
+            hash,
+            height_changed_at: now,
+            behind: false,
+            stalled: false,
+        });
+
+        if height > head.height {
+            head.height = height;
+            head.hash = hash;
+            head.height_changed_at = now;
+        } else if height == head.height && hash.is_some() {
+            head.hash = hash;
+        }
+    }
+
+    /// Checks the heads of all the peers at `now`, returns the state changes
+    pub fn check(&mut self, now: Instant) -> Vec<(PeerId, Alert)> {
+        let best_height = match self.best() {
+            Some((height, ..)) => height,
+            None => return Vec::new(),
+        };
+
+        let mut alerts = Vec::new();
+
+        for (peer_id, head) in &mut self.heads {
+            let behind = best_height - head.height > self.max_lag;
+            if behind && !head.behind {
+                alerts.push((
+                    peer_id.clone(),
+                    Alert::Behind {
+                        height: head.height,
+                        best_height,
+                    },
+                ));
+            } else if !behind && head.behind {
+                alerts.push((
+                    peer_id.clone(),
+                    Alert::CaughtUp {
+                        height: head.height,
+                    },
+                ));
+            }
+            head.behind = behind;
+
+            let stalled_for = now.duration_since(head.height_changed_at);
+            let stalled = stalled_for >= self.stall_timeout;
+            if stalled && !head.stalled {
+                alerts.push((
+                    peer_id.clone(),
+                    Alert::Stalled {
+                        height: head.height,
+                        stalled_secs: stalled_for.as_secs(),
+                    },
+                ));
+            } else if !stalled && head.stalled {
+                alerts.push((
+                    peer_id.clone(),
+                    Alert::Resumed {
+                        height: head.height,
+                    },
+                ));
+            }
+            head.stalled = stalled;
+        }
+
+        alerts
+    }
+}
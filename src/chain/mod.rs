@@ -0,0 +1,8 @@
+mod fork;
+mod head;
+
+#[cfg(test)]
+mod tests;
+
+pub use fork::{Fork, ForkDetector};
+pub use head::HeadTracker;
@@ -0,0 +1,180 @@
+use borsh::BorshSerialize;
+
+use serde_json::json;
+
+use tokio::time::{Duration, Instant};
+
+use near_crypto::{ED25519PublicKey, PublicKey};
+
+use near_primitives::{block::Block, hash::CryptoHash, network::PeerId, version::PROTOCOL_VERSION};
+
+use crate::network_protocol::{message_from_json, PeerChainInfo};
+
+use super::{
+    fork::{Branch, Fork},
+    head::Alert,
+    ForkDetector, HeadTracker,
+};
+
+fn peer_id(i: u8) -> PeerId {
+    PeerId::new(PublicKey::ED25519(ED25519PublicKey([i; 32])))
+}
+
+fn block(height: u64) -> Block {
+    branch_block(height, 0)
+}
+
+/// Blocks of different branches have different hashes at the same height
+fn branch_block(height: u64, branch: u8) -> Block {
+    Block::genesis(
+        PROTOCOL_VERSION,
+        vec![],
+        chrono::Utc::now(),
+        height,
+        0,
+        0,
+        CryptoHash([branch; 32]),
+    )
+}
+
+fn chain_info(height: u64) -> PeerChainInfo {
+    PeerChainInfo {
+        height,
+        ..Default::default()
+    }
+}
+
+#[test]
+fn test_head_tracker() {
+    let (a, b, c) = (peer_id(1), peer_id(2), peer_id(3));
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    let mut tracker = HeadTracker::new(5, Duration::from_secs(60));
+    assert!(tracker.best().is_none());
+
+    for peer_id in [&a, &b, &c] {
+        assert!(tracker
+            .record_handshake(peer_id, &chain_info(100), at(0))
+            .is_empty());
+    }
+
+    // The block broadcast by a peer moves its head, the others fall behind
+    let block_110 = block(110);
+    assert_eq!(
+        tracker.record_block(&a, block_110.header(), at(10)),
+        vec![
+            (
+                b.clone(),
+                Alert::Behind {
+                    height: 100,
+                    best_height: 110
+                }
+            ),
+            (
+                c.clone(),
+                Alert::Behind {
+                    height: 100,
+                    best_height: 110
+                }
+            ),
+        ]
+    );
+    assert_eq!(
+        tracker.best(),
+        Some((110, Some(*block_110.hash()), vec![&a]))
+    );
+
+    assert_eq!(
+        tracker.record_handshake(&b, &chain_info(108), at(20)),
+        vec![(b.clone(), Alert::CaughtUp { height: 108 })]
+    );
+
+    // The head that doesn't grow for the stall timeout is stalled
+    assert!(tracker.check(at(59)).is_empty());
+    assert_eq!(
+        tracker.check(at(60)),
+        vec![(
+            c.clone(),
+            Alert::Stalled {
+                height: 100,
+                stalled_secs: 60
+            }
+        )]
+    );
+    assert!(tracker.check(at(61)).is_empty());
+
+    // The blocks sent as block responses are recorded too, the lower heights are ignored
+    let block_111 = block(111);
+    let borsh = format!("hex:{}", hex::encode(block_111.try_to_vec().unwrap()));
+    let msg = message_from_json(&json!({
+        "block_response": { "block": { "borsh": borsh } }
+    }))
+    .unwrap();
+    assert_eq!(
+        tracker.record_message(&c, &msg, at(65)),
+        vec![
+            (c.clone(), Alert::CaughtUp { height: 111 }),
+            (c.clone(), Alert::Resumed { height: 111 }),
+        ]
+    );
+    assert!(tracker
+        .record_handshake(&c, &chain_info(90), at(66))
+        .is_empty());
+    assert_eq!(
+        tracker.best(),
+        Some((111, Some(*block_111.hash()), vec![&c]))
+    );
+
+    let heights: Vec<_> = tracker.heads().map(|(_, head)| head.height).collect();
+    assert_eq!(heights, [110, 108, 111]);
+}
+
+#[test]
+fn test_fork_detector() {
+    let (a, b, c) = (peer_id(1), peer_id(2), peer_id(3));
+
+    let mut detector = ForkDetector::default();
+    assert!(detector.compared().is_none());
+
+    let common: Vec<_> = (10..13).map(|height| branch_block(height, 0)).collect();
+    let other: Vec<_> = (12..14).map(|height| branch_block(height, 1)).collect();
+
+    let headers = |blocks: &[&Block]| -> Vec<_> {
+        blocks.iter().map(|block| block.header().clone()).collect()
+    };
+
+    detector.record_headers(&a, &headers(&[&common[0], &common[1], &common[2]]));
+    assert!(detector.compared().is_none());
+
+    // The same headers reported twice by the peer don't count
+    detector.record_headers(&a, &headers(&[&common[2]]));
+    assert!(detector.compared().is_none());
+
+    detector.record_headers(&b, &headers(&[&common[0], &common[1], &common[2]]));
+    assert_eq!(detector.compared(), Some((10, 12)));
+    assert!(detector.forks().is_empty());
+
+    // The third peer follows another branch from the height 12
+    detector.record_headers(
+        &c,
+        &headers(&[&common[0], &common[1], &other[0], &other[1]]),
+    );
+    assert_eq!(detector.compared(), Some((10, 12)));
+    assert_eq!(
+        detector.forks(),
+        vec![Fork {
+            height: 12,
+            branches: vec![
+                Branch {
+                    hash: *common[2].hash(),
+                    peers: vec![a.clone(), b.clone()],
+                },
+                Branch {
+                    hash: *other[0].hash(),
+                    peers: vec![c.clone()],
+                },
+            ],
+        }]
+    );
+}
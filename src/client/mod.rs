@@ -0,0 +1,263 @@
+use std::{
+    io,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufReader, WriteHalf},
+    sync::{mpsc, oneshot},
+    time::{self, Duration},
+};
+
+use near_primitives::{
+    block::{Block, BlockHeader},
+    hash::CryptoHash,
+    syncing,
+    types::EpochId,
+};
+
+use crate::{
+    connection::{Connection, ConnectionReader, ConnectionWriter, Session, TcpTransport},
+    network_protocol::{
+        BlockHeadersRequest, BlockHeadersResponse, BlockRequest, BlockResponse, EpochSyncRequest,
+        EpochSyncResponse, ExpectedResponse, NetworkError, PeerInfo, PeerMessage, PeersRequest,
+        PeersResponse, ReceivedMessage, ResponseContent, ResponseMatch,
+    },
+};
+
+mod pool;
+
+#[cfg(test)]
+mod tests;
+
+pub use pool::{ConnectFn, PeerPool, PoolOptions};
+
+/// Messages received from the node that are not responses to the requests of the client,
+/// ending with the error the connection failed with (if it's not closed by the node)
+pub type Broadcasts = mpsc::UnboundedReceiver<io::Result<PeerMessage>>;
+
+/// Client of the connection established with `TcpConnection`
+pub type TcpClient = Client<WriteHalf<BufReader<TcpTransport>>>;
+
+/// Request waiting for the response
+struct Pending {
+    id: u64,
+    expected: ExpectedResponse,
+    sender: oneshot::Sender<ReceivedMessage>,
+}
+
+type PendingRequests = Arc<Mutex<Vec<Pending>>>;
+
+/// Removes the pending request when the caller stops waiting for the response (e.g. times
+/// out or is cancelled)
+struct PendingGuard<'a> {
+    pending: &'a PendingRequests,
+    id: u64,
+}
+
+impl Drop for PendingGuard<'_> {
+    fn drop(&mut self) {
+        self.pending
+            .lock()
+            .unwrap()
+            .retain(|request| request.id != self.id);
+    }
+}
+
+/// Client sending the requests over the established connection and matching the responses
+/// to them (by the type of the response, and its content where possible). Can be cloned to
+/// send the requests concurrently.
+pub struct Client<W> {
+    writer: Arc<tokio::sync::Mutex<ConnectionWriter<W>>>,
+    session: Arc<Session>,
+    pending: PendingRequests,
+    next_id: Arc<AtomicU64>,
+    timeout: Duration,
+}
+
+impl<W> Clone for Client<W> {
+    fn clone(&self) -> Self {
+        Self {
+            writer: self.writer.clone(),
+            session: self.session.clone(),
+            pending: self.pending.clone(),
+            next_id: self.next_id.clone(),
+            timeout: self.timeout,
+        }
+    }
+}
+
+impl<S> Client<WriteHalf<S>>
+where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    /// Creates the client of the connection (with the handshake already performed), waiting
+    /// for each response for the given time. Returns the client and the messages received
+    /// from the node other than the responses.
+    pub fn new(connection: Connection<S>, timeout: Duration) -> (Self, Broadcasts) {
+        let (reader, writer) = connection.into_split();
+        let (broadcasts_sender, broadcasts) = mpsc::unbounded_channel();
+
+        let client = Self {
+            session: writer.session().clone(),
+            writer: Arc::new(tokio::sync::Mutex::new(writer)),
+            pending: Default::default(),
+            next_id: Default::default(),
+            timeout,
+        };
+
+        tokio::spawn(dispatch(reader, client.pending.clone(), broadcasts_sender));
+
+        (client, broadcasts)
+    }
+}
+
+/// Reads the messages of the connection, passing the responses to the pending requests and
+/// the rest to the broadcasts
+async fn dispatch<R: AsyncRead + Unpin>(
+    mut reader: ConnectionReader<R>,
+    pending: PendingRequests,
+    broadcasts: mpsc::UnboundedSender<io::Result<PeerMessage>>,
+) {
+    loop {
+        let msg = match reader.read_message().await {
+            Ok(msg) => msg,
+            Err(e) => {
+                tracing::debug!(peer_id = %reader.session().peer_id(), "connection closed: {}", e);
+                if e.kind() != io::ErrorKind::UnexpectedEof {
+                    broadcasts.send(Err(e)).ok();
+                }
+                break;
+            }
+        };
+
+        // Decoded before locking the pending requests, once for all of them
+        let received = ReceivedMessage::new(msg);
+
+        // The response matching by content first, the earliest request first
+        let msg = {
+            let mut pending = pending.lock().unwrap();
+            let best = pending
+                .iter()
+                .enumerate()
+                .map(|(i, request)| (request.expected.matches(&received), i))
+                .filter(|(response_match, _)| *response_match != ResponseMatch::No)
+                .max_by_key(|(response_match, i)| (*response_match, std::cmp::Reverse(*i)));
+
+            match best {
+                Some((_, i)) => pending
+                    .remove(i)
+                    .sender
+                    .send(received)
+                    .err()
+                    .map(|received| received.msg),
+                None => Some(received.msg),
+            }
+        };
+
+        if let Some(msg) = msg {
+            broadcasts.send(Ok(msg)).ok();
+        }
+    }
+
+    // The requests waiting for the responses fail
+    pending.lock().unwrap().clear();
+}
+
+impl<W: AsyncWrite + Unpin> Client<W> {
+    pub fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    /// Sends the message without waiting for the response
+    pub async fn send(&self, msg: PeerMessage) -> Result<(), NetworkError> {
+        self.writer
+            .lock()
+            .await
+            .write_message(msg)
+            .await
+            .map_err(NetworkError::IO)
+    }
+
+    /// Sends the request and waits for the response to it
+    pub async fn request(&self, msg: PeerMessage) -> Result<PeerMessage, NetworkError> {
+        Ok(self.request_received(msg).await?.msg)
+    }
+
+    /// Sends the request and waits for the response to it, with the content decoded when
+    /// the response was matched
+    async fn request_received(&self, msg: PeerMessage) -> Result<ReceivedMessage, NetworkError> {
+        let expected = ExpectedResponse::of(&msg).ok_or_else(|| {
+            NetworkError::IO(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the message is not a request",
+            ))
+        })?;
+        let type_name = expected.type_name();
+
+        // Registered before sending, so the response can't be missed
+        let (sender, receiver) = oneshot::channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock().unwrap().push(Pending {
+            id,
+            expected,
+            sender,
+        });
+        let _guard = PendingGuard {
+            pending: &self.pending,
+            id,
+        };
+
+        self.send(msg).await?;
+
+        match time::timeout(self.timeout, receiver).await {
+            Ok(Ok(response)) => Ok(response),
+            Ok(Err(_)) => Err(NetworkError::IO(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                format!("connection closed before {} received", type_name),
+            ))),
+            Err(_) => Err(NetworkError::IO(io::Error::new(
+                io::ErrorKind::TimedOut,
+                format!("no {} received", type_name),
+            ))),
+        }
+    }
+
+    pub async fn get_peers(&self) -> Result<Vec<PeerInfo>, NetworkError> {
+        let response = self.request((&PeersRequest).into()).await?;
+        Ok(PeersResponse::try_from(&response)?.0)
+    }
+
+    /// Gets the headers of the blocks following the first known of the given ones
+    pub async fn get_block_headers(
+        &self,
+        hashes: Vec<CryptoHash>,
+    ) -> Result<Vec<BlockHeader>, NetworkError> {
+        let response = self
+            .request_received((&BlockHeadersRequest(hashes)).into())
+            .await?;
+        match response.content {
+            Some(ResponseContent::BlockHeaders(headers)) => Ok(headers),
+            _ => Ok(BlockHeadersResponse::try_from(&response.msg)?.0),
+        }
+    }
+
+    pub async fn get_block(&self, hash: CryptoHash) -> Result<Block, NetworkError> {
+        let response = self.request_received((&BlockRequest(hash)).into()).await?;
+        match response.content {
+            Some(ResponseContent::Block(block)) => Ok(block),
+            _ => Ok(BlockResponse::try_from(&response.msg)?.0),
+        }
+    }
+
+    pub async fn get_epoch_sync(
+        &self,
+        epoch_id: EpochId,
+    ) -> Result<syncing::EpochSyncResponse, NetworkError> {
+        let response = self.request((&EpochSyncRequest(epoch_id)).into()).await?;
+        Ok(EpochSyncResponse::try_from(&response)?.0)
+    }
+}
@@ -0,0 +1,318 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    future::Future,
+    io,
+    sync::{Arc, Mutex, Weak},
+};
+
+use tokio::{
+    sync::Notify,
+    task::JoinHandle,
+    time::{self, Duration, Instant},
+};
+
+use futures::future::{self, BoxFuture};
+
+use near_primitives::{
+    block::{Block, BlockHeader},
+    hash::CryptoHash,
+};
+
+use crate::{
+    connection::TcpConnection,
+    error::Error,
+    network_protocol::{Disconnect, NetworkError},
+    target::Target,
+};
+
+use super::{Client, TcpClient};
+
+/// Interval between the attempts to connect to the peers while the pool is not full
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Connects to the target and performs the handshake
+pub type ConnectFn =
+    Arc<dyn Fn(Target) -> BoxFuture<'static, Result<TcpConnection, Error>> + Send + Sync>;
+
+pub struct PoolOptions {
+    /// Number of the sessions kept open
+    pub size: usize,
+    /// Time to wait for each response (and for a session to become available)
+    pub request_timeout: Duration,
+    /// Number of the peers each query is sent to before giving up
+    pub attempts: usize,
+    /// Connect to the peers known to the connected ones (requested with PeersRequest), not
+    /// only to the seeds
+    pub discovery: bool,
+}
+
+struct PoolSession {
+    id: u64,
+    target: Target,
+    client: TcpClient,
+}
+
+#[derive(Default)]
+struct PoolState {
+    sessions: Vec<PoolSession>,
+    // Peers to connect to when a session is missing, the ones that failed go to the end
+    candidates: VecDeque<Target>,
+    // Addresses of all the candidates ever added, so the discovered peers are added once
+    known: HashSet<String>,
+    next_session: usize,
+    next_id: u64,
+}
+
+struct PoolInner {
+    state: Mutex<PoolState>,
+    options: PoolOptions,
+    connect: ConnectFn,
+    // Notified when a session is added or removed, or the peers are discovered
+    changed: Notify,
+}
+
+/// Pool of the handshaked sessions with several peers, spreading the queries across them.
+/// The sessions closed by the peers (or failing the queries) are replaced with the
+/// sessions with other peers, and a failed query is retried with another peer.
+pub struct PeerPool {
+    inner: Arc<PoolInner>,
+    maintainer: JoinHandle<()>,
+}
+
+impl Drop for PeerPool {
+    fn drop(&mut self) {
+        self.maintainer.abort();
+    }
+}
+
+impl PeerPool {
+    /// Creates the pool connecting to the seeds (and the peers discovered through them)
+    /// in the background
+    pub fn new(seeds: Vec<Target>, connect: ConnectFn, options: PoolOptions) -> Self {
+        let mut state = PoolState::default();
+        for seed in seeds {
+            state.add_candidate(seed);
+        }
+
+        let inner = Arc::new(PoolInner {
+            state: Mutex::new(state),
+            options,
+            connect,
+            changed: Notify::new(),
+        });
+
+        let maintainer = tokio::spawn(maintain(inner.clone()));
+
+        Self { inner, maintainer }
+    }
+
+    /// Sends the query to one of the peers, retrying with the other peers if it fails (the
+    /// failed session is closed and replaced)
+    pub async fn query<T, F, Fut>(&self, query: F) -> Result<T, NetworkError>
+    where
+        F: Fn(TcpClient) -> Fut,
+        Fut: Future<Output = Result<T, NetworkError>>,
+    {
+        let mut tried = HashSet::new();
+        let mut last_error = None;
+
+        for _ in 0..self.inner.options.attempts.max(1) {
+            let (id, target, client) = match self.inner.wait_session(&tried).await {
+                Some(session) => session,
+                None => break,
+            };
+
+            match query(client).await {
+                Ok(response) => return Ok(response),
+                Err(e) => {
+                    tracing::debug!(%target, "query failed: {}", e);
+                    self.inner.remove_session(id);
+                    tried.insert(target.addr);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(last_error.unwrap_or_else(|| {
+            NetworkError::IO(io::Error::new(
+                io::ErrorKind::TimedOut,
+                "no peers available",
+            ))
+        }))
+    }
+
+    /// Gets the headers of the blocks following the first known of the given ones
+    pub async fn get_block_headers(
+        &self,
+        hashes: Vec<CryptoHash>,
+    ) -> Result<Vec<BlockHeader>, NetworkError> {
+        self.query(|client| {
+            let hashes = hashes.clone();
+            async move { client.get_block_headers(hashes).await }
+        })
+        .await
+    }
+
+    pub async fn get_block(&self, hash: CryptoHash) -> Result<Block, NetworkError> {
+        self.query(|client| async move { client.get_block(hash).await })
+            .await
+    }
+}
+
+impl PoolState {
+    fn add_candidate(&mut self, target: Target) {
+        if self.known.insert(target.addr.clone()) {
+            self.candidates.push_back(target);
+        }
+    }
+}
+
+impl PoolInner {
+    /// Waits for a session with a peer not tried yet (picking the sessions in turn), None if
+    /// there is no such session within the request timeout
+    async fn wait_session(&self, tried: &HashSet<String>) -> Option<(u64, Target, TcpClient)> {
+        let deadline = Instant::now() + self.options.request_timeout;
+
+        loop {
+            // Created before checking the sessions, so the session added meanwhile is not missed
+            let changed = self.changed.notified();
+
+            {
+                let mut state = self.state.lock().unwrap();
+                let len = state.sessions.len();
+                let start = state.next_session;
+                let found = (0..len)
+                    .map(|i| (start + i) % len)
+                    .find(|&i| !tried.contains(&state.sessions[i].target.addr));
+
+                if let Some(i) = found {
+                    state.next_session = i + 1;
+                    let session = &state.sessions[i];
+                    return Some((session.id, session.target.clone(), session.client.clone()));
+                }
+            }
+
+            time::timeout_at(deadline, changed).await.ok()?;
+        }
+    }
+
+    fn add_session(self: &Arc<Self>, target: Target, connection: TcpConnection) {
+        let (client, mut broadcasts) = Client::new(connection, self.options.request_timeout);
+
+        let id = {
+            let mut state = self.state.lock().unwrap();
+            let id = state.next_id;
+            state.next_id += 1;
+            state.sessions.push(PoolSession {
+                id,
+                target: target.clone(),
+                client: client.clone(),
+            });
+            id
+        };
+        tracing::debug!(%target, "session added");
+        self.changed.notify_waiters();
+
+        // The session is removed when the connection is closed
+        let inner = Arc::downgrade(self);
+        tokio::spawn(async move {
+            while let Some(Ok(_)) = broadcasts.recv().await {}
+            if let Some(inner) = Weak::upgrade(&inner) {
+                inner.remove_session(id);
+            }
+        });
+
+        if self.options.discovery {
+            let inner = Arc::downgrade(self);
+            tokio::spawn(async move {
+                let peers = match client.get_peers().await {
+                    Ok(peers) => peers,
+                    Err(e) => {
+                        tracing::debug!(%target, "peers request failed: {}", e);
+                        return;
+                    }
+                };
+
+                if let Some(inner) = Weak::upgrade(&inner) {
+                    let mut state = inner.state.lock().unwrap();
+                    for peer in peers {
+                        if let Some(addr) = peer.addr {
+                            state.add_candidate(Target {
+                                peer_id: Some(peer.id),
+                                addr: addr.to_string(),
+                            });
+                        }
+                    }
+                    drop(state);
+                    inner.changed.notify_waiters();
+                }
+            });
+        }
+    }
+
+    /// Removes the session (if it's not removed yet), disconnecting from the peer, which may
+    /// be connected again later
+    fn remove_session(&self, id: u64) {
+        let session = {
+            let mut state = self.state.lock().unwrap();
+            let i = match state.sessions.iter().position(|session| session.id == id) {
+                Some(i) => i,
+                None => return,
+            };
+            let session = state.sessions.remove(i);
+            state.candidates.push_back(session.target.clone());
+            session
+        };
+        tracing::debug!(target = %session.target, "session removed");
+        self.changed.notify_waiters();
+
+        tokio::spawn(async move { session.client.send((&Disconnect).into()).await.ok() });
+    }
+}
+
+/// Keeps the pool full, connecting to the candidates when the sessions are missing
+async fn maintain(inner: Arc<PoolInner>) {
+    loop {
+        let changed = inner.changed.notified();
+
+        // Each candidate is tried at most once per round, the next ones are tried if the
+        // connections fail
+        let mut untried = inner.state.lock().unwrap().candidates.len();
+        loop {
+            let targets: Vec<Target> = {
+                let mut state = inner.state.lock().unwrap();
+                let missing = inner.options.size.saturating_sub(state.sessions.len());
+                (0..missing.min(untried))
+                    .map_while(|_| state.candidates.pop_front())
+                    .collect()
+            };
+            if targets.is_empty() {
+                break;
+            }
+            untried -= targets.len();
+
+            let results = future::join_all(targets.into_iter().map(|target| {
+                let connect = (inner.connect)(target.clone());
+                async move { (target, connect.await) }
+            }))
+            .await;
+
+            for (target, result) in results {
+                match result {
+                    Ok(connection) => inner.add_session(target, connection),
+                    Err(e) => {
+                        tracing::debug!(%target, "connection failed: {}", e);
+                        inner.state.lock().unwrap().candidates.push_back(target);
+                    }
+                }
+            }
+        }
+
+        // Waits for a session to be removed, or retries connecting later (the candidates may
+        // be discovered or become available meanwhile)
+        tokio::select! {
+            _ = changed => (),
+            _ = time::sleep(RECONNECT_INTERVAL) => (),
+        }
+    }
+}
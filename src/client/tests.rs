@@ -0,0 +1,320 @@
+use std::{io, sync::Arc};
+
+use tokio::{net::TcpListener, time::Duration};
+
+use futures::FutureExt;
+
+use borsh::BorshSerialize;
+
+use serde_json::json;
+
+use near_crypto::{KeyType, SecretKey};
+
+use near_primitives::{
+    block::{Block, GenesisId},
+    hash::CryptoHash,
+    network::PeerId,
+    version::PROTOCOL_VERSION,
+};
+
+use crate::{
+    connection::{ConnectOptions, TcpConnection},
+    network_protocol::{
+        message_from_json, message_type_name, BlockRequest, Disconnect, ExpectedResponse,
+        NetworkError, PeerMessage, ReceivedMessage, ResponseMatch,
+    },
+    target::Target,
+};
+
+use super::{Client, ConnectFn, PeerPool, PoolOptions};
+
+fn genesis_id() -> GenesisId {
+    GenesisId {
+        chain_id: "localnet".into(),
+        hash: CryptoHash::hash_bytes(b"genesis"),
+    }
+}
+
+/// Establishes the connection with the node, returns the client side and the node side
+async fn connect() -> (TcpConnection, TcpConnection) {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+
+    let secret_key = SecretKey::from_random(KeyType::ED25519);
+    let peer_id = PeerId::new(secret_key.public_key());
+    let timeout = Duration::from_secs(1);
+
+    let (client, node) = tokio::join!(
+        TcpConnection::connect(
+            addr,
+            peer_id,
+            24568,
+            Some(genesis_id()),
+            0,
+            ConnectOptions::new(timeout)
+        ),
+        async {
+            let (stream, _) = listener.accept().await.unwrap();
+            TcpConnection::accept(stream, secret_key, 24567, timeout, 10, None).await
+        }
+    );
+
+    (client.unwrap().0, node.unwrap().0)
+}
+
+fn block(height: u64) -> Block {
+    Block::genesis(
+        PROTOCOL_VERSION,
+        vec![],
+        chrono::Utc::now(),
+        height,
+        0,
+        0,
+        CryptoHash::default(),
+    )
+}
+
+fn block_response(block: &Block) -> PeerMessage {
+    let borsh = format!("hex:{}", hex::encode(block.try_to_vec().unwrap()));
+    message_from_json(&json!({
+        "block_response": { "block": { "borsh": borsh } }
+    }))
+    .unwrap()
+}
+
+#[test]
+fn test_expected_response() {
+    let (first, second) = (block(1), block(2));
+
+    let expected = ExpectedResponse::of(&(&BlockRequest(*first.hash())).into()).unwrap();
+    assert_eq!(expected, ExpectedResponse::Block(*first.hash()));
+    assert_eq!(
+        expected.matches(&ReceivedMessage::new(block_response(&first))),
+        ResponseMatch::Content
+    );
+    assert_eq!(
+        expected.matches(&ReceivedMessage::new(block_response(&second))),
+        ResponseMatch::No
+    );
+
+    let undecodable =
+        message_from_json(&json!({ "block_response": { "block": { "borsh": "hex:00" } } }))
+            .unwrap();
+    let undecodable = ReceivedMessage::new(undecodable);
+    assert!(undecodable.content.is_none());
+    assert_eq!(expected.matches(&undecodable), ResponseMatch::Type);
+    let disconnect = ReceivedMessage::new((&Disconnect).into());
+    assert_eq!(expected.matches(&disconnect), ResponseMatch::No);
+
+    let peers_request = message_from_json(&json!({ "peers_request": {} })).unwrap();
+    let expected = ExpectedResponse::of(&peers_request).unwrap();
+    let peers_response = message_from_json(&json!({ "peers_response": {} })).unwrap();
+    assert_eq!(
+        expected.matches(&ReceivedMessage::new(peers_response)),
+        ResponseMatch::Type
+    );
+
+    assert_eq!(ExpectedResponse::of(&(&Disconnect).into()), None);
+}
+
+#[tokio::test]
+async fn test_client() {
+    let (connection, mut node) = connect().await;
+    let (client, mut broadcasts) = Client::new(connection, Duration::from_millis(200));
+
+    let (first, second) = (block(1), block(2));
+
+    // The responses are matched to the concurrent requests by content, not by order
+    let (first_response, second_response, _) = tokio::join!(
+        client.get_block(*first.hash()),
+        client.get_block(*second.hash()),
+        async {
+            for _ in 0..2 {
+                assert_eq!(
+                    message_type_name(&node.read_message().await.unwrap()),
+                    "block_request"
+                );
+            }
+            node.write_message((&Disconnect).into()).await.unwrap();
+            node.write_message(block_response(&second)).await.unwrap();
+            node.write_message(block_response(&first)).await.unwrap();
+        }
+    );
+    assert_eq!(first_response.unwrap().hash(), first.hash());
+    assert_eq!(second_response.unwrap().hash(), second.hash());
+
+    // The other messages are passed to the broadcasts
+    assert_eq!(
+        broadcasts.recv().await.unwrap().unwrap(),
+        (&Disconnect).into()
+    );
+
+    // The response received after the request times out is not matched to it
+    let response = client.get_peers().await;
+    assert!(matches!(
+        response,
+        Err(NetworkError::IO(e)) if e.kind() == io::ErrorKind::TimedOut
+    ));
+    node.read_message().await.unwrap();
+    let peers_response = message_from_json(&json!({ "peers_response": {} })).unwrap();
+    node.write_message(peers_response.clone()).await.unwrap();
+    assert_eq!(broadcasts.recv().await.unwrap().unwrap(), peers_response);
+
+    assert!(client.request((&Disconnect).into()).await.is_err());
+
+    // The pending requests fail when the connection is closed
+    let (response, _) = tokio::join!(client.get_block(*first.hash()), async {
+        node.read_message().await.unwrap();
+        drop(node);
+    });
+    assert!(matches!(
+        response,
+        Err(NetworkError::IO(e)) if e.kind() == io::ErrorKind::UnexpectedEof
+    ));
+    assert!(broadcasts.recv().await.is_none());
+}
+
+type Handler = Arc<dyn Fn(&PeerMessage) -> Option<PeerMessage> + Send + Sync>;
+
+/// Starts the fake node answering the messages with the handler (the connection is closed if
+/// the handler returns None)
+async fn serve(handler: Handler) -> Target {
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let secret_key = SecretKey::from_random(KeyType::ED25519);
+    let target = Target {
+        peer_id: Some(PeerId::new(secret_key.public_key())),
+        addr: listener.local_addr().unwrap().to_string(),
+    };
+
+    tokio::spawn(async move {
+        loop {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (secret_key, handler) = (secret_key.clone(), handler.clone());
+            tokio::spawn(async move {
+                let timeout = Duration::from_secs(1);
+                let (mut node, _) =
+                    TcpConnection::accept(stream, secret_key, 24567, timeout, 10, None)
+                        .await
+                        .unwrap();
+                while let Ok(msg) = node.read_message().await {
+                    match handler(&msg) {
+                        Some(response) => node.write_message(response).await.unwrap(),
+                        None => break,
+                    }
+                }
+            });
+        }
+    });
+
+    target
+}
+
+fn connect_fn() -> ConnectFn {
+    Arc::new(|target: Target| {
+        async move {
+            let (connection, _) = TcpConnection::connect(
+                target.resolve().await?,
+                target.peer_id.unwrap(),
+                24568,
+                Some(genesis_id()),
+                0,
+                ConnectOptions::new(Duration::from_secs(1)),
+            )
+            .await?;
+            Ok(connection)
+        }
+        .boxed()
+    })
+}
+
+fn pool_options(size: usize, discovery: bool) -> PoolOptions {
+    PoolOptions {
+        size,
+        request_timeout: Duration::from_secs(2),
+        attempts: 3,
+        discovery,
+    }
+}
+
+#[tokio::test]
+async fn test_peer_pool() {
+    let block = Arc::new(block(1));
+
+    let good = serve({
+        let block = block.clone();
+        Arc::new(move |_| Some(block_response(&block)))
+    })
+    .await;
+    let garbage = serve(Arc::new(|_| {
+        message_from_json(&json!({ "block_response": { "block": { "borsh": "hex:00" } } })).ok()
+    }))
+    .await;
+    let closing = serve(Arc::new(|_| None)).await;
+    let unavailable = Target {
+        peer_id: good.peer_id.clone(),
+        addr: "127.0.0.1:1".into(),
+    };
+
+    // The requests failing on the misbehaving peers are retried on the good one
+    let pool = PeerPool::new(
+        vec![unavailable, garbage, closing, good],
+        connect_fn(),
+        pool_options(2, false),
+    );
+    for _ in 0..4 {
+        assert_eq!(
+            pool.get_block(*block.hash()).await.unwrap().hash(),
+            block.hash()
+        );
+    }
+    drop(pool);
+
+    // The requests fail if no peer responds properly
+    let garbage = serve(Arc::new(|_| {
+        message_from_json(&json!({ "block_response": { "block": { "borsh": "hex:00" } } })).ok()
+    }))
+    .await;
+    let pool = PeerPool::new(vec![garbage], connect_fn(), pool_options(2, false));
+    assert!(matches!(
+        pool.get_block(*block.hash()).await,
+        Err(NetworkError::InvalidResponse)
+    ));
+}
+
+#[tokio::test]
+async fn test_peer_pool_discovery() {
+    let block = Arc::new(block(1));
+
+    let good = serve({
+        let block = block.clone();
+        Arc::new(move |msg| match message_type_name(msg) {
+            "peers_request" => message_from_json(&json!({ "peers_response": {} })).ok(),
+            _ => Some(block_response(&block)),
+        })
+    })
+    .await;
+
+    // The seed doesn't have the block, but knows the peer that has it
+    let peers_response = message_from_json(&json!({
+        "peers_response": {
+            "peers": [{ "id": good.peer_id.as_ref().unwrap(), "addr": good.addr, "account_id": null }]
+        }
+    }))
+    .unwrap();
+    let seed = serve(Arc::new(move |msg| match message_type_name(msg) {
+        "peers_request" => Some(peers_response.clone()),
+        _ => {
+            message_from_json(&json!({ "block_response": { "block": { "borsh": "hex:00" } } })).ok()
+        }
+    }))
+    .await;
+
+    let pool = PeerPool::new(vec![seed.clone()], connect_fn(), pool_options(2, true));
+    assert_eq!(
+        pool.get_block(*block.hash()).await.unwrap().hash(),
+        block.hash()
+    );
+
+    let pool = PeerPool::new(vec![seed], connect_fn(), pool_options(2, false));
+    assert!(pool.get_block(*block.hash()).await.is_err());
+}
@@ -0,0 +1,299 @@
+use std::{future::Future, sync::Arc};
+
+use serde::Serialize;
+
+use tokio::{sync::Semaphore, task::JoinHandle, time::Duration};
+
+use crate::{error::Error, network_protocol::Handshake, target::Target};
+
+use super::{ConnectContext, ConnectionArgs, TargetsArgs};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum OutputFormat {
+    /// Summary table
+    Table,
+    /// One JSON object per line
+    Json,
+}
+
+#[derive(clap::Args)]
+pub struct BatchArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Maximum number of handshakes performed concurrently
+    #[clap(short = 'p', long, default_value = "16")]
+    parallelism: usize,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: OutputFormat,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Result of the handshake with a single node
+#[derive(Serialize)]
+struct BatchResult {
+    target: String,
+    result: String,
+    exit_code: u8,
+    latency_ms: Option<u128>,
+    protocol_version: Option<u32>,
+    height: Option<u64>,
+    archival: Option<bool>,
+    genesis: Option<String>,
+}
+
+impl BatchResult {
+    fn new(target: String, result: Result<(Handshake, Duration), Error>) -> Self {
+        match result {
+            Ok((handshake, latency)) => {
+                let chain_info = &handshake.sender_chain_info;
+                Self {
+                    target,
+                    result: "ok".into(),
+                    exit_code: 0,
+                    latency_ms: Some(latency.as_millis()),
+                    protocol_version: Some(handshake.protocol_version),
+                    height: Some(chain_info.height),
+                    archival: Some(chain_info.archival),
+                    genesis: Some(super::format_genesis_id(&chain_info.genesis_id)),
+                }
+            }
+            Err(e) => Self {
+                target,
+                result: e.to_string(),
+                exit_code: e.code(),
+                latency_ms: None,
+                protocol_version: None,
+                height: None,
+                archival: None,
+                genesis: None,
+            },
+        }
+    }
+}
+
+/// Performs the handshake with the target, returns the response and the handshake latency
+pub(super) async fn handshake(
+    target: String,
+    context: Arc<ConnectContext>,
+) -> Result<(Handshake, Duration), Error> {
+    let target: Target = target.parse()?;
+    let (_connection, handshake, latency) = context.connect(&target).await?;
+    Ok((handshake, latency))
+}
+
+/// Spawns the handshakes with the targets, at most `parallelism` performed at once. The
+/// tasks are returned in the order of the targets.
+pub(super) fn spawn_handshakes<F, Fut>(
+    targets: &[String],
+    parallelism: usize,
+    handshake: F,
+) -> Vec<JoinHandle<Result<(Handshake, Duration), Error>>>
+where
+    F: Fn(String) -> Fut,
+    Fut: Future<Output = Result<(Handshake, Duration), Error>> + Send + 'static,
+{
+    let semaphore = Arc::new(Semaphore::new(parallelism.max(1)));
+
+    targets
+        .iter()
+        .map(|target| {
+            let semaphore = semaphore.clone();
+            let handshake = handshake(target.clone());
+
+            tokio::spawn(async move {
+                let _permit = semaphore.acquire_owned().await.unwrap();
+                handshake.await
+            })
+        })
+        .collect()
+}
+
+/// Fails if any of the handshakes failed, with the exit code of the first failed one (in
+/// the order of the targets)
+fn check_results(results: &[BatchResult]) -> Result<(), Error> {
+    match results.iter().find(|r| r.exit_code != 0) {
+        Some(first_failed) => Err(Error::Batch {
+            failed: results.iter().filter(|r| r.exit_code != 0).count(),
+            total: results.len(),
+            code: first_failed.exit_code,
+        }),
+        None => Ok(()),
+    }
+}
+
+fn print_table(results: &[BatchResult]) {
+    let rows: Vec<[String; 7]> = results
+        .iter()
+        .map(|r| {
+            let opt = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+            [
+                r.target.clone(),
+                r.result.clone(),
+                opt(r.latency_ms.map(|v| format!("{} ms", v))),
+                opt(r.protocol_version.map(|v| v.to_string())),
+                opt(r.height.map(|v| v.to_string())),
+                opt(r.archival.map(|v| v.to_string())),
+                opt(r.genesis.clone()),
+            ]
+        })
+        .collect();
+
+    super::print_table(
+        [
+            "TARGET", "RESULT", "LATENCY", "VERSION", "HEIGHT", "ARCHIVAL", "GENESIS",
+        ],
+        &rows,
+    );
+}
+
+/// Performs handshakes with all the nodes from the targets file concurrently and prints
+/// the results. Returns the error of the first failed target (if any).
+pub async fn run(args: BatchArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let targets = args.targets.targets(&home)?;
+
+    let context = Arc::new(ConnectContext::new(args.connection, &home)?);
+
+    let tasks = spawn_handshakes(&targets, args.parallelism, |target| {
+        handshake(target, context.clone())
+    });
+
+    let mut results = Vec::with_capacity(tasks.len());
+
+    for (target, task) in targets.into_iter().zip(tasks) {
+        let result = BatchResult::new(target, task.await.expect("handshake task panicked"));
+
+        if let OutputFormat::Json = args.format {
+            println!("{}", serde_json::to_string(&result).unwrap());
+        }
+
+        results.push(result);
+    }
+
+    if let OutputFormat::Table = args.format {
+        print_table(&results);
+    }
+
+    context.save()?;
+
+    check_results(&results)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use near_crypto::{ED25519PublicKey, PublicKey};
+
+    use near_primitives::network::PeerId;
+
+    use crate::{
+        error::exit_code,
+        network_protocol::{NetworkError, PeerChainInfo},
+    };
+
+    use super::*;
+
+    fn handshake() -> Handshake {
+        let peer_id = |i| PeerId::new(PublicKey::ED25519(ED25519PublicKey([i; 32])));
+        Handshake {
+            protocol_version: 57,
+            oldest_supported_version: 55,
+            sender_peer_id: peer_id(1),
+            target_peer_id: peer_id(0),
+            sender_listen_port: None,
+            sender_chain_info: PeerChainInfo {
+                height: 100,
+                archival: true,
+                ..Default::default()
+            },
+            partial_edge_info: Default::default(),
+        }
+    }
+
+    fn failed(target: &str) -> BatchResult {
+        BatchResult::new(target.into(), Err(NetworkError::ConnectTimeout.into()))
+    }
+
+    #[test]
+    fn test_batch_result() {
+        let result = BatchResult::new(
+            "127.0.0.1:24567".into(),
+            Ok((handshake(), Duration::from_millis(42))),
+        );
+        assert_eq!(result.result, "ok");
+        assert_eq!(result.exit_code, 0);
+        assert_eq!(result.latency_ms, Some(42));
+        assert_eq!(result.protocol_version, Some(57));
+        assert_eq!(result.height, Some(100));
+        assert_eq!(result.archival, Some(true));
+        assert!(result.genesis.is_some());
+
+        let result = failed("127.0.0.1:24567");
+        assert_eq!(result.exit_code, exit_code::CONNECT_TIMEOUT);
+        assert_eq!(
+            result.result,
+            Error::from(NetworkError::ConnectTimeout).to_string()
+        );
+        assert_eq!(result.latency_ms, None);
+        assert_eq!(result.genesis, None);
+    }
+
+    #[test]
+    fn test_check_results() {
+        let ok = || BatchResult::new("a".into(), Ok((handshake(), Duration::ZERO)));
+        assert!(check_results(&[]).is_ok());
+        assert!(check_results(&[ok(), ok()]).is_ok());
+
+        // The exit code is the one of the first failed handshake
+        let mut banned = failed("c");
+        banned.exit_code = exit_code::PEER_BANNED;
+        let results = [ok(), failed("b"), banned, ok()];
+        match check_results(&results) {
+            Err(Error::Batch {
+                failed,
+                total,
+                code,
+            }) => {
+                assert_eq!((failed, total), (2, 4));
+                assert_eq!(code, exit_code::CONNECT_TIMEOUT);
+            }
+            _ => panic!("batch error expected"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_parallelism() {
+        let running = Arc::new(AtomicUsize::new(0));
+        let max_running = Arc::new(AtomicUsize::new(0));
+
+        let targets: Vec<_> = (0..10).map(|i| i.to_string()).collect();
+        let tasks = spawn_handshakes(&targets, 3, |target| {
+            let running = running.clone();
+            let max_running = max_running.clone();
+            async move {
+                let now_running = running.fetch_add(1, Ordering::SeqCst) + 1;
+                max_running.fetch_max(now_running, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                running.fetch_sub(1, Ordering::SeqCst);
+
+                let mut handshake = handshake();
+                handshake.sender_chain_info.height = target.parse().unwrap();
+                Ok((handshake, Duration::ZERO))
+            }
+        });
+
+        // The results are returned in the order of the targets
+        for (i, task) in tasks.into_iter().enumerate() {
+            let (handshake, _) = task.await.unwrap().unwrap();
+            assert_eq!(handshake.sender_chain_info.height, i as u64);
+        }
+        assert_eq!(max_running.load(Ordering::SeqCst), 3);
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    sync::Arc,
+};
+
+use tokio::{task::JoinSet, time::Duration};
+
+use near_primitives::{network::PeerId, types::NumShards, version::ProtocolVersion};
+
+use crate::{
+    census::{Census, Report},
+    client::Client,
+    error::Error,
+    network_protocol::{Handshake, PeerInfo},
+    target::Target,
+};
+
+use super::{ConnectContext, ConnectionArgs, TargetsArgs};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum ReportFormat {
+    /// Summary tables
+    Table,
+    /// Single JSON object
+    Json,
+    /// "section,key,value" rows
+    Csv,
+}
+
+#[derive(clap::Args)]
+pub struct CensusArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Maximum number of nodes crawled concurrently
+    #[clap(short = 'p', long, default_value = "16")]
+    parallelism: usize,
+
+    /// Maximum number of nodes crawled (the targets included)
+    #[clap(long, default_value = "1000")]
+    max_nodes: usize,
+
+    /// Only handshake the targets, without requesting their peers
+    #[clap(long)]
+    no_crawl: bool,
+
+    /// Time to wait for the peers response (in seconds)
+    #[clap(long, default_value = "5")]
+    request_timeout: u64,
+
+    /// Number of shards of the chain, so the shards not tracked by any node are reported
+    /// too (by default only the shards tracked by some node are reported)
+    #[clap(long, verbatim_doc_comment)]
+    num_shards: Option<NumShards>,
+
+    /// Minimum number of nodes tracking a shard for it not to be reported as under-served
+    #[clap(long, default_value = "2")]
+    min_shard_nodes: usize,
+
+    /// Protocol version to report the upgrade readiness for [default: the latest version
+    /// run by any node]
+    #[clap(long, verbatim_doc_comment)]
+    upgrade_version: Option<ProtocolVersion>,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: ReportFormat,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Shared state the nodes are crawled with
+struct CrawlContext {
+    connect: ConnectContext,
+    crawl: bool,
+    request_timeout: Duration,
+}
+
+/// Performs the handshake with the node and requests its peers (unless only the targets
+/// are handshaken). A failed peers request doesn't fail the node, no peers are returned.
+async fn crawl_node(
+    target: &Target,
+    context: &CrawlContext,
+) -> Result<(Handshake, Vec<PeerInfo>), Error> {
+    let (connection, handshake, _) = context.connect.connect(target).await?;
+
+    if !context.crawl {
+        return Ok((handshake, Vec::new()));
+    }
+
+    let (client, _) = Client::new(connection, context.request_timeout);
+    let peers = client.get_peers().await.unwrap_or_else(|e| {
+        tracing::debug!(%target, "peers request failed: {}", e);
+        Vec::new()
+    });
+
+    if let Some(peer_store) = &context.connect.peer_store {
+        peer_store.lock().unwrap().record_peers(&peers);
+    }
+
+    Ok((handshake, peers))
+}
+
+fn print_share(header: &str, rows: impl Iterator<Item = (String, usize)>, total: usize) {
+    let rows: Vec<[String; 3]> = rows
+        .map(|(key, nodes)| {
+            [
+                key,
+                nodes.to_string(),
+                format!("{:.1}%", 100.0 * nodes as f64 / total.max(1) as f64),
+            ]
+        })
+        .collect();
+    super::print_table([header, "NODES", "SHARE"], &rows);
+}
+
+fn print_report(report: &Report) {
+    println!(
+        "Nodes: {} reachable, {} unreachable, {} archival, {} not tracking any shard",
+        report.nodes, report.unreachable, report.archival, report.untracked
+    );
+    if let Some(upgrade) = &report.upgrade {
+        println!(
+            "Upgrade to protocol version {}: {} of {} nodes ready ({:.1}%)",
+            upgrade.version,
+            upgrade.ready,
+            report.nodes,
+            100.0 * upgrade.ready as f64 / report.nodes.max(1) as f64
+        );
+    }
+    if let Some(heights) = &report.heights {
+        println!(
+            "Head heights: min {}, p10 {}, median {}, p90 {}, max {} (spread {})",
+            heights.min,
+            heights.p10,
+            heights.median,
+            heights.p90,
+            heights.max,
+            heights.max - heights.min
+        );
+    }
+
+    println!();
+    print_share(
+        "VERSION",
+        report
+            .protocol_versions
+            .iter()
+            .map(|(version, nodes)| (version.to_string(), *nodes)),
+        report.nodes,
+    );
+
+    println!();
+    print_share(
+        "OLDEST SUPPORTED",
+        report
+            .oldest_supported_versions
+            .iter()
+            .map(|(version, nodes)| (version.to_string(), *nodes)),
+        report.nodes,
+    );
+
+    println!();
+    print_share(
+        "SHARD",
+        report.shards.iter().map(|shard| {
+            let key = match shard.under_served {
+                true => format!("{} (under-served)", shard.shard_id),
+                false => shard.shard_id.to_string(),
+            };
+            (key, shard.nodes)
+        }),
+        report.nodes,
+    );
+}
+
+/// Crawls the network starting from the targets (performing the handshake with each node
+/// and requesting its peers, the peers with known addresses are crawled next) and prints
+/// the census of the reachable nodes: the protocol versions they run, the archival nodes,
+/// the shard coverage and the spread of the head heights
+pub async fn run(args: CensusArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let mut queue = args
+        .targets
+        .targets(&home)?
+        .iter()
+        .map(|target| target.parse())
+        .collect::<Result<VecDeque<Target>, _>>()?;
+
+    let context = Arc::new(CrawlContext {
+        connect: ConnectContext::new(args.connection, &home)?,
+        crawl: !args.no_crawl,
+        request_timeout: Duration::from_secs(args.request_timeout),
+    });
+
+    // Each discovered peer is crawled once: not if it's one of the targets or a node crawled
+    // already (the peer ids of the targets may be not specified)
+    let mut queued: HashSet<PeerId> = queue
+        .iter()
+        .filter_map(|target| target.peer_id.clone())
+        .collect();
+    queue.truncate(args.max_nodes);
+    let mut crawled = queue.len();
+
+    let mut census = Census::new(args.num_shards, args.min_shard_nodes, args.upgrade_version);
+
+    let mut tasks = JoinSet::new();
+    loop {
+        while tasks.len() < args.parallelism.max(1) {
+            let target = match queue.pop_front() {
+                Some(target) => target,
+                None => break,
+            };
+            let context = context.clone();
+            tasks.spawn(async move {
+                let result = crawl_node(&target, &context).await;
+                (target, result)
+            });
+        }
+
+        let (target, result) = match tasks.join_next().await {
+            Some(result) => result.expect("crawl task panicked"),
+            None => break,
+        };
+
+        match result {
+            Ok((handshake, peers)) => {
+                tracing::debug!(%target, peers = peers.len(), "node crawled");
+                census.record(&handshake);
+                queued.insert(handshake.sender_peer_id);
+
+                for peer in peers {
+                    if crawled >= args.max_nodes {
+                        break;
+                    }
+                    let addr = match peer.addr {
+                        Some(addr) if !queued.contains(&peer.id) => addr,
+                        _ => continue,
+                    };
+                    queued.insert(peer.id.clone());
+                    queue.push_back(Target {
+                        peer_id: Some(peer.id),
+                        addr: addr.to_string(),
+                    });
+                    crawled += 1;
+                }
+            }
+            Err(e) => {
+                tracing::debug!(%target, "node unreachable: {}", e);
+                census.record_unreachable();
+            }
+        }
+    }
+
+    context.connect.save()?;
+
+    let report = census.report();
+    match args.format {
+        ReportFormat::Table => print_report(&report),
+        ReportFormat::Json => println!("{}", serde_json::to_string(&report).unwrap()),
+        ReportFormat::Csv => {
+            println!("section,key,value");
+            for row in report.rows() {
+                println!("{}", row.join(","));
+            }
+        }
+    }
+
+    Ok(())
+}
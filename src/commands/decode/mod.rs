@@ -0,0 +1,146 @@
+mod pcap;
+
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use protobuf::Message;
+
+use crate::{
+    error::Error,
+    network_protocol::{message_to_json, message_type_name, PeerMessage},
+};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum InputFormat {
+    /// Hex encoded frames (whitespace is ignored)
+    Hex,
+    /// Base64 encoded frames (whitespace is ignored)
+    Base64,
+    /// Binary frames
+    Raw,
+    /// pcap or pcapng capture of the TCP connections
+    Pcap,
+}
+
+#[derive(clap::Args)]
+pub struct DecodeArgs {
+    /// Input file with the frames (4-byte little endian length prefix followed by the
+    /// protobuf PeerMessage) [default: stdin]
+    #[clap(verbatim_doc_comment)]
+    input: Option<PathBuf>,
+
+    /// Input format
+    #[clap(short = 'f', long, value_enum, default_value = "hex")]
+    format: InputFormat,
+
+    /// Port of the node to select the TCP connections of the pcap capture
+    #[clap(long, default_value = "24567")]
+    port: u16,
+}
+
+/// Splits the data into the frames, returns the frames and the size of the trailing
+/// incomplete frame
+fn split_frames(mut data: &[u8]) -> (Vec<&[u8]>, usize) {
+    let mut frames = Vec::new();
+
+    while data.len() >= 4 {
+        let len = u32::from_le_bytes(data[..4].try_into().unwrap()) as usize;
+        if data.len() - 4 < len {
+            break;
+        }
+        frames.push(&data[4..4 + len]);
+        data = &data[4 + len..];
+    }
+
+    (frames, data.len())
+}
+
+fn print_frames(data: &[u8]) {
+    let (frames, incomplete) = split_frames(data);
+
+    for (i, frame) in frames.iter().enumerate() {
+        match PeerMessage::parse_from_bytes(frame) {
+            Ok(msg) => println!(
+                "frame {}: {} ({} bytes)\n{}",
+                i + 1,
+                message_type_name(&msg),
+                frame.len(),
+                serde_json::to_string_pretty(&message_to_json(&msg)).unwrap()
+            ),
+            Err(e) => println!(
+                "frame {}: undecodable ({} bytes): {}\n{}",
+                i + 1,
+                frame.len(),
+                e,
+                hex::encode(frame)
+            ),
+        }
+    }
+
+    if incomplete > 0 {
+        println!("incomplete frame: {} bytes", incomplete);
+    }
+}
+
+fn read_input(args: &DecodeArgs) -> Result<Vec<u8>, Error> {
+    let mut data = Vec::new();
+
+    match &args.input {
+        Some(path) => {
+            data = fs::read(path).map_err(|_| {
+                Error::Config(format!("Error reading input file: {}", path.display()))
+            })?
+        }
+        None => {
+            io::stdin()
+                .read_to_end(&mut data)
+                .map_err(|e| Error::Config(format!("Error reading stdin: {}", e)))?;
+        }
+    }
+
+    let text = || -> String {
+        String::from_utf8_lossy(&data)
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect()
+    };
+
+    match args.format {
+        InputFormat::Hex => hex::decode(text())
+            .map_err(|e| Error::Config(format!("Error decoding hex input: {}", e))),
+        InputFormat::Base64 => base64::decode(text())
+            .map_err(|e| Error::Config(format!("Error decoding base64 input: {}", e))),
+        InputFormat::Raw | InputFormat::Pcap => Ok(data),
+    }
+}
+
+/// Decodes the frames and prints the messages (as JSON, with the borsh payloads expanded)
+pub fn run(args: DecodeArgs) -> Result<(), Error> {
+    let data = read_input(&args)?;
+
+    if let InputFormat::Pcap = args.format {
+        let capture = pcap::read_tcp_streams(&data, args.port)
+            .map_err(|e| Error::Config(format!("Error parsing capture: {}", e)))?;
+
+        for stream in capture.streams {
+            println!("=== {} -> {}", stream.src, stream.dst);
+            print_frames(&stream.data);
+            if stream.gap {
+                println!("missing segments, the rest of the stream is skipped");
+            }
+        }
+        if capture.truncated {
+            println!("truncated capture, the packets after the last complete one are skipped");
+        }
+    } else {
+        print_frames(&data);
+    }
+
+    Ok(())
+}
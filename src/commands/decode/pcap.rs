@@ -0,0 +1,348 @@
+use std::{
+    collections::HashMap,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+};
+
+// Link types of the captured packets
+const LINKTYPE_NULL: u32 = 0;
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_LINUX_SLL: u32 = 113;
+const LINKTYPE_LINUX_SLL2: u32 = 276;
+
+const ETHERTYPE_IPV4: u16 = 0x0800;
+const ETHERTYPE_IPV6: u16 = 0x86dd;
+const ETHERTYPE_VLAN: u16 = 0x8100;
+
+const IP_PROTOCOL_TCP: u8 = 6;
+
+const TCP_FLAG_SYN: u8 = 0x02;
+
+// pcapng block types
+const PCAPNG_SECTION_HEADER: u32 = 0x0a0d0d0a;
+const PCAPNG_INTERFACE_DESCRIPTION: u32 = 1;
+const PCAPNG_SIMPLE_PACKET: u32 = 3;
+const PCAPNG_ENHANCED_PACKET: u32 = 6;
+
+/// TCP streams of the capture
+pub struct Capture {
+    pub streams: Vec<TcpStream>,
+    /// The capture ends in the middle of a packet (the streams are extracted from the
+    /// packets before it)
+    pub truncated: bool,
+}
+
+/// Data of a single direction of a TCP connection, reassembled from the captured segments
+pub struct TcpStream {
+    pub src: SocketAddr,
+    pub dst: SocketAddr,
+    pub data: Vec<u8>,
+    /// Some segments of the stream were not captured (the data is truncated at the gap)
+    pub gap: bool,
+}
+
+struct Segment {
+    seq: u32,
+    syn: bool,
+    payload: Vec<u8>,
+}
+
+/// Little or big endian reader of the capture file fields
+#[derive(Clone, Copy)]
+struct Reader {
+    big_endian: bool,
+}
+
+impl Reader {
+    fn u16(self, data: &[u8], offset: usize) -> Option<u16> {
+        let bytes = data.get(offset..offset + 2)?.try_into().unwrap();
+        Some(if self.big_endian {
+            u16::from_be_bytes(bytes)
+        } else {
+            u16::from_le_bytes(bytes)
+        })
+    }
+
+    fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+        let bytes = data.get(offset..offset + 4)?.try_into().unwrap();
+        Some(if self.big_endian {
+            u32::from_be_bytes(bytes)
+        } else {
+            u32::from_le_bytes(bytes)
+        })
+    }
+}
+
+const NETWORK: Reader = Reader { big_endian: true };
+
+/// Captured packet: link type and the packet data
+type Packet<'a> = (u32, &'a [u8]);
+
+/// Packets of the capture, and whether the capture ends in the middle of a packet
+type Packets<'a> = (Vec<Packet<'a>>, bool);
+
+/// Reader of the pcap file with the given magic number, None if it's not a pcap magic number
+fn pcap_reader(magic: &[u8]) -> Option<Reader> {
+    match magic {
+        [0xd4, 0xc3, 0xb2, 0xa1] | [0x4d, 0x3c, 0xb2, 0xa1] => Some(Reader { big_endian: false }),
+        [0xa1, 0xb2, 0xc3, 0xd4] | [0xa1, 0xb2, 0x3c, 0x4d] => Some(Reader { big_endian: true }),
+        _ => None,
+    }
+}
+
+fn read_pcap(data: &[u8]) -> Result<Packets<'_>, String> {
+    let reader = data
+        .get(..4)
+        .and_then(pcap_reader)
+        .ok_or("not a pcap file")?;
+
+    let link_type = reader.u32(data, 20).ok_or("truncated pcap header")?;
+
+    let mut packets = Vec::new();
+    let mut offset = 24;
+    while offset < data.len() {
+        let packet = reader
+            .u32(data, offset + 8)
+            .and_then(|captured_len| data.get(offset + 16..offset + 16 + captured_len as usize));
+        let packet = match packet {
+            Some(packet) => packet,
+            None => return Ok((packets, true)),
+        };
+        packets.push((link_type, packet));
+        offset += 16 + packet.len();
+    }
+
+    Ok((packets, false))
+}
+
+fn read_pcapng(data: &[u8]) -> Result<Packets<'_>, String> {
+    let mut reader = Reader { big_endian: false };
+    let mut link_types = Vec::new();
+    let mut packets = Vec::new();
+
+    let mut offset = 0;
+    while offset < data.len() {
+        // The byte order is defined by the section header, its type is the same in both
+        // byte orders
+        let block_type = match reader.u32(data, offset) {
+            Some(block_type) => block_type,
+            None => return Ok((packets, true)),
+        };
+        if block_type == PCAPNG_SECTION_HEADER {
+            reader.big_endian = match data.get(offset + 8..offset + 12) {
+                Some([0x1a, 0x2b, 0x3c, 0x4d]) => true,
+                Some([0x4d, 0x3c, 0x2b, 0x1a]) => false,
+                Some(_) => return Err("invalid pcapng section header".into()),
+                None => return Ok((packets, true)),
+            };
+            link_types.clear();
+        }
+
+        let block_len = match reader.u32(data, offset + 4) {
+            Some(block_len) => block_len as usize,
+            None => return Ok((packets, true)),
+        };
+        if block_len < 12 {
+            return Err("invalid pcapng block length".into());
+        }
+        let body = match data.get(offset + 8..offset + block_len - 4) {
+            Some(body) => body,
+            None => return Ok((packets, true)),
+        };
+
+        match block_type {
+            PCAPNG_INTERFACE_DESCRIPTION => {
+                link_types.push(reader.u16(body, 0).ok_or("truncated pcapng block")? as u32);
+            }
+            PCAPNG_ENHANCED_PACKET => {
+                let interface_id = reader.u32(body, 0).ok_or("truncated pcapng block")?;
+                let captured_len = reader.u32(body, 12).ok_or("truncated pcapng block")?;
+                let link_type = *link_types
+                    .get(interface_id as usize)
+                    .ok_or("unknown pcapng interface")?;
+                let packet = body
+                    .get(20..20 + captured_len as usize)
+                    .ok_or("truncated pcapng packet")?;
+                packets.push((link_type, packet));
+            }
+            PCAPNG_SIMPLE_PACKET => {
+                let link_type = *link_types.first().ok_or("unknown pcapng interface")?;
+                let original_len = reader.u32(body, 0).ok_or("truncated pcapng block")?;
+                let packet = &body[4..];
+                packets.push((
+                    link_type,
+                    &packet[..packet.len().min(original_len as usize)],
+                ));
+            }
+            _ => (),
+        }
+
+        offset += block_len;
+    }
+
+    Ok((packets, false))
+}
+
+/// Returns the IP packet of the link layer frame
+fn ip_packet(link_type: u32, frame: &[u8]) -> Option<&[u8]> {
+    let (mut ethertype, mut offset) = match link_type {
+        LINKTYPE_ETHERNET => (NETWORK.u16(frame, 12)?, 14),
+        LINKTYPE_LINUX_SLL => (NETWORK.u16(frame, 14)?, 16),
+        LINKTYPE_LINUX_SLL2 => (NETWORK.u16(frame, 0)?, 20),
+        LINKTYPE_NULL | LINKTYPE_RAW => {
+            let offset = if link_type == LINKTYPE_NULL { 4 } else { 0 };
+            return frame.get(offset..);
+        }
+        _ => return None,
+    };
+
+    while ethertype == ETHERTYPE_VLAN {
+        ethertype = NETWORK.u16(frame, offset + 2)?;
+        offset += 4;
+    }
+
+    match ethertype {
+        ETHERTYPE_IPV4 | ETHERTYPE_IPV6 => frame.get(offset..),
+        _ => None,
+    }
+}
+
+/// Returns the source and destination addresses and the TCP segment of the IP packet
+fn tcp_segment(packet: &[u8]) -> Option<(IpAddr, IpAddr, &[u8])> {
+    match packet.first()? >> 4 {
+        4 => {
+            let header_len = ((packet[0] & 0x0f) as usize) * 4;
+            let total_len = NETWORK.u16(packet, 2)? as usize;
+            if *packet.get(9)? != IP_PROTOCOL_TCP {
+                return None;
+            }
+            let src: [u8; 4] = packet.get(12..16)?.try_into().unwrap();
+            let dst: [u8; 4] = packet.get(16..20)?.try_into().unwrap();
+            Some((
+                Ipv4Addr::from(src).into(),
+                Ipv4Addr::from(dst).into(),
+                packet.get(header_len..total_len.min(packet.len()))?,
+            ))
+        }
+        6 => {
+            // Extension headers are not supported
+            if *packet.get(6)? != IP_PROTOCOL_TCP {
+                return None;
+            }
+            let payload_len = NETWORK.u16(packet, 4)? as usize;
+            let src: [u8; 16] = packet.get(8..24)?.try_into().unwrap();
+            let dst: [u8; 16] = packet.get(24..40)?.try_into().unwrap();
+            Some((
+                Ipv6Addr::from(src).into(),
+                Ipv6Addr::from(dst).into(),
+                packet.get(40..(40 + payload_len).min(packet.len()))?,
+            ))
+        }
+        _ => None,
+    }
+}
+
+/// Reassembles the data of the stream from the segments (ordered by the sequence numbers,
+/// the retransmitted data is skipped)
+fn reassemble(segments: Vec<Segment>) -> (Vec<u8>, bool) {
+    // The data starts after SYN (if it's captured), or at the lowest sequence number
+    let base = match segments.iter().find(|segment| segment.syn) {
+        Some(syn) => syn.seq.wrapping_add(1),
+        None => {
+            let first = segments[0].seq;
+            segments
+                .iter()
+                .map(|segment| segment.seq)
+                .min_by_key(|seq| seq.wrapping_sub(first) as i32)
+                .unwrap()
+        }
+    };
+
+    let mut segments: Vec<(u32, Vec<u8>)> = segments
+        .into_iter()
+        .filter(|segment| !segment.payload.is_empty())
+        .map(|segment| (segment.seq.wrapping_sub(base), segment.payload))
+        .collect();
+    segments.sort_by_key(|(offset, _)| *offset);
+
+    let mut data = Vec::new();
+    for (offset, payload) in segments {
+        let offset = offset as usize;
+        if offset > data.len() {
+            return (data, true);
+        }
+        if offset + payload.len() > data.len() {
+            data.extend(&payload[data.len() - offset..]);
+        }
+    }
+
+    (data, false)
+}
+
+/// Extracts the TCP streams from or to the given port from the pcap or pcapng capture (the
+/// format is detected by the magic number). The truncated capture is read up to the last
+/// complete packet.
+pub fn read_tcp_streams(capture: &[u8], port: u16) -> Result<Capture, String> {
+    let (packets, truncated) = match capture.get(..4) {
+        Some(magic) if pcap_reader(magic).is_some() => read_pcap(capture)?,
+        // The block type of the section header is the same in both byte orders
+        Some(magic) if magic == PCAPNG_SECTION_HEADER.to_le_bytes() => read_pcapng(capture)?,
+        _ => return Err("not a pcap or pcapng capture".into()),
+    };
+
+    let mut order = Vec::new();
+    let mut streams: HashMap<(SocketAddr, SocketAddr), Vec<Segment>> = HashMap::new();
+
+    for (link_type, frame) in packets {
+        let (src_ip, dst_ip, segment) = match ip_packet(link_type, frame).and_then(tcp_segment) {
+            Some(segment) => segment,
+            None => continue,
+        };
+
+        let (src_port, dst_port) = match (NETWORK.u16(segment, 0), NETWORK.u16(segment, 2)) {
+            (Some(src_port), Some(dst_port)) => (src_port, dst_port),
+            _ => continue,
+        };
+        if src_port != port && dst_port != port {
+            continue;
+        }
+
+        let (seq, data_offset, flags) =
+            match (NETWORK.u32(segment, 4), segment.get(12), segment.get(13)) {
+                (Some(seq), Some(data_offset), Some(flags)) => (seq, data_offset, flags),
+                _ => continue,
+            };
+
+        let key = (
+            SocketAddr::new(src_ip, src_port),
+            SocketAddr::new(dst_ip, dst_port),
+        );
+        if !streams.contains_key(&key) {
+            order.push(key);
+        }
+        streams.entry(key).or_default().push(Segment {
+            seq,
+            syn: flags & TCP_FLAG_SYN != 0,
+            payload: segment
+                .get((data_offset >> 4) as usize * 4..)
+                .unwrap_or_default()
+                .to_vec(),
+        });
+    }
+
+    let streams = order
+        .into_iter()
+        .map(|key| {
+            let (data, gap) = reassemble(streams.remove(&key).unwrap());
+            TcpStream {
+                src: key.0,
+                dst: key.1,
+                data,
+                gap,
+            }
+        })
+        .filter(|stream| !stream.data.is_empty())
+        .collect();
+
+    Ok(Capture { streams, truncated })
+}
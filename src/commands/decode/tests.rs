@@ -0,0 +1,166 @@
+use protobuf::Message;
+
+use near_crypto::{ED25519PublicKey, PublicKey};
+
+use near_primitives::{block::GenesisId, network::PeerId};
+
+use crate::network_protocol::{
+    message_to_json, Handshake, HandshakeFailure, PeerChainInfo, PeerMessage,
+};
+
+use super::{pcap, split_frames};
+
+fn frame(msg: &PeerMessage) -> Vec<u8> {
+    let data = msg.write_to_bytes().unwrap();
+    [&(data.len() as u32).to_le_bytes(), data.as_slice()].concat()
+}
+
+/// Ethernet frame with IPv4 packet with TCP segment from 10.0.0.1:40000 to 10.0.0.2:24567
+fn ethernet_frame(seq: u32, flags: u8, payload: &[u8]) -> Vec<u8> {
+    let mut tcp = Vec::new();
+    tcp.extend(40000u16.to_be_bytes());
+    tcp.extend(24567u16.to_be_bytes());
+    tcp.extend(seq.to_be_bytes());
+    tcp.extend(0u32.to_be_bytes());
+    tcp.extend([5 << 4, flags]);
+    tcp.extend([0; 6]);
+    tcp.extend(payload);
+
+    let mut ip = vec![0x45, 0];
+    ip.extend((20 + tcp.len() as u16).to_be_bytes());
+    ip.extend([0, 0, 0, 0, 64, 6, 0, 0]);
+    ip.extend([10, 0, 0, 1, 10, 0, 0, 2]);
+    ip.extend(tcp);
+
+    let mut ethernet = vec![0; 12];
+    ethernet.extend(0x0800u16.to_be_bytes());
+    ethernet.extend(ip);
+    ethernet
+}
+
+fn pcap_file(frames: &[Vec<u8>]) -> Vec<u8> {
+    let mut data = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+    data.extend([0; 8]);
+    data.extend(65535u32.to_le_bytes());
+    data.extend(1u32.to_le_bytes());
+
+    for frame in frames {
+        data.extend([0; 8]);
+        data.extend((frame.len() as u32).to_le_bytes());
+        data.extend((frame.len() as u32).to_le_bytes());
+        data.extend(frame);
+    }
+
+    data
+}
+
+#[test]
+fn test_split_frames() {
+    let msg: PeerMessage = (&HandshakeFailure::InvalidTarget).into();
+    let data = [frame(&msg), frame(&msg), vec![10, 0, 0, 0, 1]].concat();
+
+    let (frames, incomplete) = split_frames(&data);
+    assert_eq!(frames.len(), 2);
+    assert_eq!(PeerMessage::parse_from_bytes(frames[1]).unwrap(), msg);
+    assert_eq!(incomplete, 5);
+}
+
+#[test]
+fn test_pcap_reassembly() {
+    let msg: PeerMessage = (&HandshakeFailure::GenesisMismatch(GenesisId::default())).into();
+    let data = frame(&msg);
+    let (first, second) = data.split_at(10);
+
+    // Segments out of order, with a retransmission
+    let capture = pcap_file(&[
+        ethernet_frame(1000, 0x02, &[]),
+        ethernet_frame(1011, 0x18, second),
+        ethernet_frame(1001, 0x18, first),
+        ethernet_frame(1001, 0x18, first),
+    ]);
+
+    let streams = pcap::read_tcp_streams(&capture, 24567).unwrap().streams;
+    assert_eq!(streams.len(), 1);
+    assert_eq!(streams[0].src, "10.0.0.1:40000".parse().unwrap());
+    assert_eq!(streams[0].data, data);
+    assert!(!streams[0].gap);
+
+    // Connections to other ports are skipped
+    let capture = pcap::read_tcp_streams(&capture, 24568).unwrap();
+    assert!(capture.streams.is_empty());
+    assert!(!capture.truncated);
+}
+
+#[test]
+fn test_truncated_pcap() {
+    let msg: PeerMessage = (&HandshakeFailure::GenesisMismatch(GenesisId::default())).into();
+    let data = frame(&msg);
+    let (first, second) = data.split_at(10);
+
+    let last = ethernet_frame(1011, 0x18, second);
+    let capture = pcap_file(&[
+        ethernet_frame(1000, 0x02, &[]),
+        ethernet_frame(1001, 0x18, first),
+        last.clone(),
+    ]);
+
+    // The stream is read up to the last complete packet, whether the capture is cut within
+    // the record header or within the packet
+    let last_record = capture.len() - 16 - last.len();
+    for len in [last_record + 8, capture.len() - 1] {
+        let truncated = pcap::read_tcp_streams(&capture[..len], 24567).unwrap();
+        assert!(truncated.truncated);
+        assert_eq!(truncated.streams.len(), 1);
+        assert_eq!(truncated.streams[0].data, first);
+    }
+
+    // The errors of the pcap file are reported (not the ones of the pcapng format)
+    assert_eq!(
+        pcap::read_tcp_streams(&capture[..20], 24567).err().unwrap(),
+        "truncated pcap header"
+    );
+    assert_eq!(
+        pcap::read_tcp_streams(b"not a capture", 24567)
+            .err()
+            .unwrap(),
+        "not a pcap or pcapng capture"
+    );
+}
+
+#[test]
+fn test_message_to_json() {
+    let peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([1u8; 32])));
+
+    let handshake = Handshake {
+        protocol_version: 57,
+        oldest_supported_version: 55,
+        sender_peer_id: peer_id.clone(),
+        target_peer_id: peer_id.clone(),
+        sender_listen_port: Some(24567),
+        sender_chain_info: PeerChainInfo {
+            genesis_id: GenesisId {
+                chain_id: "localnet".into(),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+        partial_edge_info: Default::default(),
+    };
+
+    let json = message_to_json(&PeerMessage::from(&handshake));
+    let handshake = &json["handshake"];
+
+    assert_eq!(handshake["protocol_version"], 57);
+    assert_eq!(
+        handshake["sender_chain_info"]["genesis_id"]["chain_id"],
+        "localnet"
+    );
+    assert_eq!(
+        handshake["sender_chain_info"]["genesis_id"]["hash"],
+        "11111111111111111111111111111111"
+    );
+
+    // Borsh-wrapped payloads are expanded
+    assert_eq!(handshake["sender_peer_id"], peer_id.to_string());
+    assert_eq!(handshake["partial_edge_info"]["nonce"], 0);
+}
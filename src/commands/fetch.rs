@@ -0,0 +1,144 @@
+use std::sync::Arc;
+
+use futures::{future, FutureExt};
+
+use serde_json::json;
+
+use tokio::time::Duration;
+
+use near_primitives::{hash::CryptoHash, views::BlockHeaderView};
+
+use crate::{
+    client::{ConnectFn, PeerPool, PoolOptions},
+    error::Error,
+    network_protocol::{block_to_json, NetworkError},
+    target::Target,
+};
+
+use super::{ConnectContext, ConnectionArgs, TargetsArgs};
+
+#[derive(clap::Args)]
+pub struct FetchArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Hash of the block to fetch (comma-separated or repeated)
+    #[clap(
+        long,
+        value_delimiter = ',',
+        required_unless_present = "headers",
+        verbatim_doc_comment
+    )]
+    block: Vec<CryptoHash>,
+
+    /// Hash of the block to fetch the headers following it (comma-separated or repeated)
+    #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+    headers: Vec<CryptoHash>,
+
+    /// Number of the peers to keep the connections with
+    #[clap(short = 'p', long, default_value = "4")]
+    peers: usize,
+
+    /// Connect only to the nodes of the targets file, not to the peers they know
+    #[clap(long)]
+    no_discovery: bool,
+
+    /// Time to wait for each response (in seconds)
+    #[clap(long, default_value = "5")]
+    request_timeout: u64,
+
+    /// Number of the peers each request is sent to before giving up (if the peer times out
+    /// or returns an invalid response)
+    #[clap(long, default_value = "3", verbatim_doc_comment)]
+    attempts: usize,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Fetches the blocks and the block headers from a pool of peers concurrently, printing them
+/// as JSON (one per line). Returns the error of the first failed request (if any).
+pub async fn run(args: FetchArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let mut seeds = args
+        .targets
+        .targets(&home)?
+        .iter()
+        .map(|target| target.parse())
+        .collect::<Result<Vec<Target>, _>>()?;
+
+    let context = Arc::new(ConnectContext::new(args.connection, &home)?);
+
+    // The best scored nodes are connected first
+    if let Some(peer_store) = &context.peer_store {
+        let peer_store = peer_store.lock().unwrap();
+        let score =
+            |target: &Target| match target.peer_id.as_ref().or(context.default_peer_id.as_ref()) {
+                Some(peer_id) => peer_store.score(peer_id),
+                None => 0.0,
+            };
+        seeds.sort_by(|a, b| score(b).total_cmp(&score(a)));
+    }
+
+    let connect: ConnectFn = {
+        let context = context.clone();
+        Arc::new(move |target| {
+            let context = context.clone();
+            async move {
+                let (connection, _, _) = context.connect(&target).await?;
+                Ok(connection)
+            }
+            .boxed()
+        })
+    };
+
+    let pool = PeerPool::new(
+        seeds,
+        connect,
+        PoolOptions {
+            size: args.peers.max(1),
+            request_timeout: Duration::from_secs(args.request_timeout),
+            attempts: args.attempts,
+            discovery: !args.no_discovery,
+        },
+    );
+
+    let blocks = args.block.iter().map(|&hash| {
+        pool.get_block(hash)
+            .map(move |result| ("block", hash, result.map(|block| block_to_json(&block))))
+            .boxed()
+    });
+    let headers = args.headers.iter().map(|&hash| {
+        pool.get_block_headers(vec![hash])
+            .map(move |result| {
+                let to_json = |headers: Vec<_>| {
+                    json!(headers
+                        .into_iter()
+                        .map(BlockHeaderView::from)
+                        .collect::<Vec<_>>())
+                };
+                ("headers", hash, result.map(to_json))
+            })
+            .boxed()
+    });
+
+    let mut first_error: Option<NetworkError> = None;
+
+    for (name, hash, result) in future::join_all(blocks.chain(headers)).await {
+        match result {
+            Ok(value) => println!("{}", json!({ name: value, "hash": hash })),
+            Err(e) => {
+                eprintln!("{} {} failed: {}", name, hash, e);
+                first_error.get_or_insert(e);
+            }
+        }
+    }
+
+    context.save()?;
+
+    match first_error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
@@ -0,0 +1,174 @@
+use std::sync::Arc;
+
+use serde::Serialize;
+
+use tokio::time::Duration;
+
+use near_primitives::{block::BlockHeader, hash::CryptoHash, network::PeerId, types::BlockHeight};
+
+use crate::{
+    chain::{Fork, ForkDetector},
+    client::Client,
+    error::Error,
+    target::Target,
+};
+
+use super::{batch::OutputFormat, ConnectContext, ConnectionArgs, TargetsArgs};
+
+#[derive(clap::Args)]
+pub struct ForksArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Hash of the block known to all the nodes (e.g. a recent final block) to compare the
+    /// headers following it
+    #[clap(long, verbatim_doc_comment)]
+    from: CryptoHash,
+
+    /// Number of the header requests sent to each node, each continuing from the last
+    /// header received (a node returns up to 512 headers per request)
+    #[clap(long, default_value = "1", verbatim_doc_comment)]
+    rounds: usize,
+
+    /// Time to wait for each response (in seconds)
+    #[clap(long, default_value = "5")]
+    request_timeout: u64,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: OutputFormat,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Shared state the headers are fetched with
+struct FetchContext {
+    connect: ConnectContext,
+    from: CryptoHash,
+    rounds: usize,
+    request_timeout: Duration,
+}
+
+/// Result of the comparison, printed with `-f json`
+#[derive(Serialize)]
+struct ForksReport<'a> {
+    /// Number of the nodes that returned the headers, of all the nodes
+    answered: usize,
+    total: usize,
+    /// Range of the heights reported by more than one node, None if there are none
+    compared: Option<(BlockHeight, BlockHeight)>,
+    forks: &'a [Fork],
+}
+
+/// Fetches the headers following the starting block from the node (the branch the node
+/// follows), returns the peer id of the node and the headers
+async fn fetch_headers(
+    target: &str,
+    context: &FetchContext,
+) -> Result<(PeerId, Vec<BlockHeader>), Error> {
+    let target: Target = target.parse()?;
+
+    let (connection, handshake, _) = context.connect.connect(&target).await?;
+
+    let (client, _) = Client::new(connection, context.request_timeout);
+
+    let mut headers: Vec<BlockHeader> = Vec::new();
+    for _ in 0..context.rounds {
+        let last = headers.last().map_or(context.from, |header| *header.hash());
+        let received = client.get_block_headers(vec![last]).await?;
+        if received.is_empty() {
+            break;
+        }
+        headers.extend(received);
+    }
+
+    Ok((handshake.sender_peer_id, headers))
+}
+
+/// Fetches the headers following the given block from all the nodes concurrently and
+/// reports the heights the nodes disagree on (with the nodes following each branch).
+/// Returns an error if a fork is detected, or if no height was reported by more than one
+/// node.
+pub async fn run(args: ForksArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let targets = args.targets.targets(&home)?;
+
+    let context = Arc::new(FetchContext {
+        connect: ConnectContext::new(args.connection, &home)?,
+        from: args.from,
+        rounds: args.rounds.max(1),
+        request_timeout: Duration::from_secs(args.request_timeout),
+    });
+
+    let total = targets.len();
+    let tasks: Vec<_> = targets
+        .into_iter()
+        .map(|target| {
+            let context = context.clone();
+            tokio::spawn(async move {
+                let result = fetch_headers(&target, &context).await;
+                (target, result)
+            })
+        })
+        .collect();
+
+    let mut detector = ForkDetector::default();
+    let mut answered = 0;
+    for task in tasks {
+        let (target, result) = task.await.expect("headers task panicked");
+        match result {
+            Ok((peer_id, headers)) => {
+                eprintln!("{}: {} headers", target, headers.len());
+                detector.record_headers(&peer_id, &headers);
+                answered += usize::from(!headers.is_empty());
+            }
+            Err(e) => eprintln!("{}: {}", target, e),
+        }
+    }
+
+    context.connect.save()?;
+
+    let forks = detector.forks();
+    let compared = detector.compared();
+
+    match args.format {
+        OutputFormat::Table => match compared {
+            None => (),
+            Some((first, last)) if forks.is_empty() => {
+                println!("No forks: the nodes agree on heights {}..{}", first, last)
+            }
+            Some(_) => {
+                for fork in &forks {
+                    println!("Fork at height {}:", fork.height);
+                    for branch in &fork.branches {
+                        let peers: Vec<_> = branch.peers.iter().map(PeerId::to_string).collect();
+                        println!("  {}: {}", branch.hash, peers.join(", "));
+                    }
+                }
+            }
+        },
+        OutputFormat::Json => {
+            let report = ForksReport {
+                answered,
+                total,
+                compared,
+                forks: &forks,
+            };
+            println!("{}", serde_json::to_string(&report).unwrap());
+        }
+    }
+
+    if compared.is_none() {
+        return Err(Error::NothingCompared { answered, total });
+    }
+
+    match forks.first() {
+        Some(fork) => Err(Error::Fork {
+            height: fork.height,
+            heights: forks.len(),
+        }),
+        None => Ok(()),
+    }
+}
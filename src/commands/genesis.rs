@@ -0,0 +1,28 @@
+use std::path::PathBuf;
+
+use crate::{config::NearHome, error::Error, genesis::Genesis};
+
+#[derive(clap::Args)]
+pub struct GenesisArgs {
+    /// Genesis config file [default: genesis.json file of the home directory]
+    genesis_file: Option<PathBuf>,
+
+    /// Home directory of the NEAR node [default: ~/.near]
+    #[clap(long)]
+    home: Option<PathBuf>,
+}
+
+/// Computes the genesis id from the genesis config file and prints it
+pub fn run(args: GenesisArgs) -> Result<(), Error> {
+    let genesis_file = match args.genesis_file {
+        Some(genesis_file) => genesis_file,
+        None => NearHome::new(args.home)?.genesis_file(),
+    };
+
+    let genesis_id = Genesis::from_file(&genesis_file)?.genesis_id()?;
+
+    println!("chain_id: {}", genesis_id.chain_id);
+    println!("hash: {}", genesis_id.hash);
+
+    Ok(())
+}
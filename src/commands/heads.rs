@@ -0,0 +1,210 @@
+use std::sync::Arc;
+
+use tokio::{
+    sync::mpsc,
+    time::{self, Duration, Instant, MissedTickBehavior},
+};
+
+use near_primitives::network::PeerId;
+
+use crate::{
+    chain::HeadTracker,
+    error::Error,
+    network_protocol::{PeerChainInfo, PeerMessage},
+    target::Target,
+};
+
+use super::{ConnectContext, ConnectionArgs, TargetsArgs};
+
+#[derive(clap::Args)]
+pub struct HeadsArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Number of blocks the node may be behind the best known head before it's reported
+    #[clap(long, default_value = "5")]
+    max_lag: u64,
+
+    /// Time without the head growth after which the node is reported as stalled (in
+    /// seconds)
+    #[clap(long, default_value = "60", verbatim_doc_comment)]
+    stall_timeout: u64,
+
+    /// Interval between the printouts of the heads of all the nodes (in seconds)
+    #[clap(short = 'i', long, default_value = "60")]
+    interval: u64,
+
+    /// Interval between the attempts to reconnect to the node (in seconds)
+    #[clap(long, default_value = "10")]
+    reconnect_interval: u64,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Head of the node learned by its session
+enum Update {
+    Handshake(PeerId, PeerChainInfo),
+    Message(PeerId, PeerMessage),
+}
+
+/// Keeps the session with the node, reconnecting when it's closed, and passes the handshake
+/// and the messages received to the tracker
+async fn run_session(
+    target: Target,
+    context: Arc<ConnectContext>,
+    reconnect_interval: Duration,
+    updates: mpsc::UnboundedSender<Update>,
+) {
+    // Only the first of the consecutive failures is reported
+    let mut failed = false;
+
+    loop {
+        match context.connect(&target).await {
+            Ok((mut connection, handshake, _)) => {
+                failed = false;
+                let peer_id = handshake.sender_peer_id;
+                println!(
+                    "{} {}: connected as {}",
+                    chrono::Utc::now().to_rfc3339(),
+                    target,
+                    peer_id
+                );
+                updates
+                    .send(Update::Handshake(
+                        peer_id.clone(),
+                        handshake.sender_chain_info,
+                    ))
+                    .ok();
+
+                let e = loop {
+                    match connection.read_message().await {
+                        Ok(msg) => updates.send(Update::Message(peer_id.clone(), msg)).ok(),
+                        Err(e) => break e,
+                    };
+                };
+                println!(
+                    "{} {}: disconnected ({})",
+                    chrono::Utc::now().to_rfc3339(),
+                    target,
+                    e
+                );
+            }
+            Err(e) => {
+                if !failed {
+                    println!(
+                        "{} {}: connection failed ({})",
+                        chrono::Utc::now().to_rfc3339(),
+                        target,
+                        e
+                    );
+                }
+                failed = true;
+            }
+        }
+
+        time::sleep(reconnect_interval).await;
+    }
+}
+
+fn print_heads(tracker: &HeadTracker, now: Instant) {
+    let best = tracker.best();
+    let best_height = best
+        .as_ref()
+        .map(|(height, ..)| *height)
+        .unwrap_or_default();
+
+    let rows: Vec<[String; 5]> = tracker
+        .heads()
+        .map(|(peer_id, head)| {
+            [
+                peer_id.to_string(),
+                head.height.to_string(),
+                head.hash
+                    .map_or_else(|| "-".into(), |hash| hash.to_string()),
+                (best_height - head.height).to_string(),
+                format!(
+                    "{} s ago",
+                    now.duration_since(head.height_changed_at).as_secs()
+                ),
+            ]
+        })
+        .collect();
+
+    super::print_table(["PEER", "HEIGHT", "HASH", "LAG", "CHANGED"], &rows);
+
+    if let Some((height, hash, peers)) = best {
+        println!(
+            "Best known head: {} {} ({} of {} nodes)",
+            height,
+            hash.map_or_else(|| "-".into(), |hash| hash.to_string()),
+            peers.len(),
+            rows.len()
+        );
+    }
+}
+
+/// Keeps the sessions with the nodes and tracks their heads (the heights reported in the
+/// handshakes and the blocks the nodes send), reporting the nodes that fall behind the best
+/// known head or stall. Runs until interrupted by Ctrl-C.
+pub async fn run(args: HeadsArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let targets = args
+        .targets
+        .targets(&home)?
+        .iter()
+        .map(|target| target.parse())
+        .collect::<Result<Vec<Target>, _>>()?;
+
+    let context = Arc::new(ConnectContext::new(args.connection, &home)?);
+    let reconnect_interval = Duration::from_secs(args.reconnect_interval.max(1));
+
+    let (updates_sender, mut updates) = mpsc::unbounded_channel();
+    for target in targets {
+        tokio::spawn(run_session(
+            target,
+            context.clone(),
+            reconnect_interval,
+            updates_sender.clone(),
+        ));
+    }
+
+    let mut tracker = HeadTracker::new(args.max_lag, Duration::from_secs(args.stall_timeout));
+
+    let mut interval = time::interval(Duration::from_secs(args.interval.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    interval.reset();
+
+    // The stalled heads are detected without any updates
+    let mut check_interval = time::interval(Duration::from_secs(1));
+
+    loop {
+        // The time is taken when the event arrives, not when the wait for it starts
+        let alerts = tokio::select! {
+            update = updates.recv() => match update {
+                Some(Update::Handshake(peer_id, chain_info)) => {
+                    tracker.record_handshake(&peer_id, &chain_info, Instant::now())
+                }
+                Some(Update::Message(peer_id, msg)) => {
+                    tracker.record_message(&peer_id, &msg, Instant::now())
+                }
+                None => break,
+            },
+            _ = check_interval.tick() => tracker.check(Instant::now()),
+            _ = interval.tick() => {
+                print_heads(&tracker, Instant::now());
+                context.save()?;
+                continue;
+            }
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        for (peer_id, alert) in alerts {
+            println!("{} {}: {}", chrono::Utc::now().to_rfc3339(), peer_id, alert);
+        }
+    }
+
+    print_heads(&tracker, Instant::now());
+    context.save()
+}
@@ -0,0 +1,469 @@
+pub mod batch;
+pub mod census;
+pub mod decode;
+pub mod fetch;
+pub mod forks;
+pub mod genesis;
+pub mod heads;
+pub mod monitor;
+pub mod peers;
+pub mod ping;
+pub mod probe_versions;
+pub mod proxy;
+pub mod repl;
+pub mod replay;
+pub mod send;
+
+use std::{
+    fs,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use tokio::time::{Duration, Instant};
+
+use near_primitives::{block::GenesisId, network::PeerId};
+
+use crate::{
+    config::{self, NearHome},
+    connection::{ConnectOptions, FaultInjector, FaultRule, TcpConnection},
+    error::Error,
+    genesis::{cache::GenesisCache, preset::ChainPreset, Genesis},
+    metrics,
+    network_protocol::{Handshake, HandshakeFailure, NetworkError},
+    peer_store::PeerStore,
+    target::Target,
+    transcript::Recorder,
+    DEFAULT_LISTEN_PORT,
+};
+
+/// Command line args shared by all the commands that perform handshakes
+#[derive(clap::Args, Clone)]
+pub struct ConnectionArgs {
+    /// Home directory of the NEAR node with node_key.json, config.json and genesis.json
+    /// config files [default: ~/.near]
+    #[clap(long, verbatim_doc_comment)]
+    pub home: Option<PathBuf>,
+
+    /// Connection timeout (in seconds)
+    #[clap(short = 't', long, default_value = "1")]
+    pub connection_timeout: u64,
+
+    /// Optional blockchain ID of the genesis for the handshake request - "localnet",
+    /// "testnet", "mainnet" etc. (if provided, then "genesis_hash" must be also provided).
+    #[clap(short = 'c', long, requires = "genesis_hash", verbatim_doc_comment)]
+    pub genesis_chain_id: Option<String>,
+
+    /// Optional hash of the genesis for the handshake request (if not provided, the genesis
+    /// will be requested from the node by sending a preliminary handshake request with an
+    /// empty genesis and then sending the second handshake request with the proper genesis
+    /// value). If "genesis_chain_id" is not provided, it's read from genesis.json file
+    /// of the home directory.
+    #[clap(short = 'g', long, verbatim_doc_comment)]
+    pub genesis_hash: Option<String>,
+
+    /// Optional genesis config file (genesis.json) to compute the genesis for the
+    /// handshake request from, instead of providing "genesis_chain_id" and "genesis_hash"
+    /// (if the node belongs to another chain, the handshake fails with genesis mismatch).
+    #[clap(
+        short = 'G',
+        long,
+        conflicts_with_all = ["genesis_chain_id", "genesis_hash"],
+        verbatim_doc_comment
+    )]
+    pub genesis_file: Option<PathBuf>,
+
+    /// Well-known network to take the genesis for the handshake request from (instead of
+    /// providing "genesis_chain_id" and "genesis_hash").
+    #[clap(
+        long,
+        value_enum,
+        conflicts_with_all = ["genesis_chain_id", "genesis_hash", "genesis_file"],
+        verbatim_doc_comment
+    )]
+    pub chain: Option<ChainPreset>,
+
+    /// Genesis cache file - the genesis learned from a node is stored there, so the next
+    /// time the preliminary handshake request with an empty genesis is not needed
+    /// [default: ~/.cache/near-handshake/genesis_cache.json]
+    #[clap(long, verbatim_doc_comment)]
+    pub genesis_cache: Option<PathBuf>,
+
+    /// Don't use the genesis cache
+    #[clap(long, conflicts_with = "genesis_cache")]
+    pub no_genesis_cache: bool,
+
+    // Height of the head for the handshake request
+    #[clap(short = 'b', long, default_value = "0")]
+    pub head_height: u64,
+
+    /// Record all the frames sent to and received from the nodes to the transcript file
+    /// (appended, see "replay" command)
+    #[clap(long, verbatim_doc_comment)]
+    pub record: Option<PathBuf>,
+
+    /// Inject the fault into the frames received from the nodes (for the proxy - into the
+    /// frames forwarded): "<fault>[=<value>][,type=<message type>][,p=<probability>]", where
+    /// the fault is one of delay=<ms>, drop, duplicate, reorder, truncate=<bytes>, bitflip,
+    /// stall=<bytes> (e.g. "delay=500,type=block_response,p=0.5"). For the proxy the side
+    /// the frames are forwarded to can be selected with ",to=client" or ",to=target". May
+    /// be repeated.
+    #[clap(long, verbatim_doc_comment)]
+    pub fault: Vec<FaultRule>,
+
+    /// Seed of the random generator of the fault injection (for reproducible runs)
+    #[clap(long)]
+    pub fault_seed: Option<u64>,
+
+    /// Peer store file - the outcomes of the handshakes and the state reported by the
+    /// nodes are recorded there, and the nodes that sent an invalid signature are banned
+    /// [default: ~/.cache/near-handshake/peer_store.json]
+    #[clap(long, verbatim_doc_comment)]
+    pub peer_store: Option<PathBuf>,
+
+    /// Don't use the peer store
+    #[clap(long, conflicts_with = "peer_store")]
+    pub no_peer_store: bool,
+
+    /// Connect to the nodes banned in the peer store
+    #[clap(long)]
+    pub ignore_bans: bool,
+}
+
+impl ConnectionArgs {
+    /// The args parsed from the given options, the rest are the defaults
+    #[cfg(test)]
+    pub fn parse(args: &[&str]) -> Self {
+        use clap::{Args, Command, FromArgMatches};
+
+        let command = Self::augment_args(Command::new("test"));
+        let matches = command.get_matches_from(std::iter::once(&"test").chain(args));
+        Self::from_arg_matches(&matches).unwrap()
+    }
+
+    pub fn timeout(&self) -> Duration {
+        Duration::from_secs(self.connection_timeout)
+    }
+
+    pub fn near_home(&self) -> Result<NearHome, Error> {
+        NearHome::new(self.home.clone())
+    }
+
+    pub fn genesis_id(&self, home: &NearHome) -> Result<Option<GenesisId>, Error> {
+        if let Some(chain) = self.chain {
+            return Ok(Some(chain.genesis_id()));
+        }
+
+        if let Some(genesis_file) = &self.genesis_file {
+            return Genesis::from_file(genesis_file)?.genesis_id().map(Some);
+        }
+
+        let chain_id = match (&self.genesis_chain_id, &self.genesis_hash) {
+            (None, Some(_)) => Some(home.chain_id()?),
+            (chain_id, _) => chain_id.clone(),
+        };
+
+        config::parse_genesis_id(chain_id, self.genesis_hash.clone())
+    }
+
+    /// Options of the connection with the given transcript recorder, injecting the faults
+    /// if any are configured
+    pub fn connect_options(&self, recorder: Option<Recorder>) -> ConnectOptions {
+        let mut options = ConnectOptions::new(self.timeout());
+        options.recorder = recorder;
+        if !self.fault.is_empty() {
+            options.faults = Some(FaultInjector::new(self.fault.clone(), self.fault_seed));
+        }
+        options
+    }
+
+    pub fn genesis_cache(&self) -> Result<Option<GenesisCache>, Error> {
+        if self.no_genesis_cache {
+            return Ok(None);
+        }
+
+        let path = match &self.genesis_cache {
+            Some(path) => path.clone(),
+            None => GenesisCache::default_path()?,
+        };
+
+        GenesisCache::load(&path).map(Some)
+    }
+
+    pub fn peer_store(&self) -> Result<Option<PeerStore>, Error> {
+        if self.no_peer_store {
+            return Ok(None);
+        }
+
+        let path = match &self.peer_store {
+            Some(path) => path.clone(),
+            None => PeerStore::default_path()?,
+        };
+
+        PeerStore::load(&path).map(Some)
+    }
+}
+
+/// Command line args selecting the nodes for the commands working with many nodes
+#[derive(clap::Args)]
+pub struct TargetsArgs {
+    /// File with the nodes to connect, one per line ("peer_id@host:port" or "host:port",
+    /// empty lines and lines starting with '#' are ignored). If the peer id is not
+    /// specified, it's read from node_key.json file of the home directory.
+    #[clap(required_unless_present = "boot_nodes", verbatim_doc_comment)]
+    targets_file: Option<PathBuf>,
+
+    /// Connect to the boot nodes listed in config.json file of the home directory
+    /// instead of reading the targets file
+    #[clap(long, conflicts_with = "targets_file", verbatim_doc_comment)]
+    boot_nodes: bool,
+}
+
+impl TargetsArgs {
+    pub fn targets(&self, home: &NearHome) -> Result<Vec<String>, Error> {
+        match &self.targets_file {
+            Some(targets_file) => read_targets(targets_file),
+            None => Ok(home
+                .config()?
+                .boot_nodes
+                .iter()
+                .map(Target::to_string)
+                .collect()),
+        }
+    }
+}
+
+/// Prints the rows as a table with the columns aligned
+fn print_table<const N: usize>(header: [&str; N], rows: &[[String; N]]) {
+    let header = header.map(String::from);
+
+    let mut widths = header.clone().map(|s| s.len());
+    for row in rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    for row in std::iter::once(&header).chain(rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+fn read_targets(targets_file: &Path) -> Result<Vec<String>, Error> {
+    Ok(fs::read_to_string(targets_file)
+        .map_err(|_| {
+            Error::Config(format!(
+                "Error reading targets file: {}",
+                targets_file.display()
+            ))
+        })?
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(String::from)
+        .collect())
+}
+
+/// Genesis id as "chain_id/hash"
+pub fn format_genesis_id(genesis_id: &GenesisId) -> String {
+    format!("{}/{}", genesis_id.chain_id, genesis_id.hash)
+}
+
+/// Fails if the peer is banned in the peer store (unless the bans are ignored)
+pub fn check_ban(
+    peer_id: &PeerId,
+    args: &ConnectionArgs,
+    peer_store: Option<&Mutex<PeerStore>>,
+) -> Result<(), Error> {
+    if let (Some(peer_store), false) = (peer_store, args.ignore_bans) {
+        if let Some(ban) = peer_store.lock().unwrap().ban(peer_id) {
+            return Err(Error::Banned {
+                peer_id: peer_id.clone(),
+                reason: ban.reason.clone(),
+            });
+        }
+    }
+    Ok(())
+}
+
+/// Connects to the node and performs the handshake. If the genesis is not provided, the one
+/// cached for the node is used, or the genesis learned from the node is cached otherwise.
+/// The node banned in the peer store is not connected (unless the bans are ignored), and the
+/// outcome of the handshake is recorded to the store.
+pub async fn connect(
+    addr: SocketAddr,
+    peer_id: PeerId,
+    genesis_id: Option<GenesisId>,
+    args: &ConnectionArgs,
+    cache: Option<&Mutex<GenesisCache>>,
+    peer_store: Option<&Mutex<PeerStore>>,
+) -> Result<(TcpConnection, Handshake), Error> {
+    check_ban(&peer_id, args, peer_store)?;
+
+    let cached_genesis_id = match (&genesis_id, cache) {
+        (None, Some(cache)) => cache.lock().unwrap().get(&peer_id, &addr),
+        _ => None,
+    };
+
+    let recorder = args
+        .record
+        .as_ref()
+        .map(|path| Recorder::open(path, format!("{}@{}", peer_id, addr)))
+        .transpose()?;
+
+    let start = Instant::now();
+
+    let result = TcpConnection::connect(
+        addr,
+        peer_id.clone(),
+        DEFAULT_LISTEN_PORT,
+        genesis_id.clone().or_else(|| cached_genesis_id.clone()),
+        args.head_height,
+        args.connect_options(recorder),
+    )
+    .await;
+
+    if let Some(peer_store) = peer_store {
+        peer_store.lock().unwrap().record_handshake(
+            &peer_id,
+            &addr,
+            result.as_ref().map(|(_, handshake)| handshake),
+            start.elapsed(),
+        );
+    }
+
+    metrics::record_handshake(
+        &peer_id,
+        &addr,
+        result.as_ref().map(|(_, handshake)| handshake),
+    );
+
+    match (&result, cache) {
+        (
+            Err(NetworkError::HandshakeFailure(HandshakeFailure::GenesisMismatch(node_genesis_id))),
+            Some(cache),
+        ) => {
+            if let Some(cached_genesis_id) = cached_genesis_id {
+                eprintln!(
+                    "WARNING: the genesis of node {}@{} has changed! Cached genesis: {} {}, \
+                     genesis reported by the node: {} {}. The cache is not updated, remove \
+                     the node from {} if the change is expected.",
+                    peer_id,
+                    addr,
+                    cached_genesis_id.chain_id,
+                    cached_genesis_id.hash,
+                    node_genesis_id.chain_id,
+                    node_genesis_id.hash,
+                    cache.lock().unwrap().path().display(),
+                );
+            }
+        }
+
+        (Ok((_, handshake)), Some(cache))
+            if genesis_id.is_none() && cached_genesis_id.is_none() =>
+        {
+            cache
+                .lock()
+                .unwrap()
+                .insert(&peer_id, &addr, &handshake.sender_chain_info.genesis_id);
+        }
+
+        _ => (),
+    }
+
+    Ok(result?)
+}
+
+/// Shared state the nodes are connected with: the connection args, the peer id of the
+/// targets not specifying one (read from the home directory), the genesis, the genesis cache
+/// and the peer store
+pub struct ConnectContext {
+    pub args: ConnectionArgs,
+    pub default_peer_id: Option<PeerId>,
+    pub genesis_id: Option<GenesisId>,
+    pub genesis_cache: Option<Mutex<GenesisCache>>,
+    pub peer_store: Option<Mutex<PeerStore>>,
+}
+
+impl ConnectContext {
+    /// Prepares the genesis and loads the genesis cache and the peer store once, before
+    /// connecting to any node
+    pub fn new(args: ConnectionArgs, home: &NearHome) -> Result<Self, Error> {
+        Ok(Self {
+            default_peer_id: home.peer_id().ok(),
+            genesis_id: args.genesis_id(home)?,
+            genesis_cache: args.genesis_cache()?.map(Mutex::new),
+            peer_store: args.peer_store()?.map(Mutex::new),
+            args,
+        })
+    }
+
+    /// Connects to the target (using the default peer id if the target doesn't specify one)
+    /// and performs the handshake, returns the connection, the response and the handshake
+    /// latency
+    pub async fn connect(
+        &self,
+        target: &Target,
+    ) -> Result<(TcpConnection, Handshake, Duration), Error> {
+        let peer_id = target
+            .peer_id
+            .clone()
+            .or_else(|| self.default_peer_id.clone())
+            .ok_or_else(|| {
+                Error::Config(format!(
+                    "Peer id of target not specified and node key config file not available: {}",
+                    target
+                ))
+            })?;
+
+        let addr = target.resolve().await?;
+
+        let start = Instant::now();
+
+        let (connection, handshake) = connect(
+            addr,
+            peer_id,
+            self.genesis_id.clone(),
+            &self.args,
+            self.genesis_cache.as_ref(),
+            self.peer_store.as_ref(),
+        )
+        .await?;
+
+        Ok((connection, handshake, start.elapsed()))
+    }
+
+    /// Writes the genesis cache and the peer store to their files (if modified)
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(genesis_cache) = &self.genesis_cache {
+            genesis_cache.lock().unwrap().save()?;
+        }
+        if let Some(peer_store) = &self.peer_store {
+            peer_store.lock().unwrap().save()?;
+        }
+        Ok(())
+    }
+}
+
+/// Connects to the node given as "peer_id@host:port" or "host:port" (the peer id is read from
+/// the home directory if not specified) and performs the handshake, using the genesis cache
+/// and the peer store
+pub async fn connect_target(
+    target: &str,
+    args: &ConnectionArgs,
+) -> Result<(TcpConnection, Handshake), Error> {
+    let target: Target = target.parse()?;
+
+    let context = ConnectContext::new(args.clone(), &args.near_home()?)?;
+    let result = context.connect(&target).await;
+    context.save()?;
+
+    let (connection, handshake, _) = result?;
+    Ok((connection, handshake))
+}
@@ -0,0 +1,313 @@
+#[cfg(test)]
+mod tests;
+
+use std::{
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::Write,
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+};
+
+use serde::Serialize;
+
+use tokio::time::{self, Duration, Instant, MissedTickBehavior};
+
+use near_primitives::{block::GenesisId, types::BlockHeight, version::ProtocolVersion};
+
+use crate::{error::Error, metrics, network_protocol::Handshake};
+
+use super::{
+    batch::{handshake, spawn_handshakes},
+    format_genesis_id, ConnectContext, ConnectionArgs, TargetsArgs,
+};
+
+#[derive(clap::Args)]
+pub struct MonitorArgs {
+    #[clap(flatten)]
+    targets: TargetsArgs,
+
+    /// Interval between the handshake rounds (in seconds)
+    #[clap(short = 'i', long, default_value = "60")]
+    interval: u64,
+
+    /// Time without the node height growth after which the node is reported as stalled
+    /// (in seconds)
+    #[clap(long, default_value = "300", verbatim_doc_comment)]
+    stall_timeout: u64,
+
+    /// Number of the handshake rounds to perform (runs until interrupted by default)
+    #[clap(long)]
+    rounds: Option<u64>,
+
+    /// File to append the events to (one JSON object per line)
+    #[clap(short = 'l', long)]
+    event_log: Option<PathBuf>,
+
+    /// Maximum number of handshakes performed concurrently
+    #[clap(short = 'p', long, default_value = "16")]
+    parallelism: usize,
+
+    /// Address to expose the Prometheus metrics on (at /metrics path), e.g. 127.0.0.1:9090
+    #[clap(long)]
+    metrics_addr: Option<SocketAddr>,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// State transition of a monitored node
+#[derive(Serialize, Debug, PartialEq)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum Event {
+    Up {
+        protocol_version: ProtocolVersion,
+        height: BlockHeight,
+        genesis: String,
+    },
+    Down {
+        error: String,
+        exit_code: u8,
+    },
+    GenesisChanged {
+        from: String,
+        to: String,
+    },
+    VersionChanged {
+        from: ProtocolVersion,
+        to: ProtocolVersion,
+    },
+    HeightStalled {
+        height: BlockHeight,
+        stalled_secs: u64,
+    },
+    HeightResumed {
+        height: BlockHeight,
+    },
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Up {
+                protocol_version,
+                height,
+                genesis,
+            } => write!(
+                f,
+                "up (version: {}, height: {}, genesis: {})",
+                protocol_version, height, genesis
+            ),
+            Self::Down { error, .. } => write!(f, "down ({})", error),
+            Self::GenesisChanged { from, to } => {
+                write!(f, "genesis changed from {} to {}", from, to)
+            }
+            Self::VersionChanged { from, to } => {
+                write!(f, "protocol version changed from {} to {}", from, to)
+            }
+            Self::HeightStalled {
+                height,
+                stalled_secs,
+            } => write!(
+                f,
+                "height stalled at {} for {} seconds",
+                height, stalled_secs
+            ),
+            Self::HeightResumed { height } => write!(f, "height resumed ({})", height),
+        }
+    }
+}
+
+/// Event log entry
+#[derive(Serialize)]
+struct EventRecord<'a> {
+    timestamp: String,
+    target: &'a str,
+    #[serde(flatten)]
+    event: &'a Event,
+}
+
+/// Last known state of a monitored node (the chain info is kept while the node is down,
+/// so the changes are detected when it's up again)
+#[derive(Default)]
+pub struct NodeState {
+    up: Option<bool>,
+    genesis_id: Option<GenesisId>,
+    protocol_version: Option<ProtocolVersion>,
+    height: Option<BlockHeight>,
+    height_changed_at: Option<Instant>,
+    stalled: bool,
+}
+
+impl NodeState {
+    /// Updates the state with the result of the handshake performed at `now` and returns
+    /// the state transitions
+    pub fn update(
+        &mut self,
+        result: Result<&Handshake, &Error>,
+        now: Instant,
+        stall_timeout: Duration,
+    ) -> Vec<Event> {
+        let mut events = Vec::new();
+
+        let handshake = match result {
+            Ok(handshake) => handshake,
+            Err(e) => {
+                if self.up != Some(false) {
+                    events.push(Event::Down {
+                        error: e.to_string(),
+                        exit_code: e.code(),
+                    });
+                }
+                self.up = Some(false);
+                return events;
+            }
+        };
+
+        let chain_info = &handshake.sender_chain_info;
+
+        if self.up != Some(true) {
+            events.push(Event::Up {
+                protocol_version: handshake.protocol_version,
+                height: chain_info.height,
+                genesis: format_genesis_id(&chain_info.genesis_id),
+            });
+        }
+        self.up = Some(true);
+
+        match self.genesis_id.replace(chain_info.genesis_id.clone()) {
+            Some(from) if from != chain_info.genesis_id => events.push(Event::GenesisChanged {
+                from: format_genesis_id(&from),
+                to: format_genesis_id(&chain_info.genesis_id),
+            }),
+            _ => (),
+        }
+
+        match self.protocol_version.replace(handshake.protocol_version) {
+            Some(from) if from != handshake.protocol_version => {
+                events.push(Event::VersionChanged {
+                    from,
+                    to: handshake.protocol_version,
+                })
+            }
+            _ => (),
+        }
+
+        if self.height != Some(chain_info.height) {
+            if self.stalled {
+                events.push(Event::HeightResumed {
+                    height: chain_info.height,
+                });
+            }
+            self.height = Some(chain_info.height);
+            self.height_changed_at = Some(now);
+            self.stalled = false;
+        } else if let Some(height_changed_at) = self.height_changed_at {
+            let stalled_for = now.duration_since(height_changed_at);
+            if !self.stalled && stalled_for >= stall_timeout {
+                events.push(Event::HeightStalled {
+                    height: chain_info.height,
+                    stalled_secs: stalled_for.as_secs(),
+                });
+                self.stalled = true;
+            }
+        }
+
+        events
+    }
+}
+
+fn open_event_log(path: &PathBuf) -> Result<File, Error> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir).ok();
+    }
+
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .map_err(|_| Error::Config(format!("Error opening event log file: {}", path.display())))
+}
+
+/// Performs handshakes with the nodes periodically and reports the changes of their state
+/// (to stdout and to the event log). Runs until the given number of rounds is performed
+/// or until interrupted by Ctrl-C.
+pub async fn run(args: MonitorArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let targets = args.targets.targets(&home)?;
+    let context = Arc::new(ConnectContext::new(args.connection, &home)?);
+
+    let mut event_log = args.event_log.as_ref().map(open_event_log).transpose()?;
+
+    if let Some(metrics_addr) = args.metrics_addr {
+        metrics::serve(metrics_addr).await.map_err(|e| {
+            Error::Config(format!(
+                "Error starting metrics server on {}: {}",
+                metrics_addr, e
+            ))
+        })?;
+    }
+
+    let stall_timeout = Duration::from_secs(args.stall_timeout);
+
+    let mut states: Vec<NodeState> = targets.iter().map(|_| NodeState::default()).collect();
+
+    let mut interval = time::interval(Duration::from_secs(args.interval.max(1)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    // Interrupts both the wait for the next round and the round itself
+    let ctrl_c = tokio::signal::ctrl_c();
+    tokio::pin!(ctrl_c);
+
+    let mut round = 0;
+    while args.rounds.is_none_or(|rounds| round < rounds) {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = &mut ctrl_c => break,
+        }
+        round += 1;
+
+        let handshakes = async {
+            let tasks = spawn_handshakes(&targets, args.parallelism, |target| {
+                handshake(target, context.clone())
+            });
+
+            for ((target, state), task) in targets.iter().zip(&mut states).zip(tasks) {
+                let result = task.await.expect("handshake task panicked");
+                let now = Instant::now();
+
+                for event in state.update(
+                    result.as_ref().map(|(handshake, _)| handshake),
+                    now,
+                    stall_timeout,
+                ) {
+                    let timestamp = chrono::Utc::now().to_rfc3339();
+
+                    println!("{} {}: {}", timestamp, target, event);
+
+                    if let Some(event_log) = &mut event_log {
+                        let record = EventRecord {
+                            timestamp,
+                            target,
+                            event: &event,
+                        };
+                        writeln!(event_log, "{}", serde_json::to_string(&record).unwrap())
+                            .map_err(|_| Error::Config("Error writing event log file".into()))?;
+                    }
+                }
+            }
+
+            context.save()
+        };
+
+        tokio::select! {
+            result = handshakes => result?,
+            _ = &mut ctrl_c => break,
+        }
+    }
+
+    // The outcomes of the round interrupted are saved too
+    context.save()
+}
@@ -0,0 +1,90 @@
+use tokio::time::{Duration, Instant};
+
+use near_crypto::{ED25519PublicKey, PublicKey};
+
+use near_primitives::{block::GenesisId, hash::CryptoHash, network::PeerId};
+
+use crate::{
+    error::Error,
+    network_protocol::{Handshake, NetworkError, PeerChainInfo},
+};
+
+use super::{Event, NodeState};
+
+const STALL_TIMEOUT: Duration = Duration::from_secs(300);
+
+fn handshake(protocol_version: u32, genesis_hash: u8, height: u64) -> Handshake {
+    let peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([1u8; 32])));
+
+    Handshake {
+        protocol_version,
+        oldest_supported_version: protocol_version - 2,
+        sender_peer_id: peer_id.clone(),
+        target_peer_id: peer_id,
+        sender_listen_port: None,
+        sender_chain_info: PeerChainInfo {
+            genesis_id: GenesisId {
+                chain_id: "localnet".into(),
+                hash: CryptoHash([genesis_hash; 32]),
+            },
+            height,
+            ..Default::default()
+        },
+        partial_edge_info: Default::default(),
+    }
+}
+
+#[test]
+fn test_node_state() {
+    let mut state = NodeState::default();
+    let start = Instant::now();
+    let at = |secs| start + Duration::from_secs(secs);
+
+    let events = state.update(Ok(&handshake(57, 1, 10)), at(0), STALL_TIMEOUT);
+    assert!(matches!(events[..], [Event::Up { height: 10, .. }]));
+
+    // Nothing changed
+    assert!(state
+        .update(Ok(&handshake(57, 1, 11)), at(60), STALL_TIMEOUT)
+        .is_empty());
+
+    let error = Error::Network(NetworkError::ConnectTimeout);
+    let events = state.update(Err(&error), at(120), STALL_TIMEOUT);
+    assert!(matches!(events[..], [Event::Down { exit_code: 3, .. }]));
+
+    // Down is reported once
+    assert!(state.update(Err(&error), at(180), STALL_TIMEOUT).is_empty());
+
+    // The node is restarted with another version and genesis
+    let events = state.update(Ok(&handshake(58, 2, 11)), at(240), STALL_TIMEOUT);
+    assert!(matches!(
+        events[..],
+        [
+            Event::Up { .. },
+            Event::GenesisChanged { .. },
+            Event::VersionChanged { from: 57, to: 58 }
+        ]
+    ));
+
+    // The height is the same since 60 seconds
+    assert!(state
+        .update(Ok(&handshake(58, 2, 11)), at(300), STALL_TIMEOUT)
+        .is_empty());
+    assert_eq!(
+        state.update(Ok(&handshake(58, 2, 11)), at(360), STALL_TIMEOUT),
+        vec![Event::HeightStalled {
+            height: 11,
+            stalled_secs: 300
+        }]
+    );
+
+    // Stall is reported once
+    assert!(state
+        .update(Ok(&handshake(58, 2, 11)), at(420), STALL_TIMEOUT)
+        .is_empty());
+
+    assert_eq!(
+        state.update(Ok(&handshake(58, 2, 12)), at(480), STALL_TIMEOUT),
+        vec![Event::HeightResumed { height: 12 }]
+    );
+}
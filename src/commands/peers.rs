@@ -0,0 +1,111 @@
+use std::{path::PathBuf, str::FromStr};
+
+use serde::Serialize;
+
+use near_crypto::PublicKey;
+
+use near_primitives::network::PeerId;
+
+use crate::{
+    error::Error,
+    peer_store::{PeerRecord, PeerStore},
+};
+
+use super::batch::OutputFormat;
+
+#[derive(clap::Args)]
+pub struct PeersArgs {
+    /// Peer store file [default: ~/.cache/near-handshake/peer_store.json]
+    #[clap(long)]
+    peer_store: Option<PathBuf>,
+
+    /// Lift the ban of the peer (may be repeated)
+    #[clap(long)]
+    unban: Vec<String>,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: OutputFormat,
+}
+
+/// Peer of the store as printed in JSON format
+#[derive(Serialize)]
+struct PeerEntry<'a> {
+    peer_id: &'a str,
+    score: f64,
+    #[serde(flatten)]
+    record: &'a PeerRecord,
+}
+
+fn print_table(peers: &[(&str, &PeerRecord)]) {
+    let rows: Vec<[String; 9]> = peers
+        .iter()
+        .map(|(peer_id, peer)| {
+            let opt = |v: Option<String>| v.unwrap_or_else(|| "-".into());
+            [
+                peer_id.to_string(),
+                opt(peer.addr.map(|v| v.to_string())),
+                format!("{:.1}", peer.score()),
+                format!("{}/{}", peer.successes(), peer.history.len()),
+                opt(peer.latency().map(|v| format!("{} ms", v.as_millis()))),
+                opt(peer.protocol_version.map(|v| v.to_string())),
+                opt(peer.chain_info.as_ref().map(|v| v.height.to_string())),
+                opt(peer.last_seen.map(|v| v.to_rfc3339())),
+                opt(peer.ban.as_ref().map(|v| v.reason.clone())),
+            ]
+        })
+        .collect();
+
+    super::print_table(
+        [
+            "PEER",
+            "ADDR",
+            "SCORE",
+            "OK",
+            "LATENCY",
+            "VERSION",
+            "HEIGHT",
+            "LAST SEEN",
+            "BANNED",
+        ],
+        &rows,
+    );
+}
+
+/// Prints the peers recorded in the peer store (the best scored first), after lifting the
+/// bans of the given peers
+pub fn run(args: PeersArgs) -> Result<(), Error> {
+    let path = match args.peer_store {
+        Some(path) => path,
+        None => PeerStore::default_path()?,
+    };
+    let mut peer_store = PeerStore::load(&path)?;
+
+    for peer_id in &args.unban {
+        let peer_id = PeerId::new(
+            PublicKey::from_str(peer_id)
+                .map_err(|_| Error::Config(format!("Error parsing peer id: {}", peer_id)))?,
+        );
+        if !peer_store.unban(&peer_id) {
+            eprintln!("Peer {} is not banned", peer_id);
+        }
+    }
+    peer_store.save()?;
+
+    let peers = peer_store.peers();
+    match args.format {
+        OutputFormat::Table => print_table(&peers),
+        OutputFormat::Json => {
+            for (peer_id, record) in peers {
+                let entry = PeerEntry {
+                    peer_id,
+                    score: record.score(),
+                    record,
+                };
+                println!("{}", serde_json::to_string(&entry).unwrap());
+            }
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,421 @@
+use std::net::SocketAddr;
+
+use serde::Serialize;
+
+use tokio::time::{self, Duration, Instant, MissedTickBehavior};
+
+use near_primitives::{block::GenesisId, network::PeerId, version::PROTOCOL_VERSION};
+
+use crate::{
+    connection::TcpConnection,
+    error::Error,
+    metrics,
+    network_protocol::{Handshake, NetworkError},
+    target::Target,
+    transcript::Recorder,
+    DEFAULT_LISTEN_PORT,
+};
+
+use super::{batch::OutputFormat, ConnectContext, ConnectionArgs};
+
+#[derive(clap::Args)]
+pub struct PingArgs {
+    /// Node to ping - "peer_id@host:port" or "host:port" (if the peer id is not specified,
+    /// it's read from node_key.json file of the home directory)
+    #[clap(verbatim_doc_comment)]
+    target: String,
+
+    /// Number of the handshakes to perform (runs until interrupted by default)
+    #[clap(long)]
+    count: Option<u64>,
+
+    /// Interval between the starts of the handshakes (in seconds, may be fractional)
+    #[clap(short = 'i', long, default_value = "1")]
+    interval: f64,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: OutputFormat,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Durations of the phases of the handshake (a phase not reached is None)
+#[derive(Default)]
+struct Phases {
+    connect: Option<Duration>,
+    write: Option<Duration>,
+    read: Option<Duration>,
+    total: Duration,
+}
+
+/// Handshake attempt as printed in JSON format
+#[derive(Serialize)]
+struct AttemptEntry {
+    seq: u64,
+    /// "ok" or the error
+    result: String,
+    connect_ms: Option<f64>,
+    write_ms: Option<f64>,
+    read_ms: Option<f64>,
+    total_ms: f64,
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_nanos() as f64 / 1e6
+}
+
+/// Summary of the durations of the phase over the successful handshakes
+#[derive(Serialize, Debug, PartialEq)]
+struct Stats {
+    min_ms: f64,
+    avg_ms: f64,
+    max_ms: f64,
+    stddev_ms: f64,
+    p99_ms: f64,
+}
+
+impl Stats {
+    fn new(durations: &[Duration]) -> Option<Self> {
+        let mut values: Vec<f64> = durations.iter().copied().map(millis).collect();
+        if values.is_empty() {
+            return None;
+        }
+        values.sort_by(f64::total_cmp);
+
+        let n = values.len() as f64;
+        let avg = values.iter().sum::<f64>() / n;
+        let variance = values.iter().map(|v| (v - avg).powi(2)).sum::<f64>() / n;
+        // Nearest-rank percentile
+        let p99 = values[(values.len() * 99).div_ceil(100) - 1];
+
+        Some(Self {
+            min_ms: values[0],
+            avg_ms: avg,
+            max_ms: values[values.len() - 1],
+            stddev_ms: variance.sqrt(),
+            p99_ms: p99,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct Summary {
+    handshakes: u64,
+    ok: u64,
+    failed: u64,
+    connect: Option<Stats>,
+    write: Option<Stats>,
+    read: Option<Stats>,
+    total: Option<Stats>,
+}
+
+/// Durations of the phases of the successful handshakes
+#[derive(Default)]
+struct Timings {
+    connect: Vec<Duration>,
+    write: Vec<Duration>,
+    read: Vec<Duration>,
+    total: Vec<Duration>,
+}
+
+impl Timings {
+    fn record(&mut self, phases: &Phases) {
+        if let (Some(connect), Some(write), Some(read)) =
+            (phases.connect, phases.write, phases.read)
+        {
+            self.connect.push(connect);
+            self.write.push(write);
+            self.read.push(read);
+            self.total.push(phases.total);
+        }
+    }
+}
+
+/// Performs the full handshakes with the node (each over a new connection, closed after
+/// the handshake)
+struct Pinger<'a> {
+    addr: SocketAddr,
+    peer_id: PeerId,
+    genesis_id: GenesisId,
+    args: &'a ConnectionArgs,
+}
+
+impl Pinger<'_> {
+    async fn handshake(&self) -> Result<(Phases, Result<Handshake, NetworkError>), Error> {
+        let recorder = self
+            .args
+            .record
+            .as_ref()
+            .map(|path| Recorder::open(path, format!("{}@{}", self.peer_id, self.addr)))
+            .transpose()?;
+
+        let mut phases = Phases::default();
+        let start = Instant::now();
+
+        let result = async {
+            let mut connection = TcpConnection::open(
+                self.addr,
+                self.peer_id.clone(),
+                DEFAULT_LISTEN_PORT,
+                self.args.connect_options(recorder),
+            )
+            .await?;
+            let connected = Instant::now();
+            phases.connect = Some(connected - start);
+
+            let request = connection
+                .send_handshake(
+                    PROTOCOL_VERSION,
+                    PROTOCOL_VERSION - 2,
+                    self.genesis_id.clone(),
+                    self.args.head_height,
+                )
+                .await?;
+            let sent = Instant::now();
+            phases.write = Some(sent - connected);
+
+            let response = connection.read_handshake_response(&request).await?;
+            phases.read = Some(sent.elapsed());
+
+            Ok::<_, NetworkError>(response.0)
+        }
+        .await;
+
+        phases.total = start.elapsed();
+
+        Ok((phases, result))
+    }
+}
+
+fn print_attempt(seq: u64, phases: &Phases, result: &Result<Handshake, NetworkError>) {
+    match result {
+        Ok(handshake) => {
+            let phase =
+                |phase: Option<Duration>| format!("{:.3} ms", millis(phase.unwrap_or_default()));
+            println!(
+                "seq={}: ok, connect {}, write {}, read {}, total {} (protocol version {}, height {})",
+                seq,
+                phase(phases.connect),
+                phase(phases.write),
+                phase(phases.read),
+                phase(Some(phases.total)),
+                handshake.protocol_version,
+                handshake.sender_chain_info.height
+            );
+        }
+        Err(e) => println!(
+            "seq={}: failed after {:.3} ms: {}",
+            seq,
+            millis(phases.total),
+            e
+        ),
+    }
+}
+
+fn print_summary(target: &str, summary: &Summary) {
+    println!();
+    println!("--- {} handshake statistics ---", target);
+    println!(
+        "{} handshakes, {} ok, {} failed ({:.1}% failed)",
+        summary.handshakes,
+        summary.ok,
+        summary.failed,
+        100.0 * summary.failed as f64 / summary.handshakes.max(1) as f64
+    );
+
+    let rows: Vec<[String; 6]> = [
+        ("connect", &summary.connect),
+        ("write", &summary.write),
+        ("read", &summary.read),
+        ("total", &summary.total),
+    ]
+    .into_iter()
+    .filter_map(|(phase, stats)| {
+        let stats = stats.as_ref()?;
+        let ms = |v: f64| format!("{:.3} ms", v);
+        Some([
+            phase.into(),
+            ms(stats.min_ms),
+            ms(stats.avg_ms),
+            ms(stats.max_ms),
+            ms(stats.stddev_ms),
+            ms(stats.p99_ms),
+        ])
+    })
+    .collect();
+
+    if !rows.is_empty() {
+        super::print_table(["PHASE", "MIN", "AVG", "MAX", "STDDEV", "P99"], &rows);
+    }
+}
+
+/// Learns the genesis of the node: the one provided or cached for the node, or the one the
+/// node reports to the preliminary handshake (not counted)
+async fn resolve_genesis_id(
+    addr: SocketAddr,
+    peer_id: &PeerId,
+    context: &ConnectContext,
+) -> Result<GenesisId, Error> {
+    let cached_genesis_id = context
+        .genesis_cache
+        .as_ref()
+        .and_then(|cache| cache.lock().unwrap().get(peer_id, &addr));
+
+    match context.genesis_id.clone().or(cached_genesis_id) {
+        Some(genesis_id) => Ok(genesis_id),
+        None => {
+            let (_, handshake) = super::connect(
+                addr,
+                peer_id.clone(),
+                None,
+                &context.args,
+                context.genesis_cache.as_ref(),
+                context.peer_store.as_ref(),
+            )
+            .await?;
+            Ok(handshake.sender_chain_info.genesis_id)
+        }
+    }
+}
+
+/// Repeats the full handshake with the node (connect, handshake, close), printing the
+/// durations of the phases of each handshake, and the summary of them when the count is
+/// reached or the tool is interrupted by Ctrl-C. Returns the last error if no handshake
+/// succeeded.
+pub async fn run(args: PingArgs) -> Result<(), Error> {
+    let home = args.connection.near_home()?;
+
+    let target: Target = args.target.parse()?;
+    let peer_id = match target.peer_id.clone() {
+        Some(peer_id) => peer_id,
+        None => home.peer_id()?,
+    };
+    let addr = target.resolve().await?;
+
+    let context = ConnectContext::new(args.connection, &home)?;
+    super::check_ban(&peer_id, &context.args, context.peer_store.as_ref())?;
+
+    let genesis_id = resolve_genesis_id(addr, &peer_id, &context).await?;
+
+    let pinger = Pinger {
+        addr,
+        peer_id: peer_id.clone(),
+        genesis_id,
+        args: &context.args,
+    };
+
+    let target = format!("{}@{}", peer_id, addr);
+    if let OutputFormat::Table = args.format {
+        println!("HANDSHAKE {}", target);
+    }
+
+    let mut interval = time::interval(Duration::from_secs_f64(args.interval.max(0.001)));
+    interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+    let mut timings = Timings::default();
+    let (mut seq, mut failed) = (0, 0);
+    let mut last_error = None;
+
+    while args.count.is_none_or(|count| seq < count) {
+        tokio::select! {
+            _ = interval.tick() => (),
+            _ = tokio::signal::ctrl_c() => break,
+        }
+        seq += 1;
+
+        let (phases, result) = tokio::select! {
+            result = pinger.handshake() => result?,
+            _ = tokio::signal::ctrl_c() => break,
+        };
+
+        if let Some(peer_store) = &context.peer_store {
+            peer_store.lock().unwrap().record_handshake(
+                &peer_id,
+                &addr,
+                result.as_ref(),
+                phases.total,
+            );
+        }
+        metrics::record_handshake(&peer_id, &addr, result.as_ref());
+
+        match args.format {
+            OutputFormat::Table => print_attempt(seq, &phases, &result),
+            OutputFormat::Json => {
+                let entry = AttemptEntry {
+                    seq,
+                    result: match &result {
+                        Ok(_) => "ok".into(),
+                        Err(e) => e.to_string(),
+                    },
+                    connect_ms: phases.connect.map(millis),
+                    write_ms: phases.write.map(millis),
+                    read_ms: phases.read.map(millis),
+                    total_ms: millis(phases.total),
+                };
+                println!("{}", serde_json::to_string(&entry).unwrap());
+            }
+        }
+
+        match result {
+            Ok(_) => timings.record(&phases),
+            Err(e) => {
+                failed += 1;
+                last_error = Some(e);
+            }
+        }
+    }
+
+    // The handshake interrupted is not counted
+    let handshakes = timings.total.len() as u64 + failed;
+    let summary = Summary {
+        handshakes,
+        ok: handshakes - failed,
+        failed,
+        connect: Stats::new(&timings.connect),
+        write: Stats::new(&timings.write),
+        read: Stats::new(&timings.read),
+        total: Stats::new(&timings.total),
+    };
+
+    match args.format {
+        OutputFormat::Table => print_summary(&target, &summary),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&summary).unwrap()),
+    }
+
+    context.save()?;
+
+    match (summary.ok, last_error) {
+        (0, Some(e)) => Err(e.into()),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stats() {
+        assert_eq!(Stats::new(&[]), None);
+
+        let durations: Vec<_> = [4, 2, 6, 4, 4].map(Duration::from_millis).to_vec();
+        assert_eq!(
+            Stats::new(&durations),
+            Some(Stats {
+                min_ms: 2.0,
+                avg_ms: 4.0,
+                max_ms: 6.0,
+                stddev_ms: (1.6f64).sqrt(),
+                p99_ms: 6.0,
+            })
+        );
+
+        // The 99th percentile of 200 values skips the 2 largest ones
+        let durations: Vec<_> = (1..=200).map(Duration::from_millis).collect();
+        let stats = Stats::new(&durations).unwrap();
+        assert_eq!(stats.p99_ms, 198.0);
+        assert_eq!(stats.avg_ms, 100.5);
+    }
+}
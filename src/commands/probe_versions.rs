@@ -0,0 +1,433 @@
+use std::{future::Future, net::SocketAddr, sync::Mutex};
+
+use serde::Serialize;
+
+use near_primitives::{
+    block::GenesisId,
+    network::PeerId,
+    version::{ProtocolVersion, PROTOCOL_VERSION},
+};
+
+use crate::{
+    connection::TcpConnection,
+    error::Error,
+    network_protocol::{Handshake, HandshakeFailure, NetworkError},
+    target::Target,
+    transcript::Recorder,
+    DEFAULT_LISTEN_PORT,
+};
+
+use super::{batch::OutputFormat, ConnectionArgs};
+
+#[derive(clap::Args)]
+pub struct ProbeVersionsArgs {
+    /// Node to probe - "peer_id@host:port" or "host:port" (if the peer id is not specified,
+    /// it's read from node_key.json file of the home directory)
+    #[clap(verbatim_doc_comment)]
+    target: String,
+
+    /// Lowest protocol version probed
+    #[clap(long, default_value = "1")]
+    min_version: ProtocolVersion,
+
+    /// Highest protocol version probed [default: the version of the tool + 100]
+    #[clap(long)]
+    max_version: Option<ProtocolVersion>,
+
+    /// Output format
+    #[clap(short = 'f', long, value_enum, default_value = "table")]
+    format: OutputFormat,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+/// Range of the protocol versions (oldest..=latest)
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+struct Versions {
+    oldest: ProtocolVersion,
+    latest: ProtocolVersion,
+}
+
+/// Handshake performed with the node advertising the range of the versions
+#[derive(Serialize)]
+struct Probe {
+    protocol_version: ProtocolVersion,
+    oldest_supported_version: ProtocolVersion,
+    /// "ok" or the error
+    result: String,
+    /// Version of the response of the node (it may be outside of the range advertised)
+    response_version: Option<ProtocolVersion>,
+}
+
+#[derive(Serialize)]
+struct ProbeReport {
+    peer_id: PeerId,
+    advertised: Versions,
+    /// None if the node accepts none of the versions probed
+    accepted: Option<Versions>,
+    probes: Vec<Probe>,
+}
+
+/// Performs the handshakes with the node, each over a new connection
+struct Prober<'a> {
+    addr: SocketAddr,
+    peer_id: PeerId,
+    genesis_id: GenesisId,
+    args: &'a ConnectionArgs,
+    probes: Mutex<Vec<Probe>>,
+}
+
+impl Prober<'_> {
+    async fn handshake(
+        &self,
+        protocol_version: ProtocolVersion,
+        oldest_supported_version: ProtocolVersion,
+    ) -> Result<Handshake, Error> {
+        let recorder = self
+            .args
+            .record
+            .as_ref()
+            .map(|path| Recorder::open(path, format!("{}@{}", self.peer_id, self.addr)))
+            .transpose()?;
+
+        let result = async {
+            let mut connection = TcpConnection::open(
+                self.addr,
+                self.peer_id.clone(),
+                DEFAULT_LISTEN_PORT,
+                self.args.connect_options(recorder),
+            )
+            .await?;
+
+            connection
+                .probe_handshake(
+                    protocol_version,
+                    oldest_supported_version,
+                    self.genesis_id.clone(),
+                    self.args.head_height,
+                )
+                .await
+        }
+        .await;
+
+        self.probes.lock().unwrap().push(Probe {
+            protocol_version,
+            oldest_supported_version,
+            result: match &result {
+                Ok(_) => "ok".into(),
+                Err(e) => e.to_string(),
+            },
+            response_version: result
+                .as_ref()
+                .ok()
+                .map(|response| response.0.protocol_version),
+        });
+
+        Ok(result?.0)
+    }
+
+    /// Versions the node advertises: the ones it reports when rejecting the handshake, or
+    /// the ones of its response to the handshake advertising all the versions probed
+    async fn advertised(
+        &self,
+        min: ProtocolVersion,
+        max: ProtocolVersion,
+    ) -> Result<Versions, Error> {
+        match self.handshake(max, min).await {
+            Ok(response) => Ok(Versions {
+                oldest: response.oldest_supported_version,
+                latest: response.protocol_version,
+            }),
+            Err(Error::Network(NetworkError::HandshakeFailure(
+                HandshakeFailure::ProtocolVersionMismatch {
+                    version,
+                    oldest_supported_version,
+                },
+            ))) => Ok(Versions {
+                oldest: oldest_supported_version,
+                latest: version,
+            }),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Whether the node accepts the handshake advertising the single version: it responds
+    /// with its handshake (of any version), or rejects it with ProtocolVersionMismatch
+    async fn accepts(&self, version: ProtocolVersion) -> Result<bool, Error> {
+        match self.handshake(version, version).await {
+            Ok(_) => Ok(true),
+            Err(Error::Network(NetworkError::HandshakeFailure(
+                HandshakeFailure::ProtocolVersionMismatch { .. },
+            ))) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Binary-searches the range of the versions accepted within min..=max, starting from the
+/// version known to be accepted (the accepted versions are assumed to be contiguous)
+async fn search_range<F, Fut>(
+    known: ProtocolVersion,
+    min: ProtocolVersion,
+    max: ProtocolVersion,
+    mut accepts: F,
+) -> Result<Versions, Error>
+where
+    F: FnMut(ProtocolVersion) -> Fut,
+    Fut: Future<Output = Result<bool, Error>>,
+{
+    let (mut lo, mut hi) = (min, known);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        match accepts(mid).await? {
+            true => hi = mid,
+            false => lo = mid + 1,
+        }
+    }
+    let oldest = lo;
+
+    let (mut lo, mut hi) = (known, max);
+    while lo < hi {
+        let mid = hi - (hi - lo) / 2;
+        match accepts(mid).await? {
+            true => lo = mid,
+            false => hi = mid - 1,
+        }
+    }
+
+    Ok(Versions { oldest, latest: lo })
+}
+
+fn format_versions(versions: Option<Versions>) -> String {
+    match versions {
+        Some(versions) => format!("{}..={}", versions.oldest, versions.latest),
+        None => "none".into(),
+    }
+}
+
+fn print_report(report: &ProbeReport) {
+    let rows: Vec<[String; 3]> = report
+        .probes
+        .iter()
+        .map(|probe| {
+            [
+                format!(
+                    "{}..={}",
+                    probe.oldest_supported_version, probe.protocol_version
+                ),
+                probe.result.clone(),
+                probe
+                    .response_version
+                    .map_or_else(|| "-".into(), |version| version.to_string()),
+            ]
+        })
+        .collect();
+    super::print_table(["VERSIONS", "RESULT", "RESPONSE"], &rows);
+
+    println!();
+    println!("Node: {}", report.peer_id);
+    println!(
+        "Advertised versions: {}",
+        format_versions(Some(report.advertised))
+    );
+    println!(
+        "Accepted versions:   {}{}",
+        format_versions(report.accepted),
+        match report.accepted == Some(report.advertised) {
+            true => " (as advertised)",
+            false => " (differs from the advertised range)",
+        }
+    );
+}
+
+/// Performs the handshakes with the node advertising different protocol versions (each over
+/// a new connection) and prints the range of the versions the node accepts, compared with
+/// the range it advertises
+pub async fn run(args: ProbeVersionsArgs) -> Result<(), Error> {
+    // The genesis (and the peer id) of the node is learned by the regular handshake
+    let (_, handshake) = super::connect_target(&args.target, &args.connection).await?;
+
+    let target: Target = args.target.parse()?;
+    let prober = Prober {
+        addr: target.resolve().await?,
+        peer_id: handshake.sender_peer_id,
+        genesis_id: handshake.sender_chain_info.genesis_id,
+        args: &args.connection,
+        probes: Mutex::new(Vec::new()),
+    };
+
+    let min = args.min_version.max(1);
+    let max = args.max_version.unwrap_or(PROTOCOL_VERSION + 100).max(min);
+
+    let advertised = prober.advertised(min, max).await?;
+
+    // The search starts from any version accepted, the advertised ones are tried first
+    let mut known = None;
+    for version in [advertised.latest, advertised.oldest, PROTOCOL_VERSION] {
+        if (min..=max).contains(&version) && prober.accepts(version).await? {
+            known = Some(version);
+            break;
+        }
+    }
+
+    let accepted = match known {
+        Some(known) => {
+            Some(search_range(known, min, max, |version| prober.accepts(version)).await?)
+        }
+        None => None,
+    };
+
+    let report = ProbeReport {
+        peer_id: prober.peer_id.clone(),
+        advertised,
+        accepted,
+        probes: prober.probes.into_inner().unwrap(),
+    };
+
+    match args.format {
+        OutputFormat::Table => print_report(&report),
+        OutputFormat::Json => println!("{}", serde_json::to_string(&report).unwrap()),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+
+    use tokio::{net::TcpListener, time::Duration};
+
+    use near_crypto::{KeyType, SecretKey};
+
+    use near_primitives::hash::CryptoHash;
+
+    use crate::network_protocol::{HandshakeResponse, PartialEdgeInfo};
+
+    use super::*;
+
+    /// Serves the handshakes like a node supporting the versions oldest..=latest: rejects the
+    /// handshake of a version outside of the range, and responds to the rest with its latest
+    /// version
+    async fn mock_node(oldest: ProtocolVersion, latest: ProtocolVersion) -> (SocketAddr, PeerId) {
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let peer_id = PeerId::new(secret_key.public_key());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let node_peer_id = peer_id.clone();
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let mut connection =
+                    TcpConnection::raw(stream, DEFAULT_LISTEN_PORT, Duration::from_secs(1), None);
+                let message = connection.read_message().await.unwrap();
+                let HandshakeResponse(request) = (&message).try_into().unwrap();
+
+                let response = match (oldest..=latest).contains(&request.protocol_version) {
+                    true => (&Handshake {
+                        protocol_version: latest,
+                        oldest_supported_version: oldest,
+                        sender_peer_id: node_peer_id.clone(),
+                        target_peer_id: request.sender_peer_id.clone(),
+                        sender_listen_port: None,
+                        sender_chain_info: request.sender_chain_info.clone(),
+                        partial_edge_info: PartialEdgeInfo::new(
+                            &node_peer_id,
+                            &request.sender_peer_id,
+                            request.partial_edge_info.nonce,
+                            &secret_key,
+                        ),
+                    })
+                        .into(),
+                    false => (&HandshakeFailure::ProtocolVersionMismatch {
+                        version: latest,
+                        oldest_supported_version: oldest,
+                    })
+                        .into(),
+                };
+                connection.write_message(response).await.unwrap();
+            }
+        });
+
+        (addr, peer_id)
+    }
+
+    #[tokio::test]
+    async fn test_prober() {
+        let (addr, peer_id) = mock_node(50, 60).await;
+        let args = ConnectionArgs::parse(&[]);
+        let prober = Prober {
+            addr,
+            peer_id,
+            genesis_id: GenesisId {
+                chain_id: "localnet".into(),
+                hash: CryptoHash::hash_bytes(b"genesis"),
+            },
+            args: &args,
+            probes: Mutex::new(Vec::new()),
+        };
+
+        // The node rejecting the handshake reports the versions it supports
+        let versions = |oldest, latest| Versions { oldest, latest };
+        assert_eq!(prober.advertised(1, 100).await.unwrap(), versions(50, 60));
+
+        // The response of a version other than the one advertised is an acceptance too
+        assert!(prober.accepts(55).await.unwrap());
+        assert!(!prober.accepts(61).await.unwrap());
+        assert!(!prober.accepts(49).await.unwrap());
+
+        let accepted = search_range(55, 1, 100, |version| prober.accepts(version)).await;
+        assert_eq!(accepted.unwrap(), versions(50, 60));
+
+        let probes = prober.probes.into_inner().unwrap();
+        assert_eq!(probes[0].response_version, None);
+        assert_eq!(probes[1].result, "ok");
+        assert_eq!(probes[1].response_version, Some(60));
+        assert!(probes[2].result.contains("protocol version mismatch"));
+    }
+
+    #[tokio::test]
+    async fn test_search_range() {
+        let probes = Cell::new(0);
+        let node = |oldest, latest| {
+            let probes = &probes;
+            move |version| {
+                probes.set(probes.get() + 1);
+                async move { Ok((oldest..=latest).contains(&version)) }
+            }
+        };
+
+        let versions = |oldest, latest| Versions { oldest, latest };
+
+        assert_eq!(
+            search_range(60, 1, 160, node(55, 63)).await.unwrap(),
+            versions(55, 63)
+        );
+        assert!(probes.get() <= 16);
+
+        // The range at the bounds
+        assert_eq!(
+            search_range(60, 1, 160, node(1, 160)).await.unwrap(),
+            versions(1, 160)
+        );
+        assert_eq!(
+            search_range(60, 60, 60, node(1, 160)).await.unwrap(),
+            versions(60, 60)
+        );
+
+        // A single version accepted
+        assert_eq!(
+            search_range(57, 1, 160, node(57, 57)).await.unwrap(),
+            versions(57, 57)
+        );
+
+        // The errors are propagated
+        let result = search_range(60, 1, 160, |_| async {
+            Err::<bool, _>(Error::Config("failed".into()))
+        })
+        .await;
+        assert!(result.is_err());
+    }
+}
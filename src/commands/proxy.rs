@@ -0,0 +1,465 @@
+use std::{io, net::SocketAddr, sync::Arc};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite},
+    net::{TcpListener, TcpStream},
+    sync::mpsc,
+    time::{self, Instant},
+};
+
+use bytes::BytesMut;
+
+use tokio_util::codec::Encoder;
+
+use protobuf::Message;
+
+use near_crypto::{KeyType, SecretKey};
+
+use near_primitives::network::PeerId;
+
+use crate::{
+    connection::{
+        ConnectionReader, ConnectionWriter, Fault, FaultInjector, FaultRule, FaultTarget,
+        PeerMessageCodec, TcpConnection,
+    },
+    error::Error,
+    network_protocol::{message_to_json, message_type_name, NetworkError, PeerMessage},
+    target::Target,
+    transcript::Recorder,
+    DEFAULT_LISTEN_PORT,
+};
+
+use super::ConnectionArgs;
+
+#[derive(clap::Args)]
+pub struct ProxyArgs {
+    /// Node to forward the connections to - "peer_id@host:port" or "host:port" (if the peer
+    /// id is not specified, it's read from node_key.json file of the home directory, it's
+    /// not needed with "relay_handshakes")
+    #[clap(verbatim_doc_comment)]
+    target: String,
+
+    /// Address to listen for the connections of the nodes on
+    #[clap(short = 'l', long, default_value = "127.0.0.1:24568")]
+    listen: SocketAddr,
+
+    /// Relay the handshakes between the nodes instead of performing them with the identity
+    /// of the proxy (the nodes see each other, so the connecting node has to use the peer
+    /// id of the target)
+    #[clap(long, verbatim_doc_comment)]
+    relay_handshakes: bool,
+
+    /// Print only the messages of the given types (comma-separated, e.g.
+    /// "block_response,peers_response") [default: all]
+    #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+    filter: Vec<String>,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+fn recorder(args: &ProxyArgs, peer: String) -> Result<Option<Recorder>, Error> {
+    args.connection
+        .record
+        .as_ref()
+        .map(|path| Recorder::open(path, peer))
+        .transpose()
+}
+
+/// Establishes the connections with the connecting node and the target (performing the
+/// handshakes on both sides, or none of them if the peer id of the target is not given, as
+/// the handshakes are relayed)
+async fn open(
+    stream: TcpStream,
+    client_addr: SocketAddr,
+    secret_key: SecretKey,
+    peer_id: Option<PeerId>,
+    args: &ProxyArgs,
+) -> Result<(TcpConnection, TcpConnection), Error> {
+    let target: Target = args.target.parse()?;
+    let addr = target.resolve().await?;
+
+    let timeout = args.connection.timeout();
+
+    let peer_id = match peer_id {
+        Some(peer_id) => peer_id,
+        None => {
+            let client = TcpConnection::raw(
+                stream,
+                DEFAULT_LISTEN_PORT,
+                timeout,
+                recorder(args, client_addr.to_string())?,
+            );
+
+            let stream = time::timeout(timeout, TcpStream::connect(addr))
+                .await
+                .map_err(|_| NetworkError::ConnectTimeout)?
+                .map_err(NetworkError::connect)?;
+            let node = TcpConnection::raw(
+                stream,
+                DEFAULT_LISTEN_PORT,
+                timeout,
+                recorder(args, target.to_string())?,
+            );
+
+            return Ok((client, node));
+        }
+    };
+
+    let (client, handshake) = TcpConnection::accept(
+        stream,
+        secret_key,
+        DEFAULT_LISTEN_PORT,
+        timeout,
+        args.connection.head_height,
+        recorder(args, client_addr.to_string())?,
+    )
+    .await?;
+
+    println!(
+        "{} connected as {} (protocol version {}, height {})",
+        client_addr,
+        handshake.sender_peer_id,
+        handshake.protocol_version,
+        handshake.sender_chain_info.height
+    );
+
+    // The node is expected to be on the same chain as the connecting one
+    let (node, handshake) = super::connect(
+        addr,
+        peer_id,
+        Some(handshake.sender_chain_info.genesis_id),
+        &args.connection,
+        None,
+        None,
+    )
+    .await?;
+
+    println!(
+        "{}: connected to {}@{} (protocol version {}, height {})",
+        client_addr,
+        handshake.sender_peer_id,
+        addr,
+        handshake.protocol_version,
+        handshake.sender_chain_info.height
+    );
+
+    Ok((client, node))
+}
+
+fn print_message(args: &ProxyArgs, from: &str, to: &str, msg: &PeerMessage, faults: &[Fault]) {
+    let msg_type = message_type_name(msg);
+    if args.filter.is_empty() || args.filter.iter().any(|t| t == msg_type) {
+        println!(
+            "{} -> {} {} ({} bytes) {}",
+            from,
+            to,
+            msg_type,
+            msg.compute_size(),
+            message_to_json(msg)
+        );
+        if !faults.is_empty() {
+            println!("  injected: {:?}", faults);
+        }
+    }
+}
+
+/// Maximum number of the frames read from one side waiting for the delivery to the other
+/// one (reading from the side is paused while the queue is full)
+const DELIVERY_QUEUE_LEN: usize = 64;
+
+/// Reads the messages from one side of the proxy session and queues them for the delivery to
+/// the other side (injecting the faults), until the side closes the connection
+async fn receive<R: AsyncRead + Unpin>(
+    reader: &mut ConnectionReader<R>,
+    (from, to): (&str, &str),
+    mut faults: FaultInjector,
+    deliveries: mpsc::Sender<(Instant, Vec<u8>)>,
+    args: &ProxyArgs,
+) -> Result<String, Error> {
+    let mut deliver_at = Instant::now();
+    loop {
+        let msg = match reader.read_message().await {
+            Ok(msg) => msg,
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(from.into()),
+            Err(e) => Err(NetworkError::IO(e))?,
+        };
+
+        let mut frame = BytesMut::new();
+        PeerMessageCodec.encode(&msg, &mut frame).unwrap();
+        let delivery = faults.apply(message_type_name(&msg), frame.to_vec());
+        print_message(args, from, to, &msg, &delivery.faults);
+
+        // The frames are delivered in order, so a delayed frame delays the following ones too
+        deliver_at = deliver_at.max(Instant::now() + delivery.delay);
+        for frame in delivery.frames {
+            if deliveries.send((deliver_at, frame)).await.is_err() {
+                return Ok(from.into());
+            }
+        }
+    }
+}
+
+/// Writes the queued frames to one side of the proxy session, each at the time of its
+/// delivery
+async fn deliver<W: AsyncWrite + Unpin>(
+    writer: &mut ConnectionWriter<W>,
+    mut deliveries: mpsc::Receiver<(Instant, Vec<u8>)>,
+) -> Result<(), Error> {
+    while let Some((deliver_at, frame)) = deliveries.recv().await {
+        time::sleep_until(deliver_at).await;
+        writer.write_frame(&frame).await.map_err(NetworkError::IO)?;
+    }
+    Ok(())
+}
+
+/// Fault injector of the frames forwarded to the side
+fn faults(target: FaultTarget, rules: &[FaultRule], seed: Option<u64>) -> FaultInjector {
+    let rules = rules
+        .iter()
+        .filter(|rule| rule.to.is_none_or(|to| to == target))
+        .cloned()
+        .collect();
+    FaultInjector::new(rules, seed)
+}
+
+/// Forwards the messages between the connecting node and the target (injecting the faults),
+/// until one of them closes the connection. Each direction is forwarded independently, so a
+/// side slow to receive doesn't hold back the other direction.
+async fn forward(
+    client_addr: SocketAddr,
+    client: TcpConnection,
+    node: TcpConnection,
+    args: &ProxyArgs,
+    rules: &[FaultRule],
+) -> Result<(), Error> {
+    let seed = args.connection.fault_seed;
+    let (client_name, node_name) = (client_addr.to_string(), args.target.clone());
+
+    let (mut client_reader, mut client_writer) = client.into_split();
+    let (mut node_reader, mut node_writer) = node.into_split();
+    let (to_client, client_deliveries) = mpsc::channel(DELIVERY_QUEUE_LEN);
+    let (to_node, node_deliveries) = mpsc::channel(DELIVERY_QUEUE_LEN);
+
+    let closed_by = tokio::select! {
+        result = receive(
+            &mut client_reader,
+            (&client_name, &node_name),
+            faults(FaultTarget::Target, rules, seed),
+            to_node,
+            args,
+        ) => result?,
+        result = receive(
+            &mut node_reader,
+            (&node_name, &client_name),
+            faults(FaultTarget::Client, rules, seed),
+            to_client,
+            args,
+        ) => result?,
+        result = deliver(&mut client_writer, client_deliveries) => return result,
+        result = deliver(&mut node_writer, node_deliveries) => return result,
+    };
+
+    println!("{}: connection closed by {}", client_addr, closed_by);
+    Ok(())
+}
+
+/// Proxies the connection of the node: connects to the target and forwards the messages
+async fn proxy(
+    stream: TcpStream,
+    client_addr: SocketAddr,
+    secret_key: SecretKey,
+    peer_id: Option<PeerId>,
+    args: &ProxyArgs,
+    rules: &[FaultRule],
+) -> Result<(), Error> {
+    let (client, node) = open(stream, client_addr, secret_key, peer_id, args).await?;
+    forward(client_addr, client, node, args, rules).await
+}
+
+/// Accepts the connections of the nodes, connects to the target for each of them and forwards
+/// the messages in both directions, printing them
+pub async fn run(mut args: ProxyArgs) -> Result<(), Error> {
+    // The faults are injected into the frames forwarded, not into the frames received by the
+    // proxy (which would fail to parse the corrupted ones)
+    let rules = Arc::new(std::mem::take(&mut args.connection.fault));
+
+    let listener = TcpListener::bind(args.listen)
+        .await
+        .map_err(|e| Error::Config(format!("Error listening on {}: {}", args.listen, e)))?;
+
+    // The same identity for all the connecting nodes, so it can be configured in them
+    let secret_key = SecretKey::from_random(KeyType::ED25519);
+
+    let peer_id = if args.relay_handshakes {
+        None
+    } else {
+        let target: Target = args.target.parse()?;
+        match target.peer_id {
+            Some(peer_id) => Some(peer_id),
+            None => Some(args.connection.near_home()?.peer_id()?),
+        }
+    };
+
+    if args.relay_handshakes {
+        println!("Listening on {} (relaying the handshakes)", args.listen);
+    } else {
+        println!(
+            "Listening on {} as {}",
+            args.listen,
+            PeerId::new(secret_key.public_key())
+        );
+    }
+
+    let args = Arc::new(args);
+
+    loop {
+        let (stream, client_addr) = listener.accept().await.map_err(NetworkError::IO)?;
+
+        let secret_key = secret_key.clone();
+        let peer_id = peer_id.clone();
+        let args = args.clone();
+        let rules = rules.clone();
+
+        tokio::spawn(async move {
+            let result = proxy(stream, client_addr, secret_key, peer_id, &args, &rules).await;
+            if let Err(e) = result {
+                eprintln!("{}: {}", client_addr, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use clap::{Args, Command, FromArgMatches};
+
+    use tokio::time::Duration;
+
+    use near_primitives::{block::GenesisId, hash::CryptoHash};
+
+    use crate::{
+        connection::ConnectOptions,
+        network_protocol::{BlockRequest, PeersRequest},
+    };
+
+    use super::*;
+
+    fn proxy_args(args: &[&str]) -> ProxyArgs {
+        let command = ProxyArgs::augment_args(Command::new("test"));
+        let matches = command.get_matches_from(std::iter::once(&"test").chain(args));
+        ProxyArgs::from_arg_matches(&matches).unwrap()
+    }
+
+    /// Node accepting a single connection and echoing the messages back
+    async fn mock_node() -> (SocketAddr, PeerId) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let peer_id = PeerId::new(secret_key.public_key());
+
+        tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let (mut connection, _) = TcpConnection::accept(
+                stream,
+                secret_key,
+                DEFAULT_LISTEN_PORT,
+                Duration::from_secs(1),
+                10,
+                None,
+            )
+            .await
+            .unwrap();
+            while let Ok(msg) = connection.read_message().await {
+                connection.write_message(msg).await.unwrap();
+            }
+        });
+
+        (addr, peer_id)
+    }
+
+    /// Connects the client to the node through the proxy, sends the messages and returns the
+    /// messages echoed back by the node (until the proxy stops forwarding them)
+    async fn proxy_session(relay_handshakes: bool, fault: Option<&str>) -> Vec<PeerMessage> {
+        let (node_addr, node_peer_id) = mock_node().await;
+
+        let target = format!("{}@{}", node_peer_id, node_addr);
+        let mut args = vec![target.as_str(), "--connection-timeout", "1"];
+        if relay_handshakes {
+            args.push("--relay-handshakes");
+        }
+        if let Some(fault) = fault {
+            args.extend(["--fault", fault]);
+        }
+        let mut args = proxy_args(&args);
+        let rules = std::mem::take(&mut args.connection.fault);
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let proxy_addr = listener.local_addr().unwrap();
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        // The client connects to the proxy, or to the node if the handshakes are relayed
+        let client_target = match relay_handshakes {
+            true => node_peer_id.clone(),
+            false => PeerId::new(secret_key.public_key()),
+        };
+        let peer_id = (!relay_handshakes).then_some(node_peer_id);
+
+        let proxy = tokio::spawn(async move {
+            let (stream, client_addr) = listener.accept().await.unwrap();
+            proxy(stream, client_addr, secret_key, peer_id, &args, &rules).await
+        });
+
+        let genesis_id = GenesisId {
+            chain_id: "localnet".into(),
+            hash: CryptoHash::hash_bytes(b"genesis"),
+        };
+        let (mut client, response) = TcpConnection::connect(
+            proxy_addr,
+            client_target.clone(),
+            DEFAULT_LISTEN_PORT,
+            Some(genesis_id),
+            0,
+            ConnectOptions::new(Duration::from_secs(1)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.sender_peer_id, client_target);
+
+        client
+            .write_message(PeerMessage::from(&PeersRequest))
+            .await
+            .unwrap();
+        client
+            .write_message(PeerMessage::from(&BlockRequest(CryptoHash::default())))
+            .await
+            .unwrap();
+
+        let mut received = Vec::new();
+        while let Ok(Ok(msg)) =
+            time::timeout(Duration::from_millis(300), client.read_message()).await
+        {
+            received.push(msg);
+        }
+
+        drop(client);
+        proxy.await.unwrap().unwrap();
+        received
+    }
+
+    #[tokio::test]
+    async fn test_proxy() {
+        let sent = vec![
+            PeerMessage::from(&PeersRequest),
+            PeerMessage::from(&BlockRequest(CryptoHash::default())),
+        ];
+
+        assert_eq!(proxy_session(false, None).await, sent);
+        assert_eq!(proxy_session(true, None).await, sent);
+
+        // The fault is injected into the frames forwarded to the node only
+        let received = proxy_session(false, Some("drop,type=peers_request,to=target")).await;
+        assert_eq!(received, sent[1..]);
+        let received = proxy_session(true, Some("duplicate,type=block_request,to=client")).await;
+        assert_eq!(received, [&sent[..], &sent[1..]].concat());
+    }
+}
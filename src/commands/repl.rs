@@ -0,0 +1,325 @@
+use std::{
+    future::Future,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWrite, BufReader},
+    time::Duration,
+};
+
+use protobuf::Message;
+
+use serde_json::{json, Value};
+
+use near_crypto::PublicKey;
+
+use near_primitives::{
+    hash::CryptoHash, network::PeerId, syncing::EpochSyncResponse, types::EpochId,
+    views::BlockHeaderView,
+};
+
+use crate::{
+    client::Client,
+    error::Error,
+    network_protocol::{
+        block_to_json, message_from_json, message_to_json, message_type_name, Disconnect,
+        NetworkError, PeerIdOrHash, PeerMessage, Ping, RoutedMessageBody,
+    },
+};
+
+use super::ConnectionArgs;
+
+const HELP: &str = "\
+Commands:
+  peers                      request the peers known to the node
+  headers <hash> [<hash>..]  request the headers of the blocks following the first known hash
+  block <hash>               request the block
+  epoch_sync <epoch_id>      request the epoch sync data following the epoch
+  ping <peer_id>             send a ping routed to the peer
+  raw <json>                 send the message given as JSON (see \"send\" command)
+  filter [<type>..]          print only the received messages of the given types
+                             (e.g. \"block_response\"), all messages if no types given (the
+                             responses to the requests are always printed)
+  disconnect                 send Disconnect and exit (the same as end of input)
+  help                       print this help";
+
+#[derive(clap::Args)]
+pub struct ReplArgs {
+    /// Node to connect to - "peer_id@host:port" or "host:port" (if the peer id is not
+    /// specified, it's read from node_key.json file of the home directory)
+    #[clap(verbatim_doc_comment)]
+    target: String,
+
+    /// Print only the received messages of the given types (comma-separated, e.g.
+    /// "block_response,peers_response") [default: all]
+    #[clap(long, value_delimiter = ',', verbatim_doc_comment)]
+    filter: Vec<String>,
+
+    /// Time to wait for the response to each request (in seconds)
+    #[clap(long, default_value = "5")]
+    request_timeout: u64,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+#[derive(Debug)]
+#[cfg_attr(test, derive(PartialEq))]
+enum Command {
+    Peers,
+    Headers(Vec<CryptoHash>),
+    Block(CryptoHash),
+    EpochSync(EpochId),
+    Ping(PeerId),
+    Raw(PeerMessage),
+    Filter(Vec<String>),
+    Disconnect,
+    Help,
+}
+
+fn parse_hash(hash: &str) -> Result<CryptoHash, String> {
+    CryptoHash::from_str(hash).map_err(|_| format!("invalid hash: {}", hash))
+}
+
+/// Parses the command line of the REPL (None for an empty line)
+fn parse_command(line: &str) -> Result<Option<Command>, String> {
+    let line = line.trim();
+    let (name, rest) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+    let args: Vec<&str> = rest.split_whitespace().collect();
+
+    let command = match (name, args.as_slice()) {
+        ("", _) => return Ok(None),
+        ("peers", []) => Command::Peers,
+        ("headers", hashes) if !hashes.is_empty() => Command::Headers(
+            hashes
+                .iter()
+                .map(|hash| parse_hash(hash))
+                .collect::<Result<_, _>>()?,
+        ),
+        ("block", [hash]) => Command::Block(parse_hash(hash)?),
+        ("epoch_sync", [epoch_id]) => Command::EpochSync(EpochId(parse_hash(epoch_id)?)),
+        ("ping", [peer_id]) => Command::Ping(PeerId::new(
+            PublicKey::from_str(peer_id).map_err(|_| format!("invalid peer id: {}", peer_id))?,
+        )),
+        ("raw", _) if !rest.trim().is_empty() => {
+            let value = serde_json::from_str(rest).map_err(|e| format!("invalid JSON: {}", e))?;
+            Command::Raw(message_from_json(&value)?)
+        }
+        ("filter", types) => Command::Filter(types.iter().map(|t| t.to_string()).collect()),
+        ("disconnect", []) => Command::Disconnect,
+        ("help", []) => Command::Help,
+        _ => return Err(format!("invalid command: {} (type \"help\")", line)),
+    };
+
+    Ok(Some(command))
+}
+
+fn print_message(msg: &PeerMessage) {
+    println!(
+        "<< {} ({} bytes) {}",
+        message_type_name(msg),
+        msg.compute_size(),
+        message_to_json(msg)
+    );
+}
+
+async fn send<W: AsyncWrite + Unpin>(client: &Client<W>, msg: PeerMessage) -> Result<(), Error> {
+    println!(">> {}", message_type_name(&msg));
+    Ok(client.send(msg).await?)
+}
+
+/// Sends the request in the background, the response is printed when received
+fn spawn_request<T, F>(
+    name: &'static str,
+    response_name: &'static str,
+    request: F,
+    to_json: fn(T) -> Value,
+) where
+    T: 'static,
+    F: Future<Output = Result<T, NetworkError>> + Send + 'static,
+{
+    println!(">> {}", name);
+    tokio::spawn(async move {
+        match request.await {
+            Ok(response) => println!("<< {} {}", response_name, to_json(response)),
+            Err(e) => eprintln!("{} failed: {}", name, e),
+        }
+    });
+}
+
+/// Performs the handshake with the node and runs the interactive session: sends the requests
+/// given with the commands read from stdin, printing the responses, and prints the other
+/// messages received from the node
+pub async fn run(args: ReplArgs) -> Result<(), Error> {
+    let (connection, handshake) = super::connect_target(&args.target, &args.connection).await?;
+
+    println!(
+        "Connected to {} (protocol version {}, height {}), type \"help\" for the commands",
+        handshake.sender_peer_id, handshake.protocol_version, handshake.sender_chain_info.height
+    );
+
+    // The peers reported by the node are recorded to the peer store
+    let peer_store = args.connection.peer_store()?.map(Mutex::new).map(Arc::new);
+
+    let (client, mut broadcasts) =
+        Client::new(connection, Duration::from_secs(args.request_timeout));
+
+    let mut filter = args.filter;
+    let mut ping_nonce = 0;
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+
+    loop {
+        tokio::select! {
+            line = lines.next_line() => {
+                let line = line.map_err(|e| Error::Config(format!("Error reading stdin: {}", e)))?;
+
+                let command = match line.as_deref().map(parse_command) {
+                    None => Command::Disconnect,
+                    Some(Ok(Some(command))) => command,
+                    Some(Ok(None)) => continue,
+                    Some(Err(e)) => {
+                        eprintln!("{}", e);
+                        continue;
+                    }
+                };
+
+                let requests = client.clone();
+                match command {
+                    Command::Peers => spawn_request(
+                        "peers_request",
+                        "peers_response",
+                        {
+                            let peer_store = peer_store.clone();
+                            async move {
+                                let peers = requests.get_peers().await?;
+                                if let Some(peer_store) = peer_store {
+                                    peer_store.lock().unwrap().record_peers(&peers);
+                                }
+                                Ok(peers)
+                            }
+                        },
+                        |peers| json!(peers),
+                    ),
+                    Command::Headers(hashes) => spawn_request(
+                        "block_headers_request",
+                        "block_headers_response",
+                        async move { requests.get_block_headers(hashes).await },
+                        |headers| {
+                            json!(headers.into_iter().map(BlockHeaderView::from).collect::<Vec<_>>())
+                        },
+                    ),
+                    Command::Block(hash) => spawn_request(
+                        "block_request",
+                        "block_response",
+                        async move { requests.get_block(hash).await },
+                        |block| block_to_json(&block),
+                    ),
+                    Command::EpochSync(epoch_id) => spawn_request(
+                        "epoch_sync_request",
+                        "epoch_sync_response",
+                        async move { requests.get_epoch_sync(epoch_id).await },
+                        |response| match response {
+                            EpochSyncResponse::UpToDate => json!("up_to_date"),
+                            EpochSyncResponse::Advance { light_client_block_view } => {
+                                json!({ "advance": light_client_block_view })
+                            }
+                        },
+                    ),
+                    Command::Ping(peer_id) => {
+                        ping_nonce += 1;
+                        let session = client.session();
+                        let ping = session.create_routed_message(
+                            PeerIdOrHash::PeerId(peer_id),
+                            RoutedMessageBody::Ping(Ping {
+                                nonce: ping_nonce,
+                                source: session.my_peer_id().clone(),
+                            }),
+                        );
+                        send(&client, (&ping).into()).await?
+                    }
+                    Command::Raw(msg) => send(&client, msg).await?,
+                    Command::Filter(types) => filter = types,
+                    Command::Disconnect => {
+                        send(&client, (&Disconnect).into()).await?;
+                        break;
+                    }
+                    Command::Help => println!("{}", HELP),
+                }
+            }
+
+            msg = broadcasts.recv() => match msg {
+                Some(Ok(msg)) => {
+                    if filter.is_empty() || filter.iter().any(|t| t == message_type_name(&msg)) {
+                        print_message(&msg);
+                    }
+                }
+                Some(Err(e)) => Err(NetworkError::IO(e))?,
+                None => {
+                    println!("connection closed by {}", client.session().peer_id());
+                    break;
+                }
+            }
+        }
+    }
+
+    if let Some(peer_store) = peer_store {
+        peer_store.lock().unwrap().save()?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_command() {
+        let hash = CryptoHash::hash_bytes(b"block");
+
+        assert_eq!(parse_command("  ").unwrap(), None);
+        assert_eq!(parse_command("peers").unwrap(), Some(Command::Peers));
+        assert_eq!(
+            parse_command(&format!("headers {} {}", hash, hash)).unwrap(),
+            Some(Command::Headers(vec![hash, hash]))
+        );
+        assert_eq!(
+            parse_command(&format!(" block  {} ", hash)).unwrap(),
+            Some(Command::Block(hash))
+        );
+        assert_eq!(
+            parse_command(&format!("epoch_sync {}", hash)).unwrap(),
+            Some(Command::EpochSync(EpochId(hash)))
+        );
+        assert!(matches!(
+            parse_command("ping ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX"),
+            Ok(Some(Command::Ping(_)))
+        ));
+        assert_eq!(
+            parse_command(r#"raw {"peers_request": {}}"#).unwrap(),
+            Some(Command::Raw(
+                (&crate::network_protocol::PeersRequest).into()
+            ))
+        );
+        assert_eq!(
+            parse_command("filter block_response peers_response").unwrap(),
+            Some(Command::Filter(vec![
+                "block_response".into(),
+                "peers_response".into()
+            ]))
+        );
+        assert_eq!(
+            parse_command("filter").unwrap(),
+            Some(Command::Filter(vec![]))
+        );
+
+        assert!(parse_command("headers").is_err());
+        assert!(parse_command("block bad_hash").is_err());
+        assert!(parse_command("ping bad_key").is_err());
+        assert!(parse_command("raw {").is_err());
+        assert!(parse_command("peers 1").is_err());
+        assert!(parse_command("unknown").is_err());
+    }
+}
@@ -0,0 +1,322 @@
+use std::{io, net::SocketAddr, path::PathBuf};
+
+use protobuf::Message;
+
+use tokio::{net::TcpListener, time::Duration};
+
+use near_crypto::{KeyType, SecretKey};
+
+use near_primitives::network::PeerId;
+
+use crate::{
+    connection::{Direction, TcpConnection},
+    error::Error,
+    network_protocol::{
+        message_to_json, message_type_name, Handshake, HandshakeResponse, PartialEdgeInfo,
+        PeerMessage,
+    },
+    transcript::{self, Frame},
+    DEFAULT_LISTEN_PORT,
+};
+
+#[derive(clap::Args)]
+pub struct ReplayArgs {
+    /// Transcript file recorded with "--record" option
+    transcript_file: PathBuf,
+
+    /// Act as a fake node listening on the address: the frames received from the node are
+    /// sent to the connected client, the frames sent to the node are awaited from the client.
+    /// The handshake response is re-signed with the key of the fake node (its peer id is
+    /// printed), for the handshake request of the client.
+    #[clap(long, verbatim_doc_comment)]
+    serve: Option<SocketAddr>,
+
+    /// Node of the transcript to replay ("peer_id@host:port") [default: the first node of
+    /// the transcript]
+    #[clap(long, verbatim_doc_comment)]
+    peer: Option<String>,
+
+    /// Time to wait for each frame of the client when serving (in seconds)
+    #[clap(long, default_value = "10")]
+    client_timeout: u64,
+}
+
+/// Decodes the frame data and prints it
+fn print_frame(frame: &Frame) {
+    let direction = match frame.direction {
+        Direction::In => "<-",
+        Direction::Out => "->",
+    };
+
+    match PeerMessage::parse_from_bytes(&frame.data) {
+        Ok(msg) => println!(
+            "{} {} {} {} ({} bytes): {}",
+            frame.timestamp.to_rfc3339(),
+            direction,
+            frame.peer,
+            message_type_name(&msg),
+            frame.data.len(),
+            message_to_json(&msg)
+        ),
+        Err(e) => println!(
+            "{} {} {} undecodable frame ({} bytes): {}",
+            frame.timestamp.to_rfc3339(),
+            direction,
+            frame.peer,
+            frame.data.len(),
+            e
+        ),
+    }
+}
+
+/// The recorded handshake response re-signed with the key of the fake node, as the response
+/// to the handshake request of the client. None if the frame is not a handshake.
+fn resign_handshake(
+    data: &[u8],
+    request: &Handshake,
+    secret_key: &SecretKey,
+) -> Option<PeerMessage> {
+    let msg = PeerMessage::parse_from_bytes(data).ok()?;
+    let HandshakeResponse(mut response) = (&msg).try_into().ok()?;
+
+    response.sender_peer_id = PeerId::new(secret_key.public_key());
+    response.target_peer_id = request.sender_peer_id.clone();
+    // The edge is signed with the nonce proposed by the client
+    response.partial_edge_info = PartialEdgeInfo::new(
+        &response.sender_peer_id,
+        &response.target_peer_id,
+        request.partial_edge_info.nonce,
+        secret_key,
+    );
+
+    Some((&response).into())
+}
+
+/// Replays the frames of a single node to the connected client, as the node with the given
+/// key. Fails if the client doesn't send the frame expected from it in time.
+async fn replay(
+    connection: &mut TcpConnection,
+    frames: &[&Frame],
+    secret_key: &SecretKey,
+) -> io::Result<()> {
+    // The last handshake request of the client
+    let mut request = None;
+
+    for frame in frames {
+        match frame.direction {
+            Direction::In => {
+                match request
+                    .as_ref()
+                    .and_then(|request| resign_handshake(&frame.data, request, secret_key))
+                {
+                    Some(response) => connection.write_message(response).await?,
+                    None => {
+                        let len = (frame.data.len() as u32).to_le_bytes();
+                        connection
+                            .write_frame(&[&len, frame.data.as_slice()].concat())
+                            .await?
+                    }
+                }
+            }
+            Direction::Out => match connection.read_message_with_timeout().await {
+                Ok(msg) => {
+                    if let Ok(HandshakeResponse(handshake)) = (&msg).try_into() {
+                        request = Some(handshake);
+                    }
+                }
+                // The frame the client sent is not required to be parsable
+                Err(e) if e.kind() == io::ErrorKind::InvalidData => {}
+                Err(e) => return Err(e),
+            },
+        }
+        print_frame(frame);
+    }
+
+    Ok(())
+}
+
+/// Decodes and prints the frames of the transcript, or serves them to the clients
+/// connecting to the given address (until interrupted by Ctrl-C)
+pub async fn run(args: ReplayArgs) -> Result<(), Error> {
+    let frames = transcript::read(&args.transcript_file)?;
+
+    let peer = args
+        .peer
+        .or_else(|| frames.first().map(|frame| frame.peer.clone()));
+
+    let frames: Vec<&Frame> = frames
+        .iter()
+        .filter(|frame| peer.as_ref().is_none_or(|peer| &frame.peer == peer))
+        .collect();
+
+    let addr = match args.serve {
+        Some(addr) => addr,
+        None => {
+            frames.into_iter().for_each(print_frame);
+            return Ok(());
+        }
+    };
+
+    let listener = TcpListener::bind(addr)
+        .await
+        .map_err(|e| Error::Config(format!("Error listening on {}: {}", addr, e)))?;
+
+    let secret_key = SecretKey::from_random(KeyType::ED25519);
+    println!(
+        "Serving as {}@{}",
+        PeerId::new(secret_key.public_key()),
+        addr
+    );
+
+    loop {
+        let (stream, client_addr) = tokio::select! {
+            result = listener.accept() => result.map_err(|e| {
+                Error::Config(format!("Error accepting connection: {}", e))
+            })?,
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        };
+
+        println!("Client connected: {}", client_addr);
+
+        let mut connection = TcpConnection::raw(
+            stream,
+            DEFAULT_LISTEN_PORT,
+            Duration::from_secs(args.client_timeout),
+            None,
+        );
+        tokio::select! {
+            result = replay(&mut connection, &frames, &secret_key) => match result {
+                Ok(()) => println!("Transcript replayed to {}", client_addr),
+                Err(e) => println!("Replay to {} stopped: {}", client_addr, e),
+            },
+            _ = tokio::signal::ctrl_c() => return Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use near_primitives::{block::GenesisId, hash::CryptoHash, version::PROTOCOL_VERSION};
+
+    use crate::{
+        connection::ConnectOptions,
+        network_protocol::{Disconnect, PeerChainInfo, PeersRequest},
+    };
+
+    use super::*;
+
+    fn frame(direction: Direction, msg: &PeerMessage) -> Frame {
+        Frame {
+            timestamp: Utc::now(),
+            peer: "node".into(),
+            direction,
+            data: msg.write_to_bytes().unwrap(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay() {
+        let genesis_id = GenesisId {
+            chain_id: "localnet".into(),
+            hash: CryptoHash::hash_bytes(b"genesis"),
+        };
+
+        // The handshake recorded by another client with the node
+        let handshake = |sender: &SecretKey, target: &SecretKey| {
+            let (sender_peer_id, target_peer_id) = (
+                PeerId::new(sender.public_key()),
+                PeerId::new(target.public_key()),
+            );
+            Handshake {
+                protocol_version: PROTOCOL_VERSION,
+                oldest_supported_version: PROTOCOL_VERSION - 2,
+                partial_edge_info: PartialEdgeInfo::new(
+                    &sender_peer_id,
+                    &target_peer_id,
+                    1,
+                    sender,
+                ),
+                sender_peer_id,
+                target_peer_id,
+                sender_listen_port: None,
+                sender_chain_info: PeerChainInfo {
+                    genesis_id: genesis_id.clone(),
+                    height: 10,
+                    ..Default::default()
+                },
+            }
+        };
+        let client_key = SecretKey::from_random(KeyType::ED25519);
+        let node_key = SecretKey::from_random(KeyType::ED25519);
+        let frames = [
+            frame(Direction::Out, &(&handshake(&client_key, &node_key)).into()),
+            frame(Direction::In, &(&handshake(&node_key, &client_key)).into()),
+            frame(Direction::Out, &PeerMessage::from(&PeersRequest)),
+            frame(Direction::In, &PeerMessage::from(&Disconnect)),
+        ];
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let peer_id = PeerId::new(secret_key.public_key());
+
+        let server = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.unwrap();
+            let mut connection =
+                TcpConnection::raw(stream, DEFAULT_LISTEN_PORT, Duration::from_secs(1), None);
+            replay(
+                &mut connection,
+                &frames.iter().collect::<Vec<_>>(),
+                &secret_key,
+            )
+            .await
+        });
+
+        // The client with another key accepts the re-signed response
+        let (mut connection, response) = TcpConnection::connect(
+            addr,
+            peer_id.clone(),
+            DEFAULT_LISTEN_PORT,
+            Some(genesis_id),
+            0,
+            ConnectOptions::new(Duration::from_secs(1)),
+        )
+        .await
+        .unwrap();
+        assert_eq!(response.sender_peer_id, peer_id);
+        assert_eq!(response.sender_chain_info.height, 10);
+
+        connection
+            .write_message(PeerMessage::from(&PeersRequest))
+            .await
+            .unwrap();
+        assert_eq!(
+            connection.read_message().await.unwrap(),
+            PeerMessage::from(&Disconnect)
+        );
+        server.await.unwrap().unwrap();
+
+        // The client which doesn't send anything
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let _client = tokio::net::TcpStream::connect(addr).await.unwrap();
+        let (stream, _) = listener.accept().await.unwrap();
+        let mut connection = TcpConnection::raw(
+            stream,
+            DEFAULT_LISTEN_PORT,
+            Duration::from_millis(100),
+            None,
+        );
+        let frames = [frame(Direction::Out, &PeerMessage::from(&PeersRequest))];
+        let error = replay(
+            &mut connection,
+            &frames.iter().collect::<Vec<_>>(),
+            &SecretKey::from_random(KeyType::ED25519),
+        )
+        .await
+        .unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::TimedOut);
+    }
+}
@@ -0,0 +1,129 @@
+use std::{
+    fs,
+    io::{self, Read},
+    path::PathBuf,
+};
+
+use tokio::time::{self, Duration, Instant};
+
+use protobuf::Message;
+
+use serde_json::Value;
+
+use crate::{
+    error::Error,
+    network_protocol::{
+        message_from_json, message_to_json, message_type_name, NetworkError, PeerMessage,
+    },
+};
+
+use super::ConnectionArgs;
+
+#[derive(clap::Args)]
+pub struct SendArgs {
+    /// Node to send the messages to - "peer_id@host:port" or "host:port" (if the peer id is
+    /// not specified, it's read from node_key.json file of the home directory)
+    #[clap(verbatim_doc_comment)]
+    target: String,
+
+    /// JSON file with the PeerMessage to send, or an array of them (in the format printed
+    /// by "decode" command, or the protobuf JSON mapping) [default: stdin]
+    #[clap(verbatim_doc_comment)]
+    message_file: Option<PathBuf>,
+
+    /// Time to wait for the messages from the node after sending (in seconds)
+    #[clap(short = 'w', long, default_value = "3")]
+    wait: u64,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+}
+
+fn read_messages(args: &SendArgs) -> Result<Vec<PeerMessage>, Error> {
+    let mut data = String::new();
+
+    match &args.message_file {
+        Some(path) => {
+            data = fs::read_to_string(path).map_err(|_| {
+                Error::Config(format!("Error reading message file: {}", path.display()))
+            })?
+        }
+        None => {
+            io::stdin()
+                .read_to_string(&mut data)
+                .map_err(|e| Error::Config(format!("Error reading stdin: {}", e)))?;
+        }
+    }
+
+    let value: Value = serde_json::from_str(&data)
+        .map_err(|e| Error::Config(format!("Error parsing message JSON: {}", e)))?;
+
+    let values = match value {
+        Value::Array(values) => values,
+        value => vec![value],
+    };
+
+    values
+        .iter()
+        .map(|value| {
+            message_from_json(value)
+                .map_err(|e| Error::Config(format!("Error encoding message: {}", e)))
+        })
+        .collect()
+}
+
+fn print_message(msg: &PeerMessage) {
+    println!(
+        "{} ({} bytes)\n{}",
+        message_type_name(msg),
+        msg.compute_size(),
+        serde_json::to_string_pretty(&message_to_json(msg)).unwrap()
+    );
+}
+
+/// Performs the handshake with the node, sends the messages and prints the messages received
+/// from the node until the wait time passes or the node closes the connection
+pub async fn run(args: SendArgs) -> Result<(), Error> {
+    // The messages are encoded first, so the errors are reported before connecting
+    let messages = read_messages(&args)?;
+
+    let (connection, _) = super::connect_target(&args.target, &args.connection).await?;
+    let (mut reader, mut writer) = connection.into_split();
+
+    // The messages received while sending are printed right away (the node may stop reading
+    // until its own messages are read)
+    let sending = async {
+        for msg in messages {
+            writer.write_message(msg).await?;
+        }
+        io::Result::Ok(())
+    };
+    tokio::pin!(sending);
+
+    let wait = Duration::from_secs(args.wait);
+    let mut deadline = None;
+
+    loop {
+        tokio::select! {
+            result = &mut sending, if deadline.is_none() => {
+                result.map_err(NetworkError::IO)?;
+                deadline = Some(Instant::now() + wait);
+            }
+
+            _ = time::sleep_until(deadline.unwrap_or_else(Instant::now)), if deadline.is_some() => {
+                break;
+            }
+
+            msg = reader.read_message() => match msg {
+                Ok(msg) => print_message(&msg),
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => {
+                    println!("connection closed by the node");
+                    break;
+                }
+                Err(e) => Err(NetworkError::IO(e))?,
+            }
+        }
+    }
+
+    Ok(())
+}
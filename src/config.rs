@@ -0,0 +1,206 @@
+use std::{
+    fs, io,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use serde::{de::DeserializeOwned, Deserialize};
+
+use near_crypto::PublicKey;
+
+use near_primitives::{block::GenesisId, hash::CryptoHash, network::PeerId};
+
+use crate::{error::Error, target::Target};
+
+const NODE_KEY_FILE: &str = "node_key.json";
+const CONFIG_FILE: &str = "config.json";
+const GENESIS_FILE: &str = "genesis.json";
+
+#[derive(Deserialize)]
+struct NodeKey {
+    public_key: String,
+}
+
+#[derive(Deserialize)]
+struct Config {
+    network: NetworkConfig,
+}
+
+#[derive(Deserialize)]
+struct NetworkConfig {
+    addr: String,
+    #[serde(default)]
+    boot_nodes: String,
+}
+
+#[derive(Deserialize)]
+struct GenesisChainId {
+    chain_id: String,
+}
+
+/// Network settings of the node read from its config file (config.json)
+pub struct NodeConfig {
+    /// Address the node listens on
+    pub addr: String,
+    pub boot_nodes: Vec<Target>,
+}
+
+impl NodeConfig {
+    /// Address to connect to the node from the same host (the node usually listens on
+    /// the unspecified address, like 0.0.0.0)
+    pub fn local_addr(&self) -> String {
+        match SocketAddr::from_str(&self.addr) {
+            Ok(addr) if addr.ip().is_unspecified() => {
+                let ip = match addr.ip() {
+                    IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                    IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                };
+                SocketAddr::new(ip, addr.port()).to_string()
+            }
+            _ => self.addr.clone(),
+        }
+    }
+}
+
+/// Home (working) directory of a NEAR node, with node_key.json, config.json and
+/// genesis.json files
+pub struct NearHome {
+    path: PathBuf,
+}
+
+impl NearHome {
+    /// Uses ~/.near if the path is not provided
+    pub fn new(path: Option<PathBuf>) -> Result<Self, Error> {
+        let path = match path {
+            Some(path) => path,
+            None => PathBuf::from(std::env::var("HOME").map_err(|_| {
+                Error::Config(
+                    "HOME environment variable not set (required to read node config files)".into(),
+                )
+            })?)
+            .join(".near"),
+        };
+
+        Ok(Self { path })
+    }
+
+    /// Reads the peer id (public key) from the node key config file (node_key.json)
+    pub fn peer_id(&self) -> Result<PeerId, Error> {
+        let node_key: NodeKey = read_json(&self.path.join(NODE_KEY_FILE), "node key config")?;
+
+        Ok(PeerId::new(
+            PublicKey::from_str(&node_key.public_key).map_err(|_| {
+                Error::Config(format!(
+                    "Error parsing public_key value from node key config file: {}",
+                    &node_key.public_key
+                ))
+            })?,
+        ))
+    }
+
+    /// Reads the network settings from the node config file (config.json)
+    pub fn config(&self) -> Result<NodeConfig, Error> {
+        let config: Config = read_json(&self.path.join(CONFIG_FILE), "node config")?;
+
+        Ok(NodeConfig {
+            addr: config.network.addr,
+            boot_nodes: parse_boot_nodes(&config.network.boot_nodes)?,
+        })
+    }
+
+    /// Reads the blockchain ID from the genesis config file (genesis.json)
+    pub fn chain_id(&self) -> Result<String, Error> {
+        read_json::<GenesisChainId>(&self.genesis_file(), "genesis config")
+            .map(|genesis| genesis.chain_id)
+    }
+
+    pub fn genesis_file(&self) -> PathBuf {
+        self.path.join(GENESIS_FILE)
+    }
+}
+
+fn read_json<T: DeserializeOwned>(file_path: &Path, description: &str) -> Result<T, Error> {
+    serde_json::from_reader(io::BufReader::new(fs::File::open(file_path).map_err(
+        |_| {
+            Error::Config(format!(
+                "Error opening {} file: {}",
+                description,
+                file_path.display()
+            ))
+        },
+    )?))
+    .map_err(|_| {
+        Error::Config(format!(
+            "Error parsing {} file: {}",
+            description,
+            file_path.display()
+        ))
+    })
+}
+
+/// Parses the comma separated list of boot nodes in nearcore format
+/// ("ed25519:<key>@host:port,...")
+pub fn parse_boot_nodes(boot_nodes: &str) -> Result<Vec<Target>, Error> {
+    boot_nodes
+        .split(',')
+        .map(str::trim)
+        .filter(|s| !s.is_empty())
+        .map(|s| {
+            let target = Target::from_str(s)?;
+            match target.peer_id {
+                Some(_) => Ok(target),
+                None => Err(Error::Config(format!(
+                    "Peer id of boot node not specified: {}",
+                    s
+                ))),
+            }
+        })
+        .collect()
+}
+
+/// Makes the genesis id from the optional command line args (both must be provided to
+/// get Some)
+pub fn parse_genesis_id(
+    chain_id: Option<String>,
+    hash: Option<String>,
+) -> Result<Option<GenesisId>, Error> {
+    Ok(match hash {
+        Some(hash) => Some(GenesisId {
+            chain_id: chain_id.ok_or_else(|| {
+                Error::Config("genesis_chain_id command line arg not provided".into())
+            })?,
+            hash: CryptoHash::from_str(&hash).map_err(|_| {
+                Error::Config(format!(
+                    "Error parsing hash value from genesis_hash command line arg: {}",
+                    &hash
+                ))
+            })?,
+        }),
+        None => None,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_node_config() {
+        let config = NodeConfig {
+            addr: "0.0.0.0:24567".into(),
+            boot_nodes: parse_boot_nodes(
+                "ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX@10.0.0.1:24567, \
+                 ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX@node.example.com:24567",
+            )
+            .unwrap(),
+        };
+
+        assert_eq!(config.local_addr(), "127.0.0.1:24567");
+        assert_eq!(config.boot_nodes.len(), 2);
+        assert_eq!(config.boot_nodes[1].addr, "node.example.com:24567");
+
+        assert!(parse_boot_nodes("").unwrap().is_empty());
+        assert!(parse_boot_nodes("10.0.0.1:24567").is_err());
+    }
+}
@@ -0,0 +1,101 @@
+use std::io;
+
+use bytes::{BufMut, BytesMut};
+
+use protobuf::{CodedOutputStream, Message};
+
+use tokio_util::codec::{Decoder, Encoder};
+
+use crate::network_protocol::PeerMessage;
+
+/// Size of the length prefix of the frame
+pub(super) const PREFIX_LEN: usize = 4;
+
+/// Maximum size of the message, the same as nearcore accepts (the length prefix comes from
+/// the peer, so it's checked before the buffer for the message is reserved)
+pub(super) const MAX_MESSAGE_LEN: usize = 512 * 1024 * 1024;
+
+/// Codec of the peer messages, framed with the 4-byte little-endian length prefix. Can be used
+/// with `tokio_util::codec::Framed` directly.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PeerMessageCodec;
+
+impl PeerMessageCodec {
+    /// Length of the frame (the prefix included) at the start of the data, None if the length
+    /// prefix is not complete yet
+    pub fn frame_len(src: &[u8]) -> Option<usize> {
+        let prefix = src.get(..PREFIX_LEN)?;
+        Some(PREFIX_LEN + u32::from_le_bytes(prefix.try_into().unwrap()) as usize)
+    }
+
+    /// Number of bytes missing to complete the frame at the start of the data, fails if the
+    /// message is longer than `MAX_MESSAGE_LEN`
+    pub fn missing_len(src: &[u8]) -> io::Result<usize> {
+        match Self::frame_len(src) {
+            Some(len) if len - PREFIX_LEN > MAX_MESSAGE_LEN => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("message is too large (length: {})", len - PREFIX_LEN),
+            )),
+            Some(len) => Ok(len.saturating_sub(src.len())),
+            None => Ok(PREFIX_LEN - src.len()),
+        }
+    }
+}
+
+/// Parses the message (without the length prefix)
+pub(super) fn parse_message(data: &[u8]) -> io::Result<PeerMessage> {
+    PeerMessage::parse_from_bytes(data).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("error parsing message (length: {})", data.len()),
+        )
+    })
+}
+
+impl Decoder for PeerMessageCodec {
+    type Item = PeerMessage;
+    type Error = io::Error;
+
+    fn decode(&mut self, src: &mut BytesMut) -> io::Result<Option<PeerMessage>> {
+        let missing = Self::missing_len(src)?;
+        if missing > 0 {
+            src.reserve(missing);
+            return Ok(None);
+        }
+
+        // The frame is consumed even if it can't be parsed, so the following ones can be read
+        let len = Self::frame_len(src).unwrap();
+        let frame = src.split_to(len);
+        parse_message(&frame[PREFIX_LEN..]).map(Some)
+    }
+}
+
+impl Encoder<&PeerMessage> for PeerMessageCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: &PeerMessage, dst: &mut BytesMut) -> io::Result<()> {
+        let len = msg.compute_size() as usize;
+        let len_prefix = u32::try_from(len)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidInput, "message is too large"))?;
+
+        // The message is written in place, right after the prefix
+        dst.reserve(PREFIX_LEN + len);
+        dst.put_u32_le(len_prefix);
+        let start = dst.len();
+        dst.resize(start + len, 0);
+
+        let mut os = CodedOutputStream::bytes(&mut dst[start..]);
+        msg.write_to_with_cached_sizes(&mut os)?;
+        os.check_eof();
+
+        Ok(())
+    }
+}
+
+impl Encoder<PeerMessage> for PeerMessageCodec {
+    type Error = io::Error;
+
+    fn encode(&mut self, msg: PeerMessage, dst: &mut BytesMut) -> io::Result<()> {
+        self.encode(&msg, dst)
+    }
+}
@@ -0,0 +1,366 @@
+use std::{
+    collections::VecDeque,
+    future::Future,
+    io,
+    pin::Pin,
+    str::FromStr,
+    task::{ready, Context, Poll},
+    time::SystemTime,
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, ReadBuf},
+    time::{self, Duration, Instant, Sleep},
+};
+
+use protobuf::Message;
+
+use rand_core::{RngCore, SeedableRng};
+use rand_hc::Hc128Rng;
+
+use crate::network_protocol::{message_type_name, PeerMessage};
+
+use super::codec::PeerMessageCodec;
+
+/// Fault injected into a frame
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Fault {
+    /// Deliver the frame (and so the following ones) later
+    Delay(Duration),
+    Drop,
+    Duplicate,
+    /// Deliver the frame after the next one
+    Reorder,
+    /// Cut the message to the given number of bytes (the length prefix is adjusted)
+    Truncate(usize),
+    /// Flip a random bit of the message (the length prefix is kept intact)
+    BitFlip,
+    /// Deliver only the given number of bytes of the frame (the length prefix included) and
+    /// nothing after it
+    Stall(usize),
+}
+
+/// Side of the proxy the frames are forwarded to
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FaultTarget {
+    Client,
+    Target,
+}
+
+/// Rule injecting the fault into the frames of the given message type, with the given
+/// probability. Parsed from "<fault>[=<value>][,type=<message type>][,p=<probability>]
+/// [,to=client|target]", e.g. "delay=500,type=block_response,p=0.5".
+#[derive(Clone, Debug, PartialEq)]
+pub struct FaultRule {
+    pub fault: Fault,
+    /// Type of the messages (e.g. "block_response"), all the messages if not set
+    pub msg_type: Option<String>,
+    pub probability: f64,
+    /// Side the frames are forwarded to (proxy only), both sides if not set
+    pub to: Option<FaultTarget>,
+}
+
+impl FromStr for FaultRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = s.split(',');
+        let fault = options.next().unwrap_or_default();
+        let (name, value) = match fault.split_once('=') {
+            Some((name, value)) => (name, Some(value)),
+            None => (fault, None),
+        };
+
+        let number = || -> Result<usize, String> {
+            value
+                .ok_or_else(|| format!("value required for fault: {}", name))?
+                .parse()
+                .map_err(|_| format!("invalid value of fault: {}", fault))
+        };
+
+        let fault = match name {
+            "delay" => Fault::Delay(Duration::from_millis(number()? as u64)),
+            "truncate" => Fault::Truncate(number()?),
+            "stall" => Fault::Stall(number()?),
+            _ if value.is_some() => return Err(format!("unexpected value of fault: {}", fault)),
+            "drop" => Fault::Drop,
+            "duplicate" => Fault::Duplicate,
+            "reorder" => Fault::Reorder,
+            "bitflip" => Fault::BitFlip,
+            _ => return Err(format!("unknown fault: {}", name)),
+        };
+
+        let mut rule = Self {
+            fault,
+            msg_type: None,
+            probability: 1.0,
+            to: None,
+        };
+
+        for option in options {
+            match option.split_once('=') {
+                Some(("type", msg_type)) => rule.msg_type = Some(msg_type.into()),
+                Some(("p", probability)) => {
+                    rule.probability = probability
+                        .parse()
+                        .ok()
+                        .filter(|p| (0.0..=1.0).contains(p))
+                        .ok_or_else(|| format!("invalid probability: {}", probability))?
+                }
+                Some(("to", "client")) => rule.to = Some(FaultTarget::Client),
+                Some(("to", "target")) => rule.to = Some(FaultTarget::Target),
+                _ => return Err(format!("invalid fault option: {}", option)),
+            }
+        }
+
+        Ok(rule)
+    }
+}
+
+/// Frames to deliver after the delay, and the faults injected
+#[derive(Debug, Default)]
+pub struct Delivery {
+    pub delay: Duration,
+    pub frames: Vec<Vec<u8>>,
+    pub faults: Vec<Fault>,
+}
+
+/// Applies the fault rules to the frames passing through, one by one
+pub struct FaultInjector {
+    rules: Vec<FaultRule>,
+    rng: Hc128Rng,
+
+    // Frames held back to be delivered after the next frame
+    held: Vec<Vec<u8>>,
+    // Set when a frame is stalled, nothing is delivered after it
+    stalled: bool,
+}
+
+impl FaultInjector {
+    /// Creates the injector with the given rules, a random seed is used if not given
+    pub fn new(rules: Vec<FaultRule>, seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(SystemTime::UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_nanos() as u64
+        });
+
+        Self {
+            rules,
+            rng: Hc128Rng::seed_from_u64(seed),
+            held: Vec::new(),
+            stalled: false,
+        }
+    }
+
+    pub fn is_stalled(&self) -> bool {
+        self.stalled
+    }
+
+    fn chance(&mut self, probability: f64) -> bool {
+        (self.rng.next_u64() as f64) < probability * (u64::MAX as f64)
+    }
+
+    /// Applies the rules to the frame (with the length prefix) of the message of the given
+    /// type (the name of the message type, as printed by "decode")
+    pub fn apply(&mut self, msg_type: &str, frame: Vec<u8>) -> Delivery {
+        let mut delivery = Delivery::default();
+        if self.stalled {
+            return delivery;
+        }
+
+        let mut frames = vec![frame];
+        let mut reorder = false;
+
+        for i in 0..self.rules.len() {
+            let rule = &self.rules[i];
+            if rule.msg_type.as_deref().is_some_and(|t| t != msg_type) {
+                continue;
+            }
+
+            let fault = rule.fault;
+            if !self.chance(rule.probability) {
+                continue;
+            }
+            delivery.faults.push(fault);
+
+            match fault {
+                Fault::Delay(delay) => delivery.delay += delay,
+                Fault::Drop => frames.clear(),
+                Fault::Duplicate => {
+                    frames = frames.iter().flat_map(|f| [f.clone(), f.clone()]).collect()
+                }
+                Fault::Reorder => reorder = true,
+                Fault::Truncate(len) => {
+                    for frame in &mut frames {
+                        let len = frame.len().saturating_sub(4).min(len);
+                        // The frame cut by a stall may lack even the length prefix
+                        if frame.len() >= 4 {
+                            frame.truncate(4 + len);
+                            frame[..4].copy_from_slice(&(len as u32).to_le_bytes());
+                        }
+                    }
+                }
+                Fault::BitFlip => {
+                    for frame in &mut frames {
+                        if frame.len() > 4 {
+                            let bit =
+                                (self.rng.next_u64() % ((frame.len() as u64 - 4) * 8)) as usize;
+                            frame[4 + bit / 8] ^= 1 << (bit % 8);
+                        }
+                    }
+                }
+                Fault::Stall(len) => {
+                    frames.truncate(1);
+                    if let Some(frame) = frames.first_mut() {
+                        frame.truncate(len);
+                    }
+                    self.stalled = true;
+                }
+            }
+        }
+
+        if reorder && !self.stalled {
+            self.held.append(&mut frames);
+        } else if !self.stalled {
+            frames.append(&mut self.held);
+        }
+
+        delivery.frames = frames;
+        delivery
+    }
+}
+
+/// Splits off the first complete frame of the data (with the length prefix)
+fn take_frame(data: &mut Vec<u8>) -> Option<Vec<u8>> {
+    let len = PeerMessageCodec::frame_len(data)?;
+    if data.len() < len {
+        return None;
+    }
+
+    let rest = data.split_off(len);
+    Some(std::mem::replace(data, rest))
+}
+
+/// Transport injecting the faults into the frames read from the stream (the data written is
+/// passed as is)
+pub struct FaultyStream<S> {
+    stream: S,
+    injector: FaultInjector,
+
+    // Data read from the stream, not forming a complete frame yet
+    received: Vec<u8>,
+    // Data to deliver, with the time of the delivery
+    pending: VecDeque<(Instant, Vec<u8>)>,
+    sleep: Option<Pin<Box<Sleep>>>,
+    eof: bool,
+}
+
+impl<S> FaultyStream<S> {
+    pub fn new(stream: S, injector: FaultInjector) -> Self {
+        Self {
+            stream,
+            injector,
+            received: Vec::new(),
+            pending: VecDeque::new(),
+            sleep: None,
+            eof: false,
+        }
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for FaultyStream<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let injector = &mut this.injector;
+
+        loop {
+            if let Some((deliver_at, data)) = this.pending.front_mut() {
+                if *deliver_at > Instant::now() {
+                    let sleep = this
+                        .sleep
+                        .get_or_insert_with(|| Box::pin(time::sleep_until(*deliver_at)));
+                    sleep.as_mut().reset(*deliver_at);
+                    ready!(sleep.as_mut().poll(cx));
+                    continue;
+                }
+
+                let len = data.len().min(buf.remaining());
+                buf.put_slice(&data[..len]);
+                data.drain(..len);
+                if data.is_empty() {
+                    this.pending.pop_front();
+                }
+                return Poll::Ready(Ok(()));
+            }
+
+            if this.eof {
+                return Poll::Ready(Ok(()));
+            }
+
+            // Nothing is delivered anymore, the reader is left waiting (until it times out)
+            if injector.is_stalled() {
+                return Poll::Pending;
+            }
+
+            let mut data = [0; 4096];
+            let mut read_buf = ReadBuf::new(&mut data);
+            ready!(Pin::new(&mut this.stream).poll_read(cx, &mut read_buf))?;
+
+            let now = Instant::now();
+
+            if read_buf.filled().is_empty() {
+                // The incomplete frame is delivered as it is
+                this.eof = true;
+                if !this.received.is_empty() {
+                    this.pending
+                        .push_back((now, std::mem::take(&mut this.received)));
+                }
+                continue;
+            }
+
+            this.received.extend_from_slice(read_buf.filled());
+
+            while let Some(frame) = take_frame(&mut this.received) {
+                let msg_type = PeerMessage::parse_from_bytes(&frame[4..])
+                    .map_or("none", |msg| message_type_name(&msg));
+
+                let delivery = injector.apply(msg_type, frame);
+
+                // The frames are delivered in order, so a delayed frame delays the following
+                // ones too
+                let deliver_at = this
+                    .pending
+                    .back()
+                    .map_or(now, |(deliver_at, _)| *deliver_at)
+                    .max(now + delivery.delay);
+                for frame in delivery.frames {
+                    this.pending.push_back((deliver_at, frame));
+                }
+            }
+        }
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for FaultyStream<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        Pin::new(&mut self.get_mut().stream).poll_write(cx, buf)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().stream).poll_shutdown(cx)
+    }
+}
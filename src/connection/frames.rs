@@ -0,0 +1,175 @@
+use std::{
+    io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{ready, Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use tokio_util::{
+    codec::{Decoder, Encoder},
+    io::{poll_read_buf, poll_write_buf},
+};
+
+use bytes::{BufMut, BytesMut};
+
+use tracing::Span;
+
+use crate::{
+    metrics,
+    network_protocol::{message_type_name, PeerMessage},
+    transcript::Recorder,
+};
+
+use super::{
+    codec::{PeerMessageCodec, PREFIX_LEN},
+    Direction,
+};
+
+/// Size of the buffered frames the sink writes out before accepting more messages
+const WRITE_BUFFER_SIZE: usize = 8 * 1024;
+
+/// Recorder shared by the reading and the writing sides of the connection
+pub(super) type SharedRecorder = Arc<Mutex<Recorder>>;
+
+fn record(recorder: &Option<SharedRecorder>, direction: Direction, data: &[u8]) -> io::Result<()> {
+    match recorder {
+        Some(recorder) => recorder.lock().unwrap().record(direction, data),
+        None => Ok(()),
+    }
+}
+
+/// Reading side of the connection: receives the frames, records and counts them
+#[derive(Default)]
+pub(super) struct FrameReader {
+    codec: PeerMessageCodec,
+    // Data of the frame being received (kept if reading is cancelled before the whole frame
+    // is received)
+    buffer: BytesMut,
+    pub(super) recorder: Option<SharedRecorder>,
+}
+
+impl FrameReader {
+    /// Reads the next message, None if the stream is closed between the frames
+    pub(super) fn poll_read<S: AsyncRead + Unpin>(
+        &mut self,
+        stream: &mut S,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<io::Result<PeerMessage>>> {
+        // Only the missing data of the frame is read, so the stream is not read past it
+        loop {
+            let missing = PeerMessageCodec::missing_len(&self.buffer)?;
+            if missing == 0 {
+                break;
+            }
+
+            self.buffer.reserve(missing);
+            let read = ready!(poll_read_buf(
+                Pin::new(&mut *stream),
+                cx,
+                &mut (&mut self.buffer).limit(missing)
+            ))?;
+            if read == 0 {
+                if self.buffer.is_empty() {
+                    return Poll::Ready(None);
+                }
+                return Poll::Ready(Some(Err(io::ErrorKind::UnexpectedEof.into())));
+            }
+        }
+
+        let frame_len = self.buffer.len();
+        Span::current().record("len", frame_len - PREFIX_LEN);
+
+        // Recorded before parsing, so the frames that can't be parsed are in the transcript
+        record(&self.recorder, Direction::In, &self.buffer[PREFIX_LEN..])?;
+
+        let msg = self.codec.decode(&mut self.buffer)?.unwrap();
+
+        Span::current().record("msg_type", message_type_name(&msg));
+        tracing::trace!("message received");
+
+        metrics::record_message(Direction::In, &msg, frame_len);
+
+        Poll::Ready(Some(Ok(msg)))
+    }
+}
+
+/// Writing side of the connection: buffers the frames to send, records and counts them
+#[derive(Default)]
+pub(super) struct FrameWriter {
+    codec: PeerMessageCodec,
+    // Frames not written out yet
+    buffer: BytesMut,
+    pub(super) recorder: Option<SharedRecorder>,
+}
+
+impl FrameWriter {
+    pub(super) fn poll_ready<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        if self.buffer.len() >= WRITE_BUFFER_SIZE {
+            self.poll_flush(stream, cx)
+        } else {
+            Poll::Ready(Ok(()))
+        }
+    }
+
+    pub(super) fn start_send(&mut self, msg: &PeerMessage) -> io::Result<()> {
+        let start = self.buffer.len();
+        self.codec.encode(msg, &mut self.buffer)?;
+        let frame = &self.buffer[start..];
+        Span::current().record("len", frame.len() - PREFIX_LEN);
+
+        record(&self.recorder, Direction::Out, &frame[PREFIX_LEN..])?;
+
+        tracing::trace!("message sent");
+
+        metrics::record_message(Direction::Out, msg, frame.len());
+
+        Ok(())
+    }
+
+    /// Buffers the frame (the length prefix included) as it is, even if it's incomplete or
+    /// corrupted (recorded to the transcript without the length prefix)
+    pub(super) fn start_send_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        self.buffer.extend_from_slice(data);
+
+        record(
+            &self.recorder,
+            Direction::Out,
+            data.get(PREFIX_LEN..).unwrap_or_default(),
+        )?;
+
+        tracing::trace!("frame sent");
+
+        Ok(())
+    }
+
+    /// Writes out the buffered frames
+    pub(super) fn poll_flush<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        while !self.buffer.is_empty() {
+            let written = ready!(poll_write_buf(Pin::new(&mut *stream), cx, &mut self.buffer))?;
+            if written == 0 {
+                return Poll::Ready(Err(io::ErrorKind::WriteZero.into()));
+            }
+        }
+
+        Pin::new(stream).poll_flush(cx)
+    }
+
+    pub(super) fn poll_close<S: AsyncWrite + Unpin>(
+        &mut self,
+        stream: &mut S,
+        cx: &mut Context<'_>,
+    ) -> Poll<io::Result<()>> {
+        ready!(self.poll_flush(stream, cx))?;
+        Pin::new(stream).poll_shutdown(cx)
+    }
+}
@@ -1,34 +1,85 @@
-use std::io;
+use std::{
+    future, io,
+    pin::Pin,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+};
 
 use tokio::{
-    io::{AsyncReadExt, AsyncWriteExt},
-    time,
+    io::{AsyncReadExt, AsyncWriteExt, ReadHalf, WriteHalf},
+    time::{self, Instant},
 };
 
-use protobuf::Message;
+use futures::{Sink, SinkExt};
+
+use tracing::{field, Instrument};
 
 use near_crypto::{KeyType, SecretKey};
 
 use near_primitives::{
-    block::GenesisId, network::PeerId, types::BlockHeight, version::ProtocolVersion,
+    block::GenesisId,
+    network::PeerId,
+    types::BlockHeight,
+    version::{ProtocolVersion, PROTOCOL_VERSION},
+};
+
+use crate::{
+    metrics::{self, Stage},
+    network_protocol::{
+        message_type_name, Handshake, HandshakeFailure, HandshakeResponse, NetworkError,
+        PartialEdgeInfo, PeerChainInfo, PeerIdOrHash, PeerMessage, RoutedMessage,
+        RoutedMessageBody,
+    },
+    transcript::Recorder,
 };
 
-use crate::network_protocol::{
-    Handshake, HandshakeFailure, HandshakeResponse, NetworkError, PartialEdgeInfo, PeerChainInfo,
-    PeerMessage,
+use super::{
+    frames::{FrameReader, FrameWriter},
+    ConnectionReader, ConnectionWriter,
 };
 
+/// Identity of our side and of the peer of the connection (shared by the halves of the split
+/// connection)
+pub struct Session {
+    pub(super) peer_id: PeerId,
+    pub(super) timeout: time::Duration,
+
+    pub(super) secret_key: SecretKey,
+    pub(super) my_peer_id: PeerId,
+}
+
+impl Session {
+    pub fn peer_id(&self) -> &PeerId {
+        &self.peer_id
+    }
+
+    pub fn my_peer_id(&self) -> &PeerId {
+        &self.my_peer_id
+    }
+
+    /// Creates the message routed to the target, signed with the key of the connection
+    pub fn create_routed_message(
+        &self,
+        target: PeerIdOrHash,
+        body: RoutedMessageBody,
+    ) -> RoutedMessage {
+        RoutedMessage::new(target, body, &self.secret_key)
+    }
+}
+
 pub struct Connection<Stream>
 where
     Stream: AsyncReadExt + AsyncWriteExt + std::marker::Unpin,
 {
     pub(super) stream: Stream,
-    peer_id: PeerId,
+    pub(super) session: Session,
     sender_listen_port: u16,
-    timeout: time::Duration,
 
-    secret_key: SecretKey,
-    my_peer_id: PeerId,
+    // Set when the first handshake response is received (to measure its latency once)
+    response_received: bool,
+
+    reader: FrameReader,
+    writer: FrameWriter,
 }
 
 impl<Stream> Connection<Stream>
@@ -46,15 +97,53 @@ where
 
         Self {
             stream,
-            peer_id,
+            session: Session {
+                peer_id,
+                timeout,
+
+                secret_key,
+                my_peer_id,
+            },
             sender_listen_port,
-            timeout,
 
-            secret_key,
-            my_peer_id,
+            response_received: false,
+
+            reader: FrameReader::default(),
+            writer: FrameWriter::default(),
         }
     }
 
+    /// Records all the frames sent and received from now on to the transcript
+    pub(super) fn set_recorder(&mut self, recorder: Option<Recorder>) {
+        let recorder = recorder.map(|recorder| Arc::new(Mutex::new(recorder)));
+        self.reader.recorder = recorder.clone();
+        self.writer.recorder = recorder;
+    }
+
+    /// Sets the identity of the connection (a random one is used by default)
+    pub(super) fn set_secret_key(&mut self, secret_key: SecretKey) {
+        self.session.my_peer_id = PeerId::new(secret_key.public_key());
+        self.session.secret_key = secret_key;
+    }
+
+    /// Splits the connection into the halves reading and writing the messages independently
+    /// (e.g. from different tasks)
+    pub fn into_split(
+        self,
+    ) -> (
+        ConnectionReader<ReadHalf<Stream>>,
+        ConnectionWriter<WriteHalf<Stream>>,
+    ) {
+        let (read_half, write_half) = tokio::io::split(self.stream);
+        let session = Arc::new(self.session);
+
+        (
+            ConnectionReader::new(read_half, session.clone(), self.reader),
+            ConnectionWriter::new(write_half, session, self.writer),
+        )
+    }
+
+    #[tracing::instrument(skip(self, genesis_id))]
     pub(super) async fn handshake_with_optional_genesis(
         &mut self,
         protocol_version: ProtocolVersion,
@@ -65,20 +154,34 @@ where
         // a default (empty) genesis, to get it from the node as GenesisMismatch error
         // payload
         let genesis_id = match genesis_id {
-            None => match self
-                .handshake(protocol_version, Default::default(), head_height)
-                .await
-            {
-                Err(NetworkError::HandshakeFailure(HandshakeFailure::GenesisMismatch(
-                    genesis_id,
-                ))) => Ok(genesis_id),
-                Err(e) => Err(e),
-                Ok(HandshakeResponse(_)) => Err(NetworkError::UnexpectedResponse)?,
-            },
+            None => {
+                let start = Instant::now();
+                let result = self
+                    .handshake(protocol_version, Default::default(), head_height)
+                    .instrument(tracing::info_span!("genesis_discovery"))
+                    .await;
+                metrics::observe_latency(Stage::GenesisDiscovery, start.elapsed());
+
+                match result {
+                    Err(NetworkError::HandshakeFailure(HandshakeFailure::GenesisMismatch(
+                        genesis_id,
+                    ))) => {
+                        tracing::debug!(
+                            chain_id = %genesis_id.chain_id,
+                            hash = %genesis_id.hash,
+                            "genesis received from the node"
+                        );
+                        Ok(genesis_id)
+                    }
+                    Err(e) => Err(e),
+                    Ok(HandshakeResponse(_)) => Err(NetworkError::UnexpectedResponse)?,
+                }
+            }
             Some(genesis_id) => Ok(genesis_id),
         }?;
 
         self.handshake(protocol_version, genesis_id, head_height)
+            .instrument(tracing::info_span!("handshake_round"))
             .await
     }
 
@@ -88,9 +191,9 @@ where
         genesis_id: GenesisId,
         head_height: BlockHeight,
     ) -> Handshake {
-        let sender_peer_id = self.my_peer_id.clone();
-        let target_peer_id = self.peer_id.clone();
-        let secret_key = self.secret_key.clone();
+        let sender_peer_id = self.session.my_peer_id.clone();
+        let target_peer_id = self.session.peer_id.clone();
+        let secret_key = self.session.secret_key.clone();
 
         let sender_chain_info = PeerChainInfo {
             genesis_id,
@@ -104,9 +207,9 @@ where
 
         Handshake {
             protocol_version,
-            oldest_supported_version: protocol_version - 2,
-            sender_peer_id: self.my_peer_id.clone(),
-            target_peer_id: self.peer_id.clone(),
+            oldest_supported_version: protocol_version.saturating_sub(2),
+            sender_peer_id: self.session.my_peer_id.clone(),
+            target_peer_id: self.session.peer_id.clone(),
             sender_listen_port: Some(self.sender_listen_port),
             sender_chain_info,
             partial_edge_info,
@@ -119,43 +222,279 @@ where
         genesis_id: GenesisId,
         head_height: BlockHeight,
     ) -> Result<HandshakeResponse, NetworkError> {
-        let request = (&self.create_handshake(protocol_version, genesis_id, head_height)).into();
+        self.handshake_with_versions(
+            protocol_version,
+            protocol_version.saturating_sub(2),
+            genesis_id,
+            head_height,
+        )
+        .await
+    }
 
-        self.write_message(request)
+    /// Performs the handshake advertising the given range of the supported protocol
+    /// versions (the response must be of a version within the range)
+    pub async fn handshake_with_versions(
+        &mut self,
+        protocol_version: ProtocolVersion,
+        oldest_supported_version: ProtocolVersion,
+        genesis_id: GenesisId,
+        head_height: BlockHeight,
+    ) -> Result<HandshakeResponse, NetworkError> {
+        self.versioned_handshake(
+            protocol_version,
+            oldest_supported_version,
+            genesis_id,
+            head_height,
+            true,
+        )
+        .await
+    }
+
+    /// Performs the handshake as `handshake_with_versions` does, but accepts the response
+    /// of any protocol version: the node accepting the handshake may respond with its own
+    /// version, outside of the range advertised
+    pub async fn probe_handshake(
+        &mut self,
+        protocol_version: ProtocolVersion,
+        oldest_supported_version: ProtocolVersion,
+        genesis_id: GenesisId,
+        head_height: BlockHeight,
+    ) -> Result<HandshakeResponse, NetworkError> {
+        self.versioned_handshake(
+            protocol_version,
+            oldest_supported_version,
+            genesis_id,
+            head_height,
+            false,
+        )
+        .await
+    }
+
+    #[tracing::instrument(
+        name = "handshake_with_versions",
+        skip(self, genesis_id),
+        fields(chain_id = %genesis_id.chain_id, hash = %genesis_id.hash),
+        err(level = "debug")
+    )]
+    async fn versioned_handshake(
+        &mut self,
+        protocol_version: ProtocolVersion,
+        oldest_supported_version: ProtocolVersion,
+        genesis_id: GenesisId,
+        head_height: BlockHeight,
+        check_version: bool,
+    ) -> Result<HandshakeResponse, NetworkError> {
+        let start = Instant::now();
+
+        let request = self
+            .send_handshake(
+                protocol_version,
+                oldest_supported_version,
+                genesis_id,
+                head_height,
+            )
+            .await?;
+
+        let response_message = self
+            .read_message_with_timeout()
             .await
             .map_err(NetworkError::IO)?;
 
-        (&self
+        if !self.response_received {
+            self.response_received = true;
+            metrics::observe_latency(Stage::FirstResponse, start.elapsed());
+        }
+
+        Self::verify_handshake_response(&request, &response_message, check_version)
+    }
+
+    /// Sends the handshake request advertising the given range of the supported protocol
+    /// versions, returns the request (to verify the response against)
+    pub async fn send_handshake(
+        &mut self,
+        protocol_version: ProtocolVersion,
+        oldest_supported_version: ProtocolVersion,
+        genesis_id: GenesisId,
+        head_height: BlockHeight,
+    ) -> Result<Handshake, NetworkError> {
+        let mut request = self.create_handshake(protocol_version, genesis_id, head_height);
+        request.oldest_supported_version = oldest_supported_version;
+
+        self.write_message((&request).into())
+            .await
+            .map_err(NetworkError::IO)?;
+
+        Ok(request)
+    }
+
+    /// Reads the response to the handshake request sent and verifies it
+    pub async fn read_handshake_response(
+        &mut self,
+        request: &Handshake,
+    ) -> Result<HandshakeResponse, NetworkError> {
+        let response_message = self
             .read_message_with_timeout()
             .await
-            .map_err(NetworkError::IO)?)
-            .try_into()
+            .map_err(NetworkError::IO)?;
+
+        Self::verify_handshake_response(request, &response_message, true)
     }
 
-    pub(super) async fn write_message(&mut self, msg: PeerMessage) -> io::Result<()> {
-        let msg_data = msg.write_to_bytes()?;
-        let data = [&(msg_data.len() as u32).to_le_bytes(), msg_data.as_slice()].concat();
-        self.stream.write_all(&data).await
+    fn verify_handshake_response(
+        request: &Handshake,
+        response_message: &PeerMessage,
+        check_version: bool,
+    ) -> Result<HandshakeResponse, NetworkError> {
+        let response: HandshakeResponse = response_message.try_into()?;
+
+        match check_version {
+            true => response.verify(request)?,
+            false => response.verify_peer(request)?,
+        }
+
+        tracing::debug!(
+            protocol_version = response.0.protocol_version,
+            height = response.0.sender_chain_info.height,
+            "handshake response verified"
+        );
+
+        Ok(response)
     }
 
-    pub(super) async fn read_message(&mut self) -> io::Result<PeerMessage> {
-        let mut prefix_data = [0; 4];
-        self.stream.read_exact(&mut prefix_data).await?;
+    /// Performs the handshake of the inbound connection: receives the handshake of the peer
+    /// and responds with ours (with the genesis and the protocol version of the peer, if
+    /// supported), or with HandshakeFailure if the handshake is not addressed to us
+    #[tracing::instrument(skip(self), err(level = "debug"))]
+    pub(super) async fn accept_handshake(
+        &mut self,
+        head_height: BlockHeight,
+    ) -> Result<Handshake, NetworkError> {
+        let request_message = self
+            .read_message_with_timeout()
+            .await
+            .map_err(NetworkError::IO)?;
 
-        let msg_len = u32::from_le_bytes(prefix_data) as usize;
+        let request = match (&request_message).try_into() {
+            Ok(HandshakeResponse(request)) => request,
+            Err(NetworkError::HandshakeFailure(HandshakeFailure::ParseHandshakeError(_))) => {
+                return Err(NetworkError::InvalidResponse)
+            }
+            Err(NetworkError::HandshakeFailure(_)) => return Err(NetworkError::UnexpectedResponse),
+            Err(e) => return Err(e),
+        };
 
-        let mut msg_data = vec![0u8; msg_len];
-        self.stream.read_exact(&mut msg_data).await?;
+        if request.target_peer_id != self.session.my_peer_id {
+            self.write_message((&HandshakeFailure::InvalidTarget).into())
+                .await
+                .map_err(NetworkError::IO)?;
+            return Err(NetworkError::TargetMismatch(request.target_peer_id));
+        }
 
-        PeerMessage::parse_from_bytes(&msg_data).map_err(|_| {
-            io::Error::new(
-                io::ErrorKind::InvalidData,
-                format!("error parsing message (length: {})", msg_len),
-            )
-        })
+        if !request.partial_edge_info.verify(
+            &request.sender_peer_id,
+            &self.session.my_peer_id,
+            request.sender_peer_id.public_key(),
+        ) {
+            return Err(NetworkError::InvalidSignature);
+        }
+
+        self.session.peer_id = request.sender_peer_id.clone();
+
+        let mut response = self.create_handshake(
+            request.protocol_version.min(PROTOCOL_VERSION),
+            request.sender_chain_info.genesis_id.clone(),
+            head_height,
+        );
+        // The edge is signed with the nonce proposed by the peer
+        response.partial_edge_info = PartialEdgeInfo::new(
+            &self.session.my_peer_id,
+            &self.session.peer_id,
+            request.partial_edge_info.nonce,
+            &self.session.secret_key,
+        );
+
+        self.write_message((&response).into())
+            .await
+            .map_err(NetworkError::IO)?;
+
+        tracing::debug!(
+            peer_id = %request.sender_peer_id,
+            protocol_version = request.protocol_version,
+            "handshake accepted"
+        );
+
+        Ok(request)
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(msg_type = message_type_name(&msg), len = field::Empty)
+    )]
+    pub async fn write_message(&mut self, msg: PeerMessage) -> io::Result<()> {
+        self.send(msg).await
+    }
+
+    /// Writes the frame (the length prefix included) as it is, even if it's incomplete or
+    /// corrupted (recorded to the transcript without the length prefix)
+    #[tracing::instrument(level = "debug", skip_all, fields(len = data.len()))]
+    pub async fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.start_send_frame(data)?;
+        future::poll_fn(|cx| self.writer.poll_flush(&mut self.stream, cx)).await
+    }
+
+    /// Reads the next message. Cancel safe: if the future is dropped before the whole frame
+    /// is received, the data read so far is kept for the next call.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(msg_type = field::Empty, len = field::Empty)
+    )]
+    pub async fn read_message(&mut self) -> io::Result<PeerMessage> {
+        future::poll_fn(|cx| self.reader.poll_read(&mut self.stream, cx))
+            .await
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+    }
+
+    pub async fn read_message_with_timeout(&mut self) -> io::Result<PeerMessage> {
+        time::timeout(self.session.timeout, self.read_message()).await?
+    }
+}
+
+impl<Stream> futures::Stream for Connection<Stream>
+where
+    Stream: AsyncReadExt + AsyncWriteExt + std::marker::Unpin,
+{
+    type Item = io::Result<PeerMessage>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.reader.poll_read(&mut this.stream, cx)
+    }
+}
+
+impl<Stream> Sink<PeerMessage> for Connection<Stream>
+where
+    Stream: AsyncReadExt + AsyncWriteExt + std::marker::Unpin,
+{
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_ready(&mut this.stream, cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: PeerMessage) -> io::Result<()> {
+        self.get_mut().writer.start_send(&msg)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_flush(&mut this.stream, cx)
     }
 
-    pub(super) async fn read_message_with_timeout(&mut self) -> io::Result<PeerMessage> {
-        time::timeout(self.timeout, self.read_message()).await?
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_close(&mut this.stream, cx)
     }
 }
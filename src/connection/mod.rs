@@ -1,7 +1,34 @@
+mod codec;
+mod faults;
+mod frames;
 mod generic_connection;
+mod split;
 mod tcp_connection;
 
 #[cfg(test)]
 mod tests;
 
-pub use generic_connection::Connection;
+use serde::{Deserialize, Serialize};
+
+pub use codec::PeerMessageCodec;
+pub use faults::{Fault, FaultInjector, FaultRule, FaultTarget, FaultyStream};
+pub use generic_connection::{Connection, Session};
+pub use split::{ConnectionReader, ConnectionWriter};
+pub use tcp_connection::{ConnectOptions, TcpConnection, TcpTransport};
+
+/// Direction of the message (relative to us)
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    In,
+    Out,
+}
+
+impl Direction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::In => "in",
+            Self::Out => "out",
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    future, io,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use tokio::io::{AsyncRead, AsyncWrite};
+
+use futures::{Sink, SinkExt, Stream};
+
+use tracing::field;
+
+use crate::network_protocol::{message_type_name, PeerMessage};
+
+use super::{
+    frames::{FrameReader, FrameWriter},
+    Session,
+};
+
+/// Reading half of the split connection
+pub struct ConnectionReader<R> {
+    stream: R,
+    session: Arc<Session>,
+    reader: FrameReader,
+}
+
+impl<R: AsyncRead + Unpin> ConnectionReader<R> {
+    pub(super) fn new(stream: R, session: Arc<Session>, reader: FrameReader) -> Self {
+        Self {
+            stream,
+            session,
+            reader,
+        }
+    }
+
+    pub fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    /// Reads the next message. Cancel safe, as `Connection::read_message`.
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(msg_type = field::Empty, len = field::Empty)
+    )]
+    pub async fn read_message(&mut self) -> io::Result<PeerMessage> {
+        future::poll_fn(|cx| self.reader.poll_read(&mut self.stream, cx))
+            .await
+            .unwrap_or_else(|| Err(io::ErrorKind::UnexpectedEof.into()))
+    }
+}
+
+impl<R: AsyncRead + Unpin> Stream for ConnectionReader<R> {
+    type Item = io::Result<PeerMessage>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        this.reader.poll_read(&mut this.stream, cx)
+    }
+}
+
+/// Writing half of the split connection
+pub struct ConnectionWriter<W> {
+    stream: W,
+    session: Arc<Session>,
+    writer: FrameWriter,
+}
+
+impl<W: AsyncWrite + Unpin> ConnectionWriter<W> {
+    pub(super) fn new(stream: W, session: Arc<Session>, writer: FrameWriter) -> Self {
+        Self {
+            stream,
+            session,
+            writer,
+        }
+    }
+
+    pub fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    #[tracing::instrument(
+        level = "debug",
+        skip_all,
+        fields(msg_type = message_type_name(&msg), len = field::Empty)
+    )]
+    pub async fn write_message(&mut self, msg: PeerMessage) -> io::Result<()> {
+        self.send(msg).await
+    }
+
+    /// Writes the frame as it is, as `Connection::write_frame`
+    #[tracing::instrument(level = "debug", skip_all, fields(len = data.len()))]
+    pub async fn write_frame(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.start_send_frame(data)?;
+        future::poll_fn(|cx| self.writer.poll_flush(&mut self.stream, cx)).await
+    }
+}
+
+impl<W: AsyncWrite + Unpin> Sink<PeerMessage> for ConnectionWriter<W> {
+    type Error = io::Error;
+
+    fn poll_ready(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_ready(&mut this.stream, cx)
+    }
+
+    fn start_send(self: Pin<&mut Self>, msg: PeerMessage) -> io::Result<()> {
+        self.get_mut().writer.start_send(&msg)
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_flush(&mut this.stream, cx)
+    }
+
+    fn poll_close(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        this.writer.poll_close(&mut this.stream, cx)
+    }
+}
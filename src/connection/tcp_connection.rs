@@ -1,42 +1,212 @@
-use std::net::SocketAddr;
+use std::{
+    io,
+    net::SocketAddr,
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use tokio::{
+    io::{AsyncRead, AsyncWrite, BufReader, ReadBuf},
+    net::TcpStream,
+    time::{self, Instant},
+};
 
-use tokio::{io::BufReader, net::TcpStream, time};
+use near_crypto::{KeyType, PublicKey, SecretKey};
 
 use near_primitives::{
     block::GenesisId, network::PeerId, types::BlockHeight, version::PROTOCOL_VERSION,
 };
 
-use crate::network_protocol::{Handshake, NetworkError};
+use crate::{
+    metrics::{self, Stage},
+    network_protocol::{Handshake, NetworkError},
+    transcript::Recorder,
+};
+
+use super::{Connection, FaultInjector, FaultyStream};
+
+pub type TcpConnection = Connection<BufReader<TcpTransport>>;
+
+const BUF_READER_SIZE: usize = 1024;
+
+/// Transport of the TCP connection: the stream itself, or the stream injecting the faults
+/// into the frames read if any faults are configured
+pub enum TcpTransport {
+    Plain(TcpStream),
+    Faulty(Box<FaultyStream<TcpStream>>),
+}
+
+impl TcpTransport {
+    fn new(stream: TcpStream, faults: Option<FaultInjector>) -> Self {
+        match faults {
+            Some(injector) => Self::Faulty(Box::new(FaultyStream::new(stream, injector))),
+            None => Self::Plain(stream),
+        }
+    }
+}
+
+impl AsyncRead for TcpTransport {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_read(cx, buf),
+            Self::Faulty(stream) => Pin::new(stream.as_mut()).poll_read(cx, buf),
+        }
+    }
+}
+
+impl AsyncWrite for TcpTransport {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_write(cx, buf),
+            Self::Faulty(stream) => Pin::new(stream.as_mut()).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_flush(cx),
+            Self::Faulty(stream) => Pin::new(stream.as_mut()).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Plain(stream) => Pin::new(stream).poll_shutdown(cx),
+            Self::Faulty(stream) => Pin::new(stream.as_mut()).poll_shutdown(cx),
+        }
+    }
+}
+
+/// Options of the outbound TCP connection
+pub struct ConnectOptions {
+    /// Timeout of connecting, and of reading and writing every message
+    pub timeout: time::Duration,
+    /// Recorder of the transcript of the connection, if any
+    pub recorder: Option<Recorder>,
+    /// Injector of the faults into the frames read, if any
+    pub faults: Option<FaultInjector>,
+}
 
-use super::Connection;
+impl ConnectOptions {
+    /// Options with the given timeout, without recording the transcript or injecting faults
+    pub fn new(timeout: time::Duration) -> Self {
+        Self {
+            timeout,
+            recorder: None,
+            faults: None,
+        }
+    }
+}
 
-impl Connection<BufReader<TcpStream>> {
+impl TcpConnection {
+    #[tracing::instrument(
+        skip(peer_id, sender_listen_port, genesis_id, head_height, options),
+        fields(peer_id = %peer_id),
+        err(level = "debug")
+    )]
     pub async fn connect(
         addr: SocketAddr,
         peer_id: PeerId,
         sender_listen_port: u16,
-        timeout: time::Duration,
 
         genesis_id: Option<GenesisId>,
         head_height: BlockHeight,
+
+        options: ConnectOptions,
     ) -> Result<(Self, Handshake), NetworkError> {
-        const BUF_READER_SIZE: usize = 1024;
+        let mut connection = Self::open(addr, peer_id, sender_listen_port, options).await?;
+
+        let handshake = connection
+            .handshake_with_optional_genesis(PROTOCOL_VERSION, genesis_id, head_height)
+            .await?
+            .0;
+
+        Ok((connection, handshake))
+    }
+
+    /// Opens the TCP connection to the node without performing the handshake
+    pub async fn open(
+        addr: SocketAddr,
+        peer_id: PeerId,
+        sender_listen_port: u16,
+
+        options: ConnectOptions,
+    ) -> Result<Self, NetworkError> {
+        let ConnectOptions {
+            timeout,
+            recorder,
+            faults,
+        } = options;
+
+        let start = Instant::now();
 
         let stream = BufReader::with_capacity(
             BUF_READER_SIZE,
-            time::timeout(timeout, TcpStream::connect(addr))
-                .await
-                .map_err(|e| NetworkError::IO(e.into()))?
-                .map_err(NetworkError::IO)?,
+            TcpTransport::new(
+                time::timeout(timeout, TcpStream::connect(addr))
+                    .await
+                    .map_err(|_| NetworkError::ConnectTimeout)?
+                    .map_err(NetworkError::connect)?,
+                faults,
+            ),
         );
 
+        metrics::observe_latency(Stage::TcpConnect, start.elapsed());
+        tracing::debug!("connected");
+
         let mut connection = Self::new(stream, peer_id, sender_listen_port, timeout);
+        connection.set_recorder(recorder);
 
-        let handshake = connection
-            .handshake_with_optional_genesis(PROTOCOL_VERSION, genesis_id, head_height)
-            .await?
-            .0;
+        Ok(connection)
+    }
+
+    /// Performs the handshake of the connection accepted from a peer, with the identity of
+    /// the given key. Returns the handshake of the peer.
+    #[tracing::instrument(
+        skip(stream, secret_key, sender_listen_port, timeout, head_height, recorder),
+        err(level = "debug")
+    )]
+    pub async fn accept(
+        stream: TcpStream,
+        secret_key: SecretKey,
+        sender_listen_port: u16,
+        timeout: time::Duration,
+
+        head_height: BlockHeight,
+
+        recorder: Option<Recorder>,
+    ) -> Result<(Self, Handshake), NetworkError> {
+        let mut connection = Self::raw(stream, sender_listen_port, timeout, recorder);
+        connection.set_secret_key(secret_key);
+
+        let handshake = connection.accept_handshake(head_height).await?;
 
         Ok((connection, handshake))
     }
+
+    /// Wraps the TCP connection without performing the handshake (all the messages, the
+    /// handshakes included, are read and written as they are)
+    pub fn raw(
+        stream: TcpStream,
+        sender_listen_port: u16,
+        timeout: time::Duration,
+        recorder: Option<Recorder>,
+    ) -> Self {
+        // The peer is not known before the handshake
+        let peer_id = PeerId::new(PublicKey::empty(KeyType::ED25519));
+
+        let stream = BufReader::with_capacity(BUF_READER_SIZE, TcpTransport::Plain(stream));
+        let mut connection = Self::new(stream, peer_id, sender_listen_port, timeout);
+        connection.set_recorder(recorder);
+
+        connection
+    }
 }
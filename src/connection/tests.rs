@@ -1,16 +1,35 @@
 use std::io;
 
-use tokio::{io::AsyncSeekExt, time::Duration};
+use tokio::{
+    io::{AsyncSeekExt, AsyncWriteExt, DuplexStream},
+    time::{self, Duration, Instant},
+};
+
+use bytes::BytesMut;
+
+use futures::{SinkExt, StreamExt};
+
+use tokio_util::codec::{Decoder, Encoder, Framed};
 
-use near_crypto::{ED25519PublicKey, PublicKey};
+use near_crypto::{KeyType, PublicKey, SecretKey};
 
 use near_primitives::{
     block::GenesisId, hash::CryptoHash, network::PeerId, version::PROTOCOL_VERSION,
 };
 
+use protobuf::Message;
+
 use crate::{
-    network_protocol::{Handshake, HandshakeFailure, HandshakeResponse, MessageType, NetworkError},
-    Connection,
+    network_protocol::{
+        BlockRequest, Disconnect, Handshake, HandshakeFailure, HandshakeResponse, MessageType,
+        NetworkError, PartialEdgeInfo, PeerMessage, PeersRequest,
+    },
+    transcript::{self, Recorder},
+};
+
+use super::{
+    codec::MAX_MESSAGE_LEN, Connection, Direction, Fault, FaultInjector, FaultRule, FaultTarget,
+    FaultyStream, PeerMessageCodec,
 };
 
 type TestConnection = Connection<io::Cursor<Vec<u8>>>;
@@ -23,6 +42,7 @@ async fn seek_to_start(connection: &mut TestConnection) {
         .unwrap();
 }
 
+#[allow(clippy::seek_from_current)]
 async fn assert_end_stream(connection: &mut TestConnection) {
     assert_eq!(
         connection.stream.get_ref().len() as u64,
@@ -48,6 +68,26 @@ async fn test_handshake_request(
     handshake_response
 }
 
+/// Makes the handshake the node responds with to the given request
+fn node_response(request: &Handshake, node_secret_key: &SecretKey) -> Handshake {
+    let sender_peer_id = PeerId::new(node_secret_key.public_key());
+
+    Handshake {
+        protocol_version: request.protocol_version,
+        oldest_supported_version: request.oldest_supported_version,
+        partial_edge_info: PartialEdgeInfo::new(
+            &sender_peer_id,
+            &request.sender_peer_id,
+            request.partial_edge_info.nonce,
+            node_secret_key,
+        ),
+        sender_peer_id,
+        target_peer_id: request.sender_peer_id.clone(),
+        sender_listen_port: Some(24567),
+        sender_chain_info: request.sender_chain_info.clone(),
+    }
+}
+
 fn assert_unexpected_eof(handshake_response: Result<HandshakeResponse, NetworkError>) {
     assert!(matches!(
         handshake_response,
@@ -57,7 +97,8 @@ fn assert_unexpected_eof(handshake_response: Result<HandshakeResponse, NetworkEr
 
 #[tokio::test]
 async fn test_connection() {
-    let peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([1u8; 32])));
+    let node_secret_key = SecretKey::from_seed(KeyType::ED25519, "node");
+    let peer_id = PeerId::new(node_secret_key.public_key());
 
     let sender_listen_port = 24567;
 
@@ -109,7 +150,10 @@ async fn test_connection() {
     // Write Handshake response to stream internal buffer and perform the final test
     // of handshake request
     {
-        let handshake = connection.create_handshake(PROTOCOL_VERSION, genesis_id, 0);
+        let handshake = node_response(
+            &connection.create_handshake(PROTOCOL_VERSION, genesis_id, 0),
+            &node_secret_key,
+        );
 
         connection.write_message((&handshake).into()).await.unwrap();
 
@@ -119,3 +163,555 @@ async fn test_connection() {
         );
     }
 }
+
+#[test]
+fn test_response_verification() {
+    let node_secret_key = SecretKey::from_seed(KeyType::ED25519, "node");
+    let peer_id = PeerId::new(node_secret_key.public_key());
+
+    let mut connection = Connection::new(
+        io::Cursor::new(Vec::new()),
+        peer_id,
+        24567,
+        Duration::from_secs(1),
+    );
+
+    let genesis_id = GenesisId {
+        chain_id: "localnet".into(),
+        hash: CryptoHash([2u8; 32]),
+    };
+    let request = connection.create_handshake(PROTOCOL_VERSION, genesis_id, 0);
+
+    let verify = |response: Handshake| HandshakeResponse(response).verify(&request);
+
+    assert!(verify(node_response(&request, &node_secret_key)).is_ok());
+
+    // Response from another node (e.g. a man-in-the-middle with its own key)
+    let other_secret_key = SecretKey::from_seed(KeyType::ED25519, "other");
+    assert!(matches!(
+        verify(node_response(&request, &other_secret_key)),
+        Err(NetworkError::SenderMismatch(_))
+    ));
+
+    // Response addressed to another peer
+    let mut response = node_response(&request, &node_secret_key);
+    response.target_peer_id = PeerId::new(other_secret_key.public_key());
+    assert!(matches!(
+        verify(response),
+        Err(NetworkError::TargetMismatch(_))
+    ));
+
+    // Edge signed with a key other than the sender's one
+    let mut response = node_response(&request, &node_secret_key);
+    response.partial_edge_info = PartialEdgeInfo::new(
+        &response.sender_peer_id,
+        &response.target_peer_id,
+        1,
+        &other_secret_key,
+    );
+    assert!(matches!(
+        verify(response),
+        Err(NetworkError::InvalidSignature)
+    ));
+
+    let mut response = node_response(&request, &node_secret_key);
+    response.sender_chain_info.genesis_id = Default::default();
+    assert!(matches!(
+        verify(response),
+        Err(NetworkError::GenesisMismatch(_))
+    ));
+
+    let mut response = node_response(&request, &node_secret_key);
+    response.protocol_version = request.protocol_version + 1;
+    assert!(matches!(
+        verify(response),
+        Err(NetworkError::UnsupportedProtocolVersion(_))
+    ));
+}
+
+#[tokio::test]
+async fn test_transcript() {
+    let path = std::env::temp_dir().join(format!(
+        "near-handshake-test-{}-transcript.jsonl",
+        std::process::id()
+    ));
+
+    let node_secret_key = SecretKey::from_seed(KeyType::ED25519, "node");
+    let peer_id = PeerId::new(node_secret_key.public_key());
+
+    let mut connection = Connection::new(
+        io::Cursor::new(Vec::new()),
+        peer_id,
+        24567,
+        Duration::from_secs(1),
+    );
+    connection.set_recorder(Some(Recorder::open(&path, "node".into()).unwrap()));
+
+    let handshake_failure = HandshakeFailure::InvalidTarget;
+    connection
+        .write_message((&handshake_failure).into())
+        .await
+        .unwrap();
+
+    seek_to_start(&mut connection).await;
+    connection.read_message().await.unwrap();
+
+    let frames = transcript::read(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    assert_eq!(frames.len(), 2);
+    assert_eq!(frames[0].direction, Direction::Out);
+    assert_eq!(frames[1].direction, Direction::In);
+    assert_eq!(frames[0].data, frames[1].data);
+    assert_eq!(
+        frames[0].data,
+        PeerMessage::from(&handshake_failure)
+            .write_to_bytes()
+            .unwrap()
+    );
+}
+
+#[tokio::test]
+async fn test_read_message_cancelled() {
+    let (stream, mut node) = tokio::io::duplex(1024);
+
+    let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+    let mut connection = Connection::new(stream, peer_id, 24567, Duration::from_secs(1));
+
+    let msg = PeerMessage::from(&HandshakeFailure::InvalidTarget);
+    let data = msg.write_to_bytes().unwrap();
+    let frame = [&(data.len() as u32).to_le_bytes(), data.as_slice()].concat();
+
+    // Reading is cancelled when only a part of the frame is received
+    node.write_all(&frame[..6]).await.unwrap();
+    assert!(
+        time::timeout(Duration::from_millis(10), connection.read_message())
+            .await
+            .is_err()
+    );
+
+    // The data already received is not lost
+    node.write_all(&frame[6..]).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), msg);
+}
+
+#[tokio::test]
+async fn test_accept_handshake() {
+    let (stream, node_stream) = tokio::io::duplex(1024);
+
+    let mut node = Connection::new(
+        node_stream,
+        PeerId::new(PublicKey::empty(KeyType::ED25519)),
+        24567,
+        Duration::from_secs(1),
+    );
+    let node_peer_id = node.session.my_peer_id().clone();
+
+    let mut connection =
+        Connection::new(stream, node_peer_id.clone(), 24568, Duration::from_secs(1));
+    let genesis_id = GenesisId {
+        chain_id: "localnet".into(),
+        hash: CryptoHash::hash_bytes(b"genesis"),
+    };
+
+    let (response, request) = tokio::join!(
+        connection.handshake(PROTOCOL_VERSION, genesis_id.clone(), 0),
+        node.accept_handshake(10)
+    );
+
+    // The response is verified by the connecting side
+    let response = response.unwrap().0;
+    assert_eq!(response.sender_peer_id, node_peer_id);
+    assert_eq!(response.sender_chain_info.genesis_id, genesis_id);
+    assert_eq!(response.sender_chain_info.height, 10);
+
+    let request = request.unwrap();
+    assert_eq!(&request.sender_peer_id, connection.session.my_peer_id());
+
+    // The handshake addressed to another peer is rejected
+    let mut connection = Connection::new(
+        connection.stream,
+        PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key()),
+        24568,
+        Duration::from_secs(1),
+    );
+    let (response, request) = tokio::join!(
+        connection.handshake(PROTOCOL_VERSION, genesis_id, 0),
+        node.accept_handshake(10)
+    );
+    assert!(matches!(
+        response,
+        Err(NetworkError::HandshakeFailure(
+            HandshakeFailure::InvalidTarget
+        ))
+    ));
+    assert!(matches!(request, Err(NetworkError::TargetMismatch(_))));
+}
+
+#[tokio::test]
+async fn test_handshake_with_versions() {
+    let (stream, node_stream) = tokio::io::duplex(1024);
+
+    let mut node = Connection::new(
+        node_stream,
+        PeerId::new(PublicKey::empty(KeyType::ED25519)),
+        24567,
+        Duration::from_secs(1),
+    );
+    let node_peer_id = node.session.my_peer_id().clone();
+
+    let mut connection = Connection::new(stream, node_peer_id, 24568, Duration::from_secs(1));
+    let genesis_id = GenesisId {
+        chain_id: "localnet".into(),
+        hash: CryptoHash::hash_bytes(b"genesis"),
+    };
+
+    // The node responds with the version advertised
+    let (response, request) = tokio::join!(
+        connection.handshake_with_versions(
+            PROTOCOL_VERSION - 1,
+            PROTOCOL_VERSION - 1,
+            genesis_id.clone(),
+            0
+        ),
+        node.accept_handshake(10)
+    );
+    assert_eq!(response.unwrap().0.protocol_version, PROTOCOL_VERSION - 1);
+    assert_eq!(
+        request.unwrap().oldest_supported_version,
+        PROTOCOL_VERSION - 1
+    );
+
+    // The node responds with its own version, which is not within the range advertised
+    let (response, _) = tokio::join!(
+        connection.handshake_with_versions(
+            PROTOCOL_VERSION + 2,
+            PROTOCOL_VERSION + 1,
+            genesis_id.clone(),
+            0
+        ),
+        node.accept_handshake(10)
+    );
+    assert!(matches!(
+        response,
+        Err(NetworkError::UnsupportedProtocolVersion(version)) if version == PROTOCOL_VERSION
+    ));
+
+    // The request and the response phases performed separately
+    let (response, _) = tokio::join!(
+        async {
+            let request = connection
+                .send_handshake(PROTOCOL_VERSION, PROTOCOL_VERSION - 2, genesis_id, 0)
+                .await?;
+            connection.read_handshake_response(&request).await
+        },
+        node.accept_handshake(10)
+    );
+    assert_eq!(response.unwrap().0.sender_chain_info.height, 10);
+}
+
+fn frame(msg: &PeerMessage) -> Vec<u8> {
+    let data = msg.write_to_bytes().unwrap();
+    [&(data.len() as u32).to_le_bytes(), data.as_slice()].concat()
+}
+
+/// Makes the connection reading through the faulty transport, with the stream of the node
+fn faulty_connection(rules: &[&str]) -> (Connection<FaultyStream<DuplexStream>>, DuplexStream) {
+    let (stream, node) = tokio::io::duplex(4096);
+
+    let rules = rules.iter().map(|rule| rule.parse().unwrap()).collect();
+    let stream = FaultyStream::new(stream, FaultInjector::new(rules, Some(1)));
+
+    let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+    let connection = Connection::new(stream, peer_id, 24567, Duration::from_millis(100));
+
+    (connection, node)
+}
+
+#[test]
+fn test_fault_rule() {
+    assert_eq!(
+        "delay=500,type=block_response,p=0.5,to=client"
+            .parse::<FaultRule>()
+            .unwrap(),
+        FaultRule {
+            fault: Fault::Delay(Duration::from_millis(500)),
+            msg_type: Some("block_response".into()),
+            probability: 0.5,
+            to: Some(FaultTarget::Client),
+        }
+    );
+    assert_eq!(
+        "drop".parse::<FaultRule>().unwrap(),
+        FaultRule {
+            fault: Fault::Drop,
+            msg_type: None,
+            probability: 1.0,
+            to: None,
+        }
+    );
+    assert_eq!(
+        "stall=6".parse::<FaultRule>().unwrap().fault,
+        Fault::Stall(6)
+    );
+
+    assert!("delay".parse::<FaultRule>().is_err());
+    assert!("truncate=x".parse::<FaultRule>().is_err());
+    assert!("drop=1".parse::<FaultRule>().is_err());
+    assert!("unknown".parse::<FaultRule>().is_err());
+    assert!("drop,p=2".parse::<FaultRule>().is_err());
+    assert!("drop,to=nobody".parse::<FaultRule>().is_err());
+    assert!("drop,size=1".parse::<FaultRule>().is_err());
+}
+
+#[test]
+fn test_fault_probability() {
+    let rules = vec!["drop,p=0.5".parse().unwrap()];
+    let delivered = |seed| {
+        let mut injector = FaultInjector::new(rules.clone(), Some(seed));
+        (0..100)
+            .map(|_| !injector.apply("disconnect", vec![0; 4]).frames.is_empty())
+            .collect::<Vec<_>>()
+    };
+
+    // The same faults are injected with the same seed
+    let first = delivered(1);
+    assert_eq!(first, delivered(1));
+    assert_ne!(first, delivered(2));
+
+    let count = first.iter().filter(|delivered| **delivered).count();
+    assert!((20..80).contains(&count));
+}
+
+#[tokio::test]
+async fn test_faults() {
+    let peers_request = PeerMessage::from(&PeersRequest);
+    let disconnect = PeerMessage::from(&Disconnect);
+    let block_request = PeerMessage::from(&BlockRequest(CryptoHash::hash_bytes(b"block")));
+    let frames = [&peers_request, &disconnect, &block_request]
+        .map(frame)
+        .concat();
+
+    // Dropped
+    let (mut connection, mut node) = faulty_connection(&["drop,type=peers_request"]);
+    node.write_all(&frames).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+    assert_eq!(connection.read_message().await.unwrap(), block_request);
+
+    // Duplicated
+    let (mut connection, mut node) = faulty_connection(&["duplicate,type=disconnect"]);
+    node.write_all(&frames).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), peers_request);
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+    assert_eq!(connection.read_message().await.unwrap(), block_request);
+
+    // Reordered
+    let (mut connection, mut node) = faulty_connection(&["reorder,type=peers_request"]);
+    node.write_all(&frames).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+    assert_eq!(connection.read_message().await.unwrap(), peers_request);
+    assert_eq!(connection.read_message().await.unwrap(), block_request);
+
+    // Truncated (within the block hash, so the frame is not decoded)
+    let (mut connection, mut node) = faulty_connection(&["truncate=10,type=block_request"]);
+    node.write_all(&frames).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), peers_request);
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+    let error = connection.read_message().await.unwrap_err();
+    assert!(matches!(
+        error.kind(),
+        io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof
+    ));
+
+    let mut injector = FaultInjector::new(vec!["truncate=2".parse().unwrap()], None);
+    let delivery = injector.apply("block_request", frame(&block_request));
+    assert_eq!(
+        delivery.frames,
+        vec![[&2u32.to_le_bytes(), &frame(&block_request)[4..6]].concat()]
+    );
+    assert_eq!(delivery.faults, vec![Fault::Truncate(2)]);
+
+    // Truncated and stalled
+    let rules = vec!["truncate=2".parse().unwrap(), "stall=5".parse().unwrap()];
+    let delivery = FaultInjector::new(rules, None).apply("block_request", frame(&block_request));
+    assert_eq!(
+        delivery.frames,
+        vec![[&2u32.to_le_bytes(), &frame(&block_request)[4..5]].concat()]
+    );
+
+    // Stalled within the length prefix, then truncated (the partial prefix is kept as is)
+    let rules = vec!["stall=2".parse().unwrap(), "truncate=1".parse().unwrap()];
+    let delivery = FaultInjector::new(rules, None).apply("block_request", frame(&block_request));
+    assert_eq!(delivery.frames, vec![frame(&block_request)[..2].to_vec()]);
+    assert_eq!(delivery.faults, vec![Fault::Stall(2), Fault::Truncate(1)]);
+
+    // Corrupted
+    let (mut connection, mut node) = faulty_connection(&["bitflip,type=block_request"]);
+    node.write_all(&frame(&block_request)).await.unwrap();
+    let corrupted = connection.read_message().await.unwrap();
+    assert_ne!(corrupted, block_request);
+
+    // Delayed
+    let (mut connection, mut node) = faulty_connection(&["delay=50,type=peers_request"]);
+    let start = Instant::now();
+    node.write_all(&frames).await.unwrap();
+    assert_eq!(connection.read_message().await.unwrap(), peers_request);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    // The following frames are not delivered before the delayed one
+    assert_eq!(connection.read_message().await.unwrap(), disconnect);
+}
+
+#[tokio::test]
+async fn test_fault_stall() {
+    let (mut connection, mut node) = faulty_connection(&["stall=6,type=disconnect"]);
+
+    let disconnect = PeerMessage::from(&Disconnect);
+    node.write_all(&frame(&disconnect)).await.unwrap();
+    node.write_all(&frame(&PeerMessage::from(&PeersRequest)))
+        .await
+        .unwrap();
+
+    // Nothing is delivered after the part of the stalled frame, so reading times out
+    assert_eq!(
+        connection
+            .read_message_with_timeout()
+            .await
+            .unwrap_err()
+            .kind(),
+        io::ErrorKind::TimedOut
+    );
+}
+
+#[test]
+fn test_codec() {
+    let disconnect = PeerMessage::from(&Disconnect);
+    let block_request = PeerMessage::from(&BlockRequest(CryptoHash::hash_bytes(b"block")));
+
+    let mut codec = PeerMessageCodec;
+    let mut buffer = BytesMut::new();
+    codec.encode(&block_request, &mut buffer).unwrap();
+    codec.encode(&disconnect, &mut buffer).unwrap();
+    assert_eq!(buffer, [frame(&block_request), frame(&disconnect)].concat());
+
+    // The frames are decoded only when complete
+    let mut input = buffer.split_off(3);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    buffer.unsplit(input.split_to(10));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    buffer.unsplit(input);
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(block_request));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(disconnect.clone()));
+    assert_eq!(codec.decode(&mut buffer).unwrap(), None);
+    assert!(buffer.is_empty());
+
+    // The frame that can't be parsed is skipped
+    buffer.extend_from_slice(&[1, 0, 0, 0, 0xff]);
+    codec.encode(&disconnect, &mut buffer).unwrap();
+    assert_eq!(
+        codec.decode(&mut buffer).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert_eq!(codec.decode(&mut buffer).unwrap(), Some(disconnect));
+}
+
+#[tokio::test]
+async fn test_oversized_frame() {
+    let prefix = (MAX_MESSAGE_LEN as u32 + 1).to_le_bytes();
+
+    // Rejected before the buffer for the message is reserved
+    let mut buffer = BytesMut::from(&prefix[..]);
+    assert_eq!(
+        PeerMessageCodec.decode(&mut buffer).unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+    assert!(buffer.capacity() < MAX_MESSAGE_LEN);
+
+    let (stream, mut node_stream) = tokio::io::duplex(1024);
+    let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+    let mut connection = Connection::new(stream, peer_id, 24567, Duration::from_secs(1));
+    node_stream.write_all(&prefix).await.unwrap();
+    assert_eq!(
+        connection.read_message().await.unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[tokio::test]
+async fn test_sink_stream() {
+    let (stream, node_stream) = tokio::io::duplex(1024);
+
+    let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+    let mut connection = Connection::new(stream, peer_id, 24567, Duration::from_secs(1));
+    let mut node = Framed::new(node_stream, PeerMessageCodec);
+
+    let messages = vec![
+        PeerMessage::from(&PeersRequest),
+        PeerMessage::from(&BlockRequest(CryptoHash::hash_bytes(b"block"))),
+        PeerMessage::from(&Disconnect),
+    ];
+
+    for msg in &messages {
+        connection.feed(msg.clone()).await.unwrap();
+    }
+    connection.flush().await.unwrap();
+    for msg in &messages {
+        assert_eq!(&node.next().await.unwrap().unwrap(), msg);
+    }
+
+    for msg in &messages {
+        node.send(msg.clone()).await.unwrap();
+    }
+    drop(node);
+
+    // The stream ends when the node closes the connection
+    let received: Vec<_> = connection.map(Result::unwrap).collect().await;
+    assert_eq!(received, messages);
+}
+
+#[tokio::test]
+async fn test_split() {
+    let (stream, node_stream) = tokio::io::duplex(1024);
+
+    let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+    let connection = Connection::new(stream, peer_id.clone(), 24567, Duration::from_secs(1));
+    let my_peer_id = connection.session.my_peer_id().clone();
+
+    let (mut reader, mut writer) = connection.into_split();
+    assert_eq!(reader.session().peer_id(), &peer_id);
+    assert_eq!(writer.session().my_peer_id(), &my_peer_id);
+
+    let (mut node_writer, mut node_reader) =
+        Framed::new(node_stream, PeerMessageCodec).split::<PeerMessage>();
+
+    // The node echoes the messages back
+    let node = tokio::spawn(async move {
+        while let Some(msg) = node_reader.next().await {
+            node_writer.send(msg.unwrap()).await.unwrap();
+        }
+    });
+
+    let messages: Vec<_> = (0..100)
+        .map(|i| PeerMessage::from(&BlockRequest(CryptoHash::hash_bytes(&[i]))))
+        .collect();
+
+    // The messages are read while the others are being written
+    let writing = tokio::spawn({
+        let messages = messages.clone();
+        async move {
+            for msg in messages {
+                writer.write_message(msg).await.unwrap();
+            }
+            writer
+        }
+    });
+
+    for msg in &messages {
+        assert_eq!(&reader.read_message().await.unwrap(), msg);
+    }
+
+    // The node stops when the connection is closed
+    writing.await.unwrap().close().await.unwrap();
+    node.await.unwrap();
+    assert!(reader.next().await.is_none());
+}
@@ -0,0 +1,187 @@
+use std::{fmt, io, process::ExitCode};
+
+use near_primitives::{network::PeerId, types::BlockHeight};
+
+use crate::network_protocol::{HandshakeFailure, NetworkError};
+
+/// Process exit codes reported for each failure category (0 means success, 2 is also
+/// used by the command line parser for invalid arguments)
+pub mod exit_code {
+    pub const CONFIG_ERROR: u8 = 2;
+    pub const CONNECT_TIMEOUT: u8 = 3;
+    pub const IO_ERROR: u8 = 4;
+    pub const INVALID_RESPONSE: u8 = 5;
+    pub const PROTOCOL_VERSION_MISMATCH: u8 = 6;
+    pub const GENESIS_MISMATCH: u8 = 7;
+    pub const INVALID_TARGET: u8 = 8;
+    pub const INVALID_SIGNATURE: u8 = 9;
+    pub const SENDER_MISMATCH: u8 = 10;
+    pub const TARGET_MISMATCH: u8 = 11;
+    pub const RESPONSE_GENESIS_MISMATCH: u8 = 12;
+    pub const RESPONSE_PROTOCOL_VERSION_MISMATCH: u8 = 13;
+    pub const PEER_BANNED: u8 = 14;
+    pub const FORK_DETECTED: u8 = 15;
+    pub const NOTHING_COMPARED: u8 = 16;
+    pub const READ_TIMEOUT: u8 = 17;
+}
+
+#[derive(Debug)]
+pub enum Error {
+    Config(String),
+    Network(NetworkError),
+    /// The peer is banned in the peer store (the connection is not attempted)
+    Banned {
+        peer_id: PeerId,
+        reason: String,
+    },
+    /// The nodes disagree on the blocks at some heights (height is the lowest of them)
+    Fork {
+        height: BlockHeight,
+        heights: usize,
+    },
+    /// No height was reported by more than one node, so the nodes can't be compared
+    NothingCompared {
+        answered: usize,
+        total: usize,
+    },
+    /// Some of the handshakes performed in batch mode failed (code is the exit code
+    /// of the first failed handshake)
+    Batch {
+        failed: usize,
+        total: usize,
+        code: u8,
+    },
+}
+
+impl Error {
+    pub fn exit_code(&self) -> ExitCode {
+        ExitCode::from(self.code())
+    }
+
+    pub fn code(&self) -> u8 {
+        use exit_code::*;
+
+        match self {
+            Self::Config(_) => CONFIG_ERROR,
+            Self::Batch { code, .. } => *code,
+            Self::Banned { .. } => PEER_BANNED,
+            Self::Fork { .. } => FORK_DETECTED,
+            Self::NothingCompared { .. } => NOTHING_COMPARED,
+            Self::Network(e) => match e {
+                NetworkError::ConnectTimeout => CONNECT_TIMEOUT,
+                // The connection is established, but the node doesn't respond in time (the
+                // connect timeouts, the ones reported by the OS included, are ConnectTimeout)
+                NetworkError::IO(e) if e.kind() == io::ErrorKind::TimedOut => READ_TIMEOUT,
+                NetworkError::IO(_) => IO_ERROR,
+                NetworkError::InvalidResponse | NetworkError::UnexpectedResponse => {
+                    INVALID_RESPONSE
+                }
+                NetworkError::InvalidSignature => INVALID_SIGNATURE,
+                NetworkError::SenderMismatch(_) => SENDER_MISMATCH,
+                NetworkError::TargetMismatch(_) => TARGET_MISMATCH,
+                NetworkError::GenesisMismatch(_) => RESPONSE_GENESIS_MISMATCH,
+                NetworkError::UnsupportedProtocolVersion(_) => RESPONSE_PROTOCOL_VERSION_MISMATCH,
+                NetworkError::HandshakeFailure(failure) => match failure {
+                    HandshakeFailure::ProtocolVersionMismatch { .. } => PROTOCOL_VERSION_MISMATCH,
+                    HandshakeFailure::GenesisMismatch(_) => GENESIS_MISMATCH,
+                    HandshakeFailure::InvalidTarget => INVALID_TARGET,
+                    HandshakeFailure::UnknownReason | HandshakeFailure::ParseHandshakeError(_) => {
+                        INVALID_RESPONSE
+                    }
+                },
+            },
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Config(msg) => write!(f, "{}", msg),
+            Self::Network(e) => write!(f, "Error establishing connection to node: {}", e),
+            Self::Banned { peer_id, reason } => write!(
+                f,
+                "Peer {} is banned ({}), use --ignore-bans to connect anyway",
+                peer_id, reason
+            ),
+            Self::Fork { height, heights } => write!(
+                f,
+                "Fork detected: the nodes disagree on {} heights starting from {}",
+                heights, height
+            ),
+            Self::NothingCompared { answered, total } => write!(
+                f,
+                "No heights reported by more than one node ({} of {} nodes answered)",
+                answered, total
+            ),
+            Self::Batch { failed, total, .. } => {
+                write!(f, "Handshake failed with {} of {} nodes", failed, total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Network(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<NetworkError> for Error {
+    fn from(value: NetworkError) -> Self {
+        Self::Network(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{net::TcpListener, time::Duration};
+
+    use near_crypto::{KeyType, SecretKey};
+
+    use near_primitives::network::PeerId;
+
+    use crate::{
+        connection::{ConnectOptions, TcpConnection},
+        DEFAULT_LISTEN_PORT,
+    };
+
+    use super::*;
+
+    #[tokio::test]
+    async fn test_timeout_exit_codes() {
+        let error = Error::from(NetworkError::ConnectTimeout);
+        assert_eq!(error.code(), exit_code::CONNECT_TIMEOUT);
+
+        // The connect timeout reported by the OS (ETIMEDOUT)
+        let error = Error::from(NetworkError::connect(io::ErrorKind::TimedOut.into()));
+        assert_eq!(error.code(), exit_code::CONNECT_TIMEOUT);
+        let error = Error::from(NetworkError::connect(
+            io::ErrorKind::ConnectionRefused.into(),
+        ));
+        assert_eq!(error.code(), exit_code::IO_ERROR);
+
+        // The node accepts the connection, but doesn't respond to the handshake
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let node = tokio::spawn(async move { listener.accept().await.unwrap() });
+
+        let peer_id = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+        let result = TcpConnection::connect(
+            addr,
+            peer_id,
+            DEFAULT_LISTEN_PORT,
+            Some(Default::default()),
+            0,
+            ConnectOptions::new(Duration::from_millis(100)),
+        )
+        .await;
+        let error = Error::from(result.err().unwrap());
+        assert_eq!(error.code(), exit_code::READ_TIMEOUT);
+
+        node.await.unwrap();
+    }
+}
@@ -0,0 +1,134 @@
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use near_primitives::{block::GenesisId, hash::CryptoHash, network::PeerId};
+
+use crate::error::Error;
+
+#[derive(Serialize, Deserialize, Clone)]
+struct CachedGenesisId {
+    chain_id: String,
+    hash: CryptoHash,
+}
+
+/// Persistent store of the genesis ids learned from the nodes (JSON file), keyed by the peer
+/// id and by the address of the node
+pub struct GenesisCache {
+    path: PathBuf,
+    entries: BTreeMap<String, CachedGenesisId>,
+    /// Keys inserted since the cache was loaded or saved
+    modified: BTreeSet<String>,
+}
+
+impl GenesisCache {
+    /// Default location of the cache file (~/.cache/near-handshake/genesis_cache.json)
+    pub fn default_path() -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(std::env::var("HOME").map_err(|_| {
+            Error::Config(
+                "HOME environment variable not set (required to locate genesis cache file)".into(),
+            )
+        })?)
+        .join(".cache/near-handshake/genesis_cache.json"))
+    }
+
+    /// Loads the cache from the file (a missing or corrupt file means an empty cache)
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            path: path.into(),
+            entries: Self::read_entries(path)?,
+            modified: BTreeSet::new(),
+        })
+    }
+
+    fn read_entries(path: &Path) -> Result<BTreeMap<String, CachedGenesisId>, Error> {
+        Ok(match fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Error parsing genesis cache file {}, starting with an empty cache: {}",
+                    path.display(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(_) => {
+                return Err(Error::Config(format!(
+                    "Error opening genesis cache file: {}",
+                    path.display()
+                )))
+            }
+        })
+    }
+
+    /// Writes the cache to the file (if it was modified). The entries inserted since loading
+    /// are merged into the current file contents, so the entries saved meanwhile by other
+    /// runs are kept. The file is replaced atomically, so an interrupted write doesn't
+    /// corrupt it.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if self.modified.is_empty() {
+            return Ok(());
+        }
+
+        let error = || {
+            Error::Config(format!(
+                "Error writing genesis cache file: {}",
+                self.path.display()
+            ))
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|_| error())?;
+        }
+
+        let mut entries = Self::read_entries(&self.path)?;
+        for key in &self.modified {
+            entries.insert(key.clone(), self.entries[key].clone());
+        }
+
+        let tmp_path = self
+            .path
+            .with_extension(format!("json.{}.tmp", std::process::id()));
+        fs::write(&tmp_path, serde_json::to_string_pretty(&entries).unwrap())
+            .map_err(|_| error())?;
+        fs::rename(&tmp_path, &self.path).map_err(|_| error())?;
+
+        self.entries = entries;
+        self.modified.clear();
+
+        Ok(())
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Looks up the genesis id of the node by its peer id first, then by its address
+    pub fn get(&self, peer_id: &PeerId, addr: &SocketAddr) -> Option<GenesisId> {
+        self.entries
+            .get(&peer_id.to_string())
+            .or_else(|| self.entries.get(&addr.to_string()))
+            .map(|cached| GenesisId {
+                chain_id: cached.chain_id.clone(),
+                hash: cached.hash,
+            })
+    }
+
+    pub fn insert(&mut self, peer_id: &PeerId, addr: &SocketAddr, genesis_id: &GenesisId) {
+        for key in [peer_id.to_string(), addr.to_string()] {
+            self.entries.insert(
+                key.clone(),
+                CachedGenesisId {
+                    chain_id: genesis_id.chain_id.clone(),
+                    hash: genesis_id.hash,
+                },
+            );
+            self.modified.insert(key);
+        }
+    }
+}
@@ -0,0 +1,131 @@
+use std::{collections::BTreeMap, iter};
+
+use rand_core::{RngCore, SeedableRng};
+use rand_hc::Hc128Rng;
+
+use borsh::BorshSerialize;
+
+use near_primitives::{
+    hash::CryptoHash,
+    types::{validator_stake::ValidatorStake, AccountInfo, Balance, NumSeats},
+    version::{ProtocolFeature, ProtocolVersion},
+};
+
+/// Finds the maximum stake threshold such that the validators get at least `num_seats` seats
+/// in total (a validator gets stake / threshold seats)
+fn find_threshold(stakes: &[Balance], num_seats: NumSeats) -> Option<Balance> {
+    let stake_sum: Balance = stakes.iter().sum();
+    if stake_sum < num_seats.into() {
+        return None;
+    }
+
+    let (mut left, mut right): (Balance, Balance) = (1, stake_sum + 1);
+    'outer: loop {
+        if left == right - 1 {
+            break Some(left);
+        }
+        let mid = (left + right) / 2;
+        let mut current_sum: Balance = 0;
+        for stake in stakes {
+            current_sum += stake / mid;
+            if current_sum >= num_seats.into() {
+                left = mid;
+                continue 'outer;
+            }
+        }
+        right = mid;
+    }
+}
+
+/// Copy of the (old) rand crate index generation used by nearcore to shuffle the seats
+fn gen_index(rng: &mut Hc128Rng, bound: u64) -> u64 {
+    let zone = (bound << bound.leading_zeros()).wrapping_sub(1);
+    loop {
+        let v = rng.next_u64();
+        let mul = (v as u128) * (bound as u128);
+        let (hi, lo) = ((mul >> 64) as u64, mul as u64);
+        if lo <= zone {
+            return hi;
+        }
+    }
+}
+
+fn shuffle_seats(seats: &mut [usize], rng_seed: [u8; 32]) {
+    let mut rng = Hc128Rng::from_seed(rng_seed);
+    for i in (1..seats.len()).rev() {
+        seats.swap(i, gen_index(&mut rng, (i + 1) as u64) as usize);
+    }
+}
+
+/// Selects the block producers of the first epoch from the genesis validators (in the
+/// order nearcore epoch manager returns them)
+pub fn genesis_block_producers(
+    validators: &[AccountInfo],
+    num_block_producer_seats: NumSeats,
+    num_hidden_validator_seats: NumSeats,
+) -> Option<Vec<ValidatorStake>> {
+    let ordered_validators: BTreeMap<_, _> = validators
+        .iter()
+        .map(|validator| (&validator.account_id, validator))
+        .collect();
+
+    let stakes: Vec<Balance> = ordered_validators.values().map(|v| v.amount).collect();
+    let threshold = find_threshold(
+        &stakes,
+        num_block_producer_seats + num_hidden_validator_seats,
+    )?;
+
+    let final_validators: Vec<&AccountInfo> = ordered_validators
+        .into_values()
+        .filter(|validator| validator.amount >= threshold)
+        .collect();
+
+    // Each validator gets the number of seats proportional to its stake
+    let mut seats: Vec<usize> = final_validators
+        .iter()
+        .enumerate()
+        .flat_map(|(i, validator)| iter::repeat_n(i, (validator.amount / threshold) as usize))
+        .collect();
+
+    // Genesis epoch uses the zero random seed
+    shuffle_seats(&mut seats, [0; 32]);
+
+    let mut block_producers: Vec<usize> = Vec::new();
+    for i in seats.into_iter().take(num_block_producer_seats as usize) {
+        if !block_producers.contains(&i) {
+            block_producers.push(i);
+        }
+    }
+
+    Some(
+        block_producers
+            .into_iter()
+            .map(|i| {
+                let validator = final_validators[i];
+                ValidatorStake::new(
+                    validator.account_id.clone(),
+                    validator.public_key.clone(),
+                    validator.amount,
+                )
+            })
+            .collect(),
+    )
+}
+
+/// Computes the hash of the block producers (next_bp_hash of the genesis block header)
+pub fn compute_bp_hash(
+    block_producers: Vec<ValidatorStake>,
+    protocol_version: ProtocolVersion,
+) -> CryptoHash {
+    let data = if protocol_version >= ProtocolFeature::BlockHeaderV3.protocol_version() {
+        block_producers.try_to_vec()
+    } else {
+        block_producers
+            .into_iter()
+            .map(ValidatorStake::into_v1)
+            .collect::<Vec<_>>()
+            .try_to_vec()
+    };
+
+    CryptoHash::hash_bytes(&data.unwrap())
+}
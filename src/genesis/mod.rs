@@ -0,0 +1,110 @@
+pub mod cache;
+mod epoch;
+pub mod preset;
+mod state;
+mod trie;
+
+#[cfg(test)]
+mod tests;
+
+use std::{fs, io, path::Path};
+
+use serde::Deserialize;
+
+use chrono::{DateTime, Utc};
+
+use near_primitives::{
+    block::{genesis_chunks, Block, GenesisId},
+    serialize::dec_format,
+    shard_layout::ShardLayout,
+    state_record::StateRecord,
+    types::{AccountInfo, Balance, BlockHeight, Gas, NumSeats},
+    version::ProtocolVersion,
+};
+
+use crate::error::Error;
+
+/// Subset of the genesis config (genesis.json) fields used to build the genesis block
+#[derive(Deserialize)]
+pub struct Genesis {
+    pub chain_id: String,
+    pub protocol_version: ProtocolVersion,
+    pub genesis_time: DateTime<Utc>,
+    pub genesis_height: BlockHeight,
+    pub gas_limit: Gas,
+    #[serde(with = "dec_format")]
+    pub min_gas_price: Balance,
+    #[serde(with = "dec_format")]
+    pub total_supply: Balance,
+    pub num_block_producer_seats: NumSeats,
+    #[serde(default)]
+    pub avg_hidden_validator_seats_per_shard: Vec<NumSeats>,
+    pub validators: Vec<AccountInfo>,
+    #[serde(default = "ShardLayout::v0_single_shard")]
+    pub shard_layout: ShardLayout,
+    /// Required: the genesis with the records in a separate records file isn't supported
+    /// (its state, thus its hash, can't be computed from genesis.json alone)
+    pub records: Vec<StateRecord>,
+}
+
+impl Genesis {
+    pub fn from_file(file_path: &Path) -> Result<Self, Error> {
+        serde_json::from_reader(io::BufReader::new(fs::File::open(file_path).map_err(
+            |_| {
+                Error::Config(format!(
+                    "Error opening genesis config file: {}",
+                    file_path.display()
+                ))
+            },
+        )?))
+        .map_err(|e| {
+            Error::Config(format!(
+                "Error parsing genesis config file: {} ({})",
+                file_path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Builds the genesis block the same way nearcore does: applies the genesis records to
+    /// get the state roots of the shards, makes the genesis chunks and selects the block
+    /// producers of the first epoch
+    pub fn genesis_block(&self) -> Result<Block, Error> {
+        let state_roots =
+            state::compute_state_roots(&self.records, &self.validators, &self.shard_layout);
+
+        let chunks = genesis_chunks(
+            state_roots,
+            self.shard_layout.num_shards(),
+            self.gas_limit,
+            self.genesis_height,
+            self.protocol_version,
+        );
+
+        let block_producers = epoch::genesis_block_producers(
+            &self.validators,
+            self.num_block_producer_seats,
+            self.avg_hidden_validator_seats_per_shard.iter().sum(),
+        )
+        .ok_or_else(|| {
+            Error::Config("Not enough validator stake in genesis to fill the seats".into())
+        })?;
+
+        Ok(Block::genesis(
+            self.protocol_version,
+            chunks.iter().map(|chunk| chunk.cloned_header()).collect(),
+            self.genesis_time,
+            self.genesis_height,
+            self.min_gas_price,
+            self.total_supply,
+            epoch::compute_bp_hash(block_producers, self.protocol_version),
+        ))
+    }
+
+    pub fn genesis_id(&self) -> Result<GenesisId, Error> {
+        Ok(GenesisId {
+            chain_id: self.chain_id.clone(),
+            hash: *self.genesis_block()?.hash(),
+        })
+    }
+}
@@ -0,0 +1,24 @@
+use std::str::FromStr;
+
+use near_primitives::{block::GenesisId, hash::CryptoHash};
+
+/// Well-known NEAR networks
+#[derive(Clone, Copy, Debug, clap::ValueEnum)]
+pub enum ChainPreset {
+    Mainnet,
+    Testnet,
+}
+
+impl ChainPreset {
+    pub fn genesis_id(&self) -> GenesisId {
+        let (chain_id, hash) = match self {
+            Self::Mainnet => ("mainnet", "EPnLgE7iEq9s7yTkos96M3cWymH5avBAPm3qx3NXqR8H"),
+            Self::Testnet => ("testnet", "FWJ9kR6KFWoyMoNjpLXXGHeuiy7tEY6GmoFeCA5yuc6b"),
+        };
+
+        GenesisId {
+            chain_id: chain_id.into(),
+            hash: CryptoHash::from_str(hash).unwrap(),
+        }
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, HashMap};
+
+use borsh::BorshSerialize;
+
+use near_primitives::{
+    account::Account,
+    receipt::{DelayedReceiptIndices, Receipt, ReceiptEnum, ReceivedData},
+    shard_layout::{account_id_to_shard_id, ShardLayout},
+    state_record::{state_record_to_account_id, StateRecord},
+    trie_key::TrieKey,
+    types::{AccountId, AccountInfo, ShardId, StateRoot, StorageUsage},
+};
+
+use super::trie;
+
+// Storage usage config of the runtime (the values are the same for all protocol versions)
+const NUM_BYTES_ACCOUNT: StorageUsage = 100;
+const NUM_EXTRA_BYTES_RECORD: StorageUsage = 40;
+
+/// State of a single shard built from the genesis records, the same way nearcore runtime
+/// applies the genesis
+#[derive(Default)]
+struct ShardState {
+    entries: BTreeMap<Vec<u8>, Vec<u8>>,
+    // Accounts are stored to the trie at the end, after their storage usage is calculated
+    accounts: BTreeMap<AccountId, Account>,
+    storage_usage: HashMap<AccountId, StorageUsage>,
+    delayed_receipts: Vec<Receipt>,
+}
+
+impl ShardState {
+    fn set<T: BorshSerialize>(&mut self, key: TrieKey, value: &T) {
+        self.entries
+            .insert(key.to_vec(), value.try_to_vec().unwrap());
+    }
+
+    fn add_storage_usage(&mut self, account_id: &AccountId, storage_usage: StorageUsage) {
+        *self.storage_usage.entry(account_id.clone()).or_default() += storage_usage;
+    }
+
+    fn apply_record(&mut self, record: &StateRecord) {
+        match record {
+            StateRecord::Account {
+                account_id,
+                account,
+            } => {
+                self.add_storage_usage(account_id, NUM_BYTES_ACCOUNT);
+                self.accounts.insert(account_id.clone(), account.clone());
+            }
+
+            StateRecord::Data {
+                account_id,
+                data_key,
+                value,
+            } => {
+                self.add_storage_usage(
+                    account_id,
+                    NUM_EXTRA_BYTES_RECORD + data_key.len() as u64 + value.len() as u64,
+                );
+                self.entries.insert(
+                    TrieKey::ContractData {
+                        account_id: account_id.clone(),
+                        key: data_key.clone(),
+                    }
+                    .to_vec(),
+                    value.clone(),
+                );
+            }
+
+            StateRecord::Contract { account_id, code } => {
+                self.add_storage_usage(account_id, code.len() as u64);
+                self.entries.insert(
+                    TrieKey::ContractCode {
+                        account_id: account_id.clone(),
+                    }
+                    .to_vec(),
+                    code.clone(),
+                );
+            }
+
+            StateRecord::AccessKey {
+                account_id,
+                public_key,
+                access_key,
+            } => {
+                self.add_storage_usage(
+                    account_id,
+                    NUM_EXTRA_BYTES_RECORD
+                        + public_key.try_to_vec().unwrap().len() as u64
+                        + access_key.try_to_vec().unwrap().len() as u64,
+                );
+                self.set(
+                    TrieKey::AccessKey {
+                        account_id: account_id.clone(),
+                        public_key: public_key.clone(),
+                    },
+                    access_key,
+                );
+            }
+
+            StateRecord::ReceivedData {
+                account_id,
+                data_id,
+                data,
+            } => self.set(
+                TrieKey::ReceivedData {
+                    receiver_id: account_id.clone(),
+                    data_id: *data_id,
+                },
+                &ReceivedData { data: data.clone() },
+            ),
+
+            StateRecord::PostponedReceipt(receipt) => self.apply_postponed_receipt(receipt),
+
+            StateRecord::DelayedReceipt(receipt) => {
+                self.delayed_receipts.push(receipt.as_ref().clone())
+            }
+        }
+    }
+
+    fn apply_postponed_receipt(&mut self, receipt: &Receipt) {
+        let receiver_id = &receipt.receiver_id;
+
+        let mut pending_data_count: u32 = 0;
+        if let ReceiptEnum::Action(action_receipt) = &receipt.receipt {
+            for data_id in &action_receipt.input_data_ids {
+                let received_data_key = TrieKey::ReceivedData {
+                    receiver_id: receiver_id.clone(),
+                    data_id: *data_id,
+                };
+                if !self.entries.contains_key(&received_data_key.to_vec()) {
+                    pending_data_count += 1;
+                    self.set(
+                        TrieKey::PostponedReceiptId {
+                            receiver_id: receiver_id.clone(),
+                            data_id: *data_id,
+                        },
+                        &receipt.receipt_id,
+                    );
+                }
+            }
+        }
+
+        self.set(
+            TrieKey::PendingDataCount {
+                receiver_id: receiver_id.clone(),
+                receipt_id: receipt.receipt_id,
+            },
+            &pending_data_count,
+        );
+        self.set(
+            TrieKey::PostponedReceipt {
+                receiver_id: receiver_id.clone(),
+                receipt_id: receipt.receipt_id,
+            },
+            receipt,
+        );
+    }
+
+    fn finalize(mut self, validators: &[&AccountInfo]) -> StateRoot {
+        for (account_id, storage_usage) in &self.storage_usage {
+            if let Some(account) = self.accounts.get_mut(account_id) {
+                account.set_storage_usage(*storage_usage);
+            }
+        }
+
+        for validator in validators {
+            if let Some(account) = self.accounts.get_mut(&validator.account_id) {
+                account.set_locked(validator.amount);
+            }
+        }
+
+        for (account_id, account) in std::mem::take(&mut self.accounts) {
+            self.set(TrieKey::Account { account_id }, &account);
+        }
+
+        if !self.delayed_receipts.is_empty() {
+            let delayed_receipts = std::mem::take(&mut self.delayed_receipts);
+            for (index, receipt) in delayed_receipts.iter().enumerate() {
+                self.set(
+                    TrieKey::DelayedReceipt {
+                        index: index as u64,
+                    },
+                    receipt,
+                );
+            }
+            self.set(
+                TrieKey::DelayedReceiptIndices,
+                &DelayedReceiptIndices {
+                    first_index: 0,
+                    next_available_index: delayed_receipts.len() as u64,
+                },
+            );
+        }
+
+        trie::compute_state_root(&self.entries)
+    }
+}
+
+/// Computes the state roots of all shards of the genesis state
+pub fn compute_state_roots(
+    records: &[StateRecord],
+    validators: &[AccountInfo],
+    shard_layout: &ShardLayout,
+) -> Vec<StateRoot> {
+    let shard_of =
+        |account_id: &AccountId| -> ShardId { account_id_to_shard_id(account_id, shard_layout) };
+
+    let mut shards: Vec<ShardState> = (0..shard_layout.num_shards())
+        .map(|_| ShardState::default())
+        .collect();
+
+    for record in records {
+        shards[shard_of(state_record_to_account_id(record)) as usize].apply_record(record);
+    }
+
+    shards
+        .into_iter()
+        .enumerate()
+        .map(|(shard_id, shard)| {
+            let validators: Vec<&AccountInfo> = validators
+                .iter()
+                .filter(|validator| shard_of(&validator.account_id) == shard_id as ShardId)
+                .collect();
+            shard.finalize(&validators)
+        })
+        .collect()
+}
@@ -0,0 +1,219 @@
+use std::{collections::BTreeMap, fs, path::Path, str::FromStr};
+
+use clap::ValueEnum;
+
+use near_crypto::{ED25519PublicKey, PublicKey};
+
+use near_primitives::{hash::CryptoHash, network::PeerId};
+
+use super::{cache::GenesisCache, preset::ChainPreset, trie, Genesis};
+
+const GENESIS_JSON: &str = r#"{
+    "protocol_version": 57,
+    "genesis_time": "2022-12-01T10:00:00.000000Z",
+    "chain_id": "localnet",
+    "genesis_height": 0,
+    "num_block_producer_seats": 50,
+    "num_block_producer_seats_per_shard": [50],
+    "avg_hidden_validator_seats_per_shard": [0],
+    "gas_limit": 1000000000000000,
+    "min_gas_price": "100000000",
+    "total_supply": "2050000000000000000000000000000000",
+    "validators": [
+        {
+            "account_id": "test.near",
+            "public_key": "ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX",
+            "amount": "50000000000000000000000000000000"
+        }
+    ],
+    "records": [
+        {
+            "Account": {
+                "account_id": "test.near",
+                "account": {
+                    "amount": "1000000000000000000000000000000000",
+                    "locked": "50000000000000000000000000000000",
+                    "code_hash": "11111111111111111111111111111111",
+                    "storage_usage": 0,
+                    "version": "V1"
+                }
+            }
+        },
+        {
+            "AccessKey": {
+                "account_id": "test.near",
+                "public_key": "ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX",
+                "access_key": { "nonce": 0, "permission": "FullAccess" }
+            }
+        }
+    ]
+}"#;
+
+/// Hash of the genesis above as computed by this implementation, pinned so that any change
+/// of the computation is noticed (the computation is checked against neard itself by
+/// `test_neard_fixtures` and `test_neard_genesis_id`)
+const GENESIS_HASH: &str = "2g7NwWSSagXaBALNQnNgMb4JogQisie62kXudComyJRp";
+
+#[test]
+fn test_state_root() {
+    let mut entries = BTreeMap::new();
+    assert_eq!(trie::compute_state_root(&entries), CryptoHash::default());
+
+    entries.insert(b"abc".to_vec(), b"1".to_vec());
+    let root1 = trie::compute_state_root(&entries);
+    assert_ne!(root1, CryptoHash::default());
+
+    // A key which is a prefix of another one, and keys diverging in the middle of a byte
+    entries.insert(b"abcd".to_vec(), b"2".to_vec());
+    entries.insert(b"abcf".to_vec(), b"3".to_vec());
+    entries.insert(b"b".to_vec(), b"4".to_vec());
+    let root2 = trie::compute_state_root(&entries);
+    assert_ne!(root1, root2);
+
+    // The root depends on the values, not only on the keys
+    entries.insert(b"b".to_vec(), b"5".to_vec());
+    assert_ne!(root2, trie::compute_state_root(&entries));
+}
+
+#[test]
+fn test_genesis_id() {
+    let genesis: Genesis = serde_json::from_str(GENESIS_JSON).unwrap();
+
+    let genesis_id = genesis.genesis_id().unwrap();
+    assert_eq!(genesis_id.chain_id, "localnet");
+    assert_eq!(genesis_id.hash, CryptoHash::from_str(GENESIS_HASH).unwrap());
+    assert_eq!(genesis_id, genesis.genesis_id().unwrap());
+
+    // Genesis with another state must have another hash
+    let mut other_genesis: Genesis = serde_json::from_str(GENESIS_JSON).unwrap();
+    other_genesis.records.pop();
+    assert_ne!(genesis_id.hash, other_genesis.genesis_id().unwrap().hash);
+
+    // Genesis with the records in a separate file
+    let mut json: serde_json::Value = serde_json::from_str(GENESIS_JSON).unwrap();
+    json.as_object_mut().unwrap().remove("records");
+    let error = serde_json::from_value::<Genesis>(json).err().unwrap();
+    assert!(error.to_string().contains("missing field `records`"));
+}
+
+/// Checks the genesis ids computed from the genesis config files generated by neard against
+/// the genesis hashes neard reported for them: each directory in src/genesis/testdata holds a
+/// genesis.json (e.g. of `neard init --chain-id localnet`) and a genesis_hash file
+#[test]
+fn test_neard_fixtures() {
+    let testdata = Path::new(env!("CARGO_MANIFEST_DIR")).join("src/genesis/testdata");
+    let fixtures = match fs::read_dir(&testdata) {
+        Ok(entries) => entries.map(|entry| entry.unwrap().path()),
+        Err(_) => {
+            eprintln!("no neard genesis fixtures in {}", testdata.display());
+            return;
+        }
+    };
+
+    for fixture in fixtures.filter(|path| path.is_dir()) {
+        let genesis = Genesis::from_file(&fixture.join("genesis.json")).unwrap();
+        let hash = fs::read_to_string(fixture.join("genesis_hash")).unwrap();
+        assert_eq!(
+            genesis.genesis_id().unwrap().hash,
+            CryptoHash::from_str(hash.trim()).unwrap(),
+            "genesis hash of {}",
+            fixture.display()
+        );
+    }
+}
+
+/// Checks the genesis id computed from a genesis config file of neard against the genesis
+/// hash neard reports for it (e.g. "genesis_hash" of the "status" RPC): the file is given by
+/// NEAR_GENESIS_FILE and the hash by NEAR_GENESIS_HASH. For the genesis of mainnet or testnet
+/// the hash may be omitted, the id must match the one of the preset then.
+#[test]
+#[ignore = "requires a genesis file of neard (NEAR_GENESIS_FILE and NEAR_GENESIS_HASH)"]
+fn test_neard_genesis_id() {
+    let path = std::env::var("NEAR_GENESIS_FILE").expect("NEAR_GENESIS_FILE not set");
+    let genesis = Genesis::from_file(Path::new(&path)).unwrap();
+    let genesis_id = genesis.genesis_id().unwrap();
+
+    if let Ok(preset) = ChainPreset::from_str(&genesis.chain_id, false) {
+        assert_eq!(genesis_id, preset.genesis_id());
+    }
+
+    match std::env::var("NEAR_GENESIS_HASH") {
+        Ok(hash) => assert_eq!(genesis_id.hash, CryptoHash::from_str(&hash).unwrap()),
+        Err(_) => assert!(
+            ChainPreset::from_str(&genesis.chain_id, false).is_ok(),
+            "NEAR_GENESIS_HASH not set"
+        ),
+    }
+}
+
+#[test]
+fn test_chain_presets() {
+    for preset in ChainPreset::value_variants() {
+        let name = preset.to_possible_value().unwrap();
+        let genesis_id = preset.genesis_id();
+        assert_eq!(genesis_id.chain_id, name.get_name());
+        assert_ne!(genesis_id.hash, CryptoHash::default());
+    }
+    assert_ne!(
+        ChainPreset::Mainnet.genesis_id().hash,
+        ChainPreset::Testnet.genesis_id().hash
+    );
+}
+
+#[test]
+fn test_genesis_cache() {
+    let dir = std::env::temp_dir().join(format!("near-handshake-test-{}", std::process::id()));
+    let path = dir.join("genesis_cache.json");
+
+    let peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([1u8; 32])));
+    let other_peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([2u8; 32])));
+    let addr = "127.0.0.1:24567".parse().unwrap();
+    let other_addr = "127.0.0.1:24568".parse().unwrap();
+
+    let genesis_id = ChainPreset::Testnet.genesis_id();
+
+    // A missing file means an empty cache
+    let mut cache = GenesisCache::load(&path).unwrap();
+    assert_eq!(cache.get(&peer_id, &addr), None);
+
+    cache.insert(&peer_id, &addr, &genesis_id);
+    cache.save().unwrap();
+
+    let cache = GenesisCache::load(&path).unwrap();
+    assert_eq!(cache.get(&peer_id, &other_addr), Some(genesis_id.clone()));
+    assert_eq!(cache.get(&other_peer_id, &addr), Some(genesis_id.clone()));
+    assert_eq!(cache.get(&other_peer_id, &other_addr), None);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), 1);
+
+    // The caches loaded by concurrent runs keep the entries saved by each other
+    let mut cache = GenesisCache::load(&path).unwrap();
+    let mut other_cache = GenesisCache::load(&path).unwrap();
+    let mainnet_genesis_id = ChainPreset::Mainnet.genesis_id();
+    cache.insert(&other_peer_id, &other_addr, &mainnet_genesis_id);
+    other_cache.insert(&peer_id, &addr, &mainnet_genesis_id);
+    cache.save().unwrap();
+    other_cache.save().unwrap();
+
+    let cache = GenesisCache::load(&path).unwrap();
+    assert_eq!(cache.get(&peer_id, &addr), Some(mainnet_genesis_id.clone()));
+    assert_eq!(
+        cache.get(&other_peer_id, &other_addr),
+        Some(mainnet_genesis_id)
+    );
+
+    // A corrupt file means an empty cache, overwritten on the next save
+    fs::write(&path, "{\"truncated").unwrap();
+    let mut cache = GenesisCache::load(&path).unwrap();
+    assert_eq!(cache.get(&peer_id, &addr), None);
+
+    cache.insert(&other_peer_id, &other_addr, &genesis_id);
+    cache.save().unwrap();
+    assert_eq!(
+        GenesisCache::load(&path)
+            .unwrap()
+            .get(&other_peer_id, &other_addr),
+        Some(genesis_id)
+    );
+
+    fs::remove_dir_all(dir).unwrap();
+}
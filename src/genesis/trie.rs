@@ -0,0 +1,165 @@
+use std::collections::BTreeMap;
+
+use near_primitives::hash::CryptoHash;
+
+// Node types of the raw (serialized) trie node
+const LEAF_NODE: u8 = 0;
+const BRANCH_NODE_NO_VALUE: u8 = 1;
+const BRANCH_NODE_WITH_VALUE: u8 = 2;
+const EXTENSION_NODE: u8 = 3;
+
+// Costs used by nearcore to calculate the memory usage of trie nodes (the memory usage is
+// a part of the serialized node, so it affects the node hash)
+const BYTE_OF_KEY_COST: u64 = 2;
+const BYTE_OF_VALUE_COST: u64 = 1;
+const NODE_COST: u64 = 50;
+
+/// Hash and memory usage of a trie node (including all its children)
+struct Node {
+    hash: CryptoHash,
+    memory_usage: u64,
+}
+
+/// Computes the root of the nearcore state trie (Merkle Patricia trie) containing the given
+/// key-value pairs (the root of the empty trie is the default hash)
+pub fn compute_state_root(entries: &BTreeMap<Vec<u8>, Vec<u8>>) -> CryptoHash {
+    let entries: Vec<(Vec<u8>, &[u8])> = entries
+        .iter()
+        .map(|(key, value)| (to_nibbles(key), value.as_slice()))
+        .collect();
+
+    build(&entries, 0).map_or_else(CryptoHash::default, |node| node.hash)
+}
+
+fn to_nibbles(key: &[u8]) -> Vec<u8> {
+    key.iter()
+        .flat_map(|byte| [byte >> 4, byte & 0x0f])
+        .collect()
+}
+
+/// Encodes nibbles the same way nearcore NibbleSlice does (hex-prefix encoding)
+fn encode_nibbles(nibbles: &[u8], is_leaf: bool) -> Vec<u8> {
+    let odd = nibbles.len() % 2;
+
+    let mut data = Vec::with_capacity(nibbles.len() / 2 + 1);
+    data.push(if odd == 1 { 0x10 + nibbles[0] } else { 0 } + if is_leaf { 0x20 } else { 0 });
+    data.extend(nibbles[odd..].chunks(2).map(|pair| pair[0] * 16 + pair[1]));
+
+    data
+}
+
+fn value_memory_usage(value: &[u8]) -> u64 {
+    value.len() as u64 * BYTE_OF_VALUE_COST + NODE_COST
+}
+
+fn make_node(mut data: Vec<u8>, memory_usage: u64) -> Node {
+    data.extend(memory_usage.to_le_bytes());
+
+    Node {
+        hash: CryptoHash::hash_bytes(&data),
+        memory_usage,
+    }
+}
+
+fn write_key(data: &mut Vec<u8>, key: &[u8]) {
+    data.extend((key.len() as u32).to_le_bytes());
+    data.extend(key);
+}
+
+fn write_value(data: &mut Vec<u8>, value: &[u8]) {
+    data.extend((value.len() as u32).to_le_bytes());
+    data.extend(CryptoHash::hash_bytes(value).as_bytes());
+}
+
+/// Builds the (sub)trie of the sorted entries, all of them share the first `depth` nibbles
+fn build(entries: &[(Vec<u8>, &[u8])], depth: usize) -> Option<Node> {
+    match entries {
+        [] => None,
+
+        [(key, value)] => {
+            let key = encode_nibbles(&key[depth..], true);
+
+            let mut data = vec![LEAF_NODE];
+            write_key(&mut data, &key);
+            write_value(&mut data, value);
+
+            let memory_usage =
+                NODE_COST + key.len() as u64 * BYTE_OF_KEY_COST + value_memory_usage(value);
+
+            Some(make_node(data, memory_usage))
+        }
+
+        _ => {
+            // Entries are sorted, so the common prefix of all of them is the common prefix
+            // of the first and the last ones
+            let (first, last) = (
+                &entries[0].0[depth..],
+                &entries[entries.len() - 1].0[depth..],
+            );
+            let prefix_len = first.iter().zip(last).take_while(|(a, b)| a == b).count();
+
+            if prefix_len == 0 {
+                return Some(build_branch(entries, depth));
+            }
+
+            let key = encode_nibbles(&first[..prefix_len], false);
+            let child = build_branch(entries, depth + prefix_len);
+
+            let mut data = vec![EXTENSION_NODE];
+            write_key(&mut data, &key);
+            data.extend(child.hash.as_bytes());
+
+            let memory_usage = NODE_COST + key.len() as u64 * BYTE_OF_KEY_COST + child.memory_usage;
+
+            Some(make_node(data, memory_usage))
+        }
+    }
+}
+
+fn build_branch(entries: &[(Vec<u8>, &[u8])], depth: usize) -> Node {
+    // At most one (the first, as entries are sorted) key can end at the branch
+    let (value, entries) = match entries.split_first() {
+        Some(((key, value), rest)) if key.len() == depth => (Some(*value), rest),
+        _ => (None, entries),
+    };
+
+    let mut children: [Option<Node>; 16] = Default::default();
+
+    let mut start = 0;
+    while start < entries.len() {
+        let nibble = entries[start].0[depth];
+        let end = start
+            + entries[start..]
+                .iter()
+                .take_while(|(key, _)| key[depth] == nibble)
+                .count();
+        children[nibble as usize] = build(&entries[start..end], depth + 1);
+        start = end;
+    }
+
+    let mut data = Vec::new();
+    let mut memory_usage = NODE_COST;
+
+    match value {
+        Some(value) => {
+            data.push(BRANCH_NODE_WITH_VALUE);
+            write_value(&mut data, value);
+            memory_usage += value_memory_usage(value);
+        }
+        None => data.push(BRANCH_NODE_NO_VALUE),
+    }
+
+    let bitmap = children
+        .iter()
+        .enumerate()
+        .filter(|(_, child)| child.is_some())
+        .fold(0u16, |bitmap, (i, _)| bitmap | (1 << i));
+    data.extend(bitmap.to_le_bytes());
+
+    for child in children.iter().flatten() {
+        data.extend(child.hash.as_bytes());
+        memory_usage += child.memory_usage;
+    }
+
+    make_node(data, memory_usage)
+}
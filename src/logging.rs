@@ -0,0 +1,52 @@
+use tracing::level_filters::LevelFilter;
+
+use tracing_subscriber::{filter::Targets, fmt, layer::SubscriberExt, util::SubscriberInitExt};
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+pub enum LogFormat {
+    /// Human readable lines
+    Text,
+    /// One JSON object per line
+    Json,
+}
+
+/// Command line args controlling the log output (the logs are written to stderr)
+#[derive(clap::Args)]
+pub struct LogArgs {
+    /// Verbose logging (-v for debug logs, -vv for trace logs including every message
+    /// sent and received)
+    #[clap(short = 'v', long, action = clap::ArgAction::Count, global = true, verbatim_doc_comment)]
+    verbose: u8,
+
+    /// Log output format
+    #[clap(long, value_enum, default_value = "text", global = true)]
+    log_format: LogFormat,
+}
+
+impl LogArgs {
+    pub fn init(&self) {
+        let level = match self.verbose {
+            0 => LevelFilter::WARN,
+            1 => LevelFilter::DEBUG,
+            _ => LevelFilter::TRACE,
+        };
+
+        // Verbose logs only of this crate, not of the dependencies
+        let filter = Targets::new()
+            .with_target(env!("CARGO_CRATE_NAME"), level)
+            .with_default(LevelFilter::WARN);
+
+        let layer = fmt::layer().with_writer(std::io::stderr);
+
+        match self.log_format {
+            LogFormat::Text => tracing_subscriber::registry()
+                .with(layer)
+                .with(filter)
+                .init(),
+            LogFormat::Json => tracing_subscriber::registry()
+                .with(layer.json().with_current_span(true).with_span_list(true))
+                .with(filter)
+                .init(),
+        }
+    }
+}
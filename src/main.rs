@@ -1,130 +1,214 @@
-use std::{fs, io, net, str::FromStr};
+use std::{ffi::OsString, process::ExitCode};
 
-use serde::Deserialize;
-
-use clap::Parser;
-
-use tokio::time::Duration;
-
-use near_crypto::PublicKey;
-
-use near_primitives::{block::GenesisId, hash::CryptoHash, network::PeerId};
+use clap::{error::ErrorKind, parser::ValueSource, CommandFactory, FromArgMatches};
 
+mod census;
+mod chain;
+mod client;
+mod commands;
+mod config;
 mod connection;
+mod error;
+mod genesis;
+mod logging;
+mod metrics;
 mod network_protocol;
+mod peer_store;
+mod target;
+mod transcript;
 
-use connection::Connection;
+use commands::ConnectionArgs;
+use error::Error;
 use network_protocol::Handshake;
 
 const DEFAULT_LISTEN_PORT: u16 = 24567;
 
-#[derive(Deserialize)]
-struct NodeKey {
-    public_key: String,
-}
+const DEFAULT_NODE_ADDR: &str = "127.0.0.1:24567";
 
 #[derive(clap::Parser)]
 struct Args {
-    /// Network address of the node to connect (address:port)
-    #[clap(short = 'n', long, default_value = "127.0.0.1:24567")]
-    node_addr: String,
-
-    /// Connection timeout (in seconds)
-    #[clap(short = 't', long, default_value = "1")]
-    connection_timeout: u64,
-
-    /// Optional blockchain ID of the genesis for the handshake request - "localnet",
-    /// "testnet", "mainnet" etc. (if provided, then "genesis_hash" must be also provided).
-    #[clap(short = 'c', long, requires = "genesis_hash", verbatim_doc_comment)]
-    genesis_chain_id: Option<String>,
-
-    /// Optional hash of the genesis for the handshake request (if not provided, the genesis
-    /// will be requested from the node by sending a preliminary handshake request with an
-    /// empty genesis and then sending the second handshake request with the proper genesis
-    /// value). Requires that "genesis_chain_id" is also provided.
-    #[clap(short = 'g', long, requires = "genesis_chain_id", verbatim_doc_comment)]
-    genesis_hash: Option<String>,
-
-    // Height of the head for the handshake request
-    #[clap(short = 'b', long, default_value = "0")]
-    head_height: u64,
+    #[clap(subcommand)]
+    command: Option<Command>,
+
+    /// Network address of the node to connect - "address:port" or "peer_id@address:port"
+    /// (nearcore boot node format, e.g. "ed25519:<key>@127.0.0.1:24567"). If the peer id
+    /// is not provided, it's read from node_key.json file of the home directory. If the
+    /// address is not provided, the one from config.json file of the home directory is
+    /// used when "home" is set, otherwise 127.0.0.1:24567.
+    #[clap(short = 'n', long, verbatim_doc_comment)]
+    node_addr: Option<String>,
+
+    #[clap(flatten)]
+    connection: ConnectionArgs,
+
+    #[clap(flatten)]
+    log: logging::LogArgs,
 }
 
-async fn run(args: Args) -> Result<Handshake, String> {
-    let node_key: NodeKey = {
-        let node_key_file_path = format!(
-            "{}/.near/node_key.json",
-            std::env::var("HOME").map_err(|_| {
-                "HOME environment variable not set (required to read node key config file)"
-            })?
-        );
-
-        serde_json::from_reader(io::BufReader::new(
-            fs::File::open(&node_key_file_path).map_err(|_| {
-                format!(
-                    "Error opening node key config file: {}",
-                    &node_key_file_path
-                )
-            })?,
-        ))
-        .map_err(|_| {
-            format!(
-                "Error parsing node key config file: {}",
-                &node_key_file_path
-            )
-        })?
-    };
+#[derive(clap::Subcommand)]
+enum Command {
+    /// Perform handshakes with many nodes concurrently
+    Batch(commands::batch::BatchArgs),
+    /// Crawl the network from the nodes and print the census of the reachable nodes: protocol
+    /// versions, archival nodes, tracked shards and head heights
+    Census(commands::census::CensusArgs),
+    /// Decode the frames of the NEAR peer-to-peer protocol (hex, base64, binary or pcap capture)
+    Decode(commands::decode::DecodeArgs),
+    /// Fetch the blocks or the block headers from a pool of nodes, retrying the failed
+    /// requests with other nodes
+    Fetch(commands::fetch::FetchArgs),
+    /// Fetch the block headers from the nodes and report the heights the nodes disagree on
+    Forks(commands::forks::ForksArgs),
+    /// Compute the genesis id (chain id and genesis block hash) from a genesis config file
+    Genesis(commands::genesis::GenesisArgs),
+    /// Keep the sessions with the nodes and track their chain heads, reporting the nodes
+    /// that fall behind or stall
+    Heads(commands::heads::HeadsArgs),
+    /// Perform handshakes with the nodes periodically and report their state changes
+    Monitor(commands::monitor::MonitorArgs),
+    /// List the peers recorded in the peer store with their scores, or lift their bans
+    Peers(commands::peers::PeersArgs),
+    /// Repeat the full handshake with the node (connect, handshake, close) and print the
+    /// durations of its phases with the ping-style statistics
+    Ping(commands::ping::PingArgs),
+    /// Perform the handshakes with the node advertising different protocol versions and
+    /// report the range of the versions the node accepts
+    ProbeVersions(commands::probe_versions::ProbeVersionsArgs),
+    /// Forward the connections of the nodes to the target node, printing the messages
+    /// exchanged
+    Proxy(commands::proxy::ProxyArgs),
+    /// Decode the frames of a transcript recorded with "--record", or replay the recorded
+    /// responses acting as a fake node
+    Replay(commands::replay::ReplayArgs),
+    /// Perform the handshake with the node and start an interactive session sending the
+    /// requests and printing the messages received from the node
+    Repl(commands::repl::ReplArgs),
+    /// Perform the handshake with the node, send the messages read from JSON and print the
+    /// messages received from the node
+    Send(commands::send::SendArgs),
+}
 
-    let peer_id = PeerId::new(PublicKey::from_str(&node_key.public_key).map_err(|_| {
-        format!(
-            "Error parsing public_key value from node key config file: {}",
-            &node_key.public_key
-        )
-    })?);
-
-    let genesis_id = match args.genesis_hash {
-        Some(hash) => Some(GenesisId {
-            chain_id: args
-                .genesis_chain_id
-                .ok_or("genesis_chain_id command line arg not provided")?,
-            hash: CryptoHash::from_str(&hash).map_err(|_| {
-                format!(
-                    "Error parsing hash value from genesis_hash command line arg: {}",
-                    &hash
-                )
-            })?,
-        }),
-        None => None,
+/// Parses the command line. The handshake args of the top level command are not allowed with
+/// a subcommand (the subcommands have their own ones), only the global logging args are.
+fn parse_args<I, T>(args: I) -> Result<Args, clap::Error>
+where
+    I: IntoIterator<Item = T>,
+    T: Into<OsString> + Clone,
+{
+    let mut command = Args::command();
+    let matches = command.try_get_matches_from_mut(args)?;
+
+    if let Some(subcommand) = matches.subcommand_name() {
+        let misplaced = command
+            .get_arguments()
+            .filter(|arg| !arg.is_global_set())
+            .find(|arg| {
+                matches.value_source(arg.get_id().as_str()) == Some(ValueSource::CommandLine)
+            })
+            .and_then(|arg| arg.get_long());
+        if let Some(long) = misplaced {
+            let message = format!(
+                "--{} must be given after the subcommand: {} --{}",
+                long, subcommand, long
+            );
+            return Err(command.error(ErrorKind::ArgumentConflict, message));
+        }
+    }
+
+    Args::from_arg_matches(&matches).map_err(|e| e.format(&mut command))
+}
+
+async fn run(args: Args) -> Result<Handshake, Error> {
+    let target = match args.node_addr {
+        Some(node_addr) => node_addr,
+        None => match args.connection.home {
+            Some(_) => args.connection.near_home()?.config()?.local_addr(),
+            None => DEFAULT_NODE_ADDR.into(),
+        },
     };
 
-    let node_addr: net::SocketAddr = args.node_addr.parse().map_err(|_| {
-        format!(
-            "Error parsing network address from node_addr command line arg: {}",
-            &args.node_addr
-        )
-    })?;
-
-    let (_connection, handshake) = Connection::connect(
-        node_addr,
-        peer_id,
-        DEFAULT_LISTEN_PORT,
-        Duration::from_secs(args.connection_timeout),
-        genesis_id,
-        args.head_height,
-    )
-    .await
-    .map_err(|e| format!("Error establishing connection to node: {:#?}", e))?;
+    let (_connection, handshake) = commands::connect_target(&target, &args.connection).await?;
 
     Ok(handshake)
 }
 
 #[tokio::main]
-async fn main() {
-    match run(Args::parse()).await {
-        Ok(handshake) => println!(
-            "Handshake performed successfully, response from the node: {:#?}",
-            handshake
-        ),
-        Err(e) => println!("{}", e),
+async fn main() -> ExitCode {
+    let args = parse_args(std::env::args_os()).unwrap_or_else(|e| e.exit());
+
+    args.log.init();
+
+    let result = match args.command {
+        Some(Command::Batch(args)) => commands::batch::run(args).await,
+        Some(Command::Census(args)) => commands::census::run(args).await,
+        Some(Command::Decode(args)) => commands::decode::run(args),
+        Some(Command::Fetch(args)) => commands::fetch::run(args).await,
+        Some(Command::Forks(args)) => commands::forks::run(args).await,
+        Some(Command::Genesis(args)) => commands::genesis::run(args),
+        Some(Command::Heads(args)) => commands::heads::run(args).await,
+        Some(Command::Monitor(args)) => commands::monitor::run(args).await,
+        Some(Command::Peers(args)) => commands::peers::run(args),
+        Some(Command::Ping(args)) => commands::ping::run(args).await,
+        Some(Command::ProbeVersions(args)) => commands::probe_versions::run(args).await,
+        Some(Command::Proxy(args)) => commands::proxy::run(args).await,
+        Some(Command::Replay(args)) => commands::replay::run(args).await,
+        Some(Command::Repl(args)) => commands::repl::run(args).await,
+        Some(Command::Send(args)) => commands::send::run(args).await,
+        None => run(args).await.map(|handshake| {
+            println!(
+                "Handshake performed successfully, response from the node: {:#?}",
+                handshake
+            )
+        }),
+    };
+
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{}", e);
+            e.exit_code()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_args() {
+        Args::command().debug_assert();
+
+        // The global logging args are accepted before the subcommand
+        let args = parse_args(["near-handshake", "-v", "batch", "/dev/null"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Batch(_))));
+
+        let args = parse_args([
+            "near-handshake",
+            "--log-format",
+            "json",
+            "peers",
+            "-f",
+            "json",
+        ])
+        .unwrap();
+        assert!(matches!(args.command, Some(Command::Peers(_))));
+
+        // and after it
+        let args = parse_args(["near-handshake", "batch", "/dev/null", "-vv"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Batch(_))));
+
+        // The handshake without a subcommand
+        let args =
+            parse_args(["near-handshake", "-v", "-n", "127.0.0.1:24567", "-t", "5"]).unwrap();
+        assert!(args.command.is_none());
+        assert_eq!(args.node_addr.as_deref(), Some("127.0.0.1:24567"));
+        assert_eq!(args.connection.connection_timeout, 5);
+
+        // The handshake args of the top level command are not silently ignored
+        let e = parse_args(["near-handshake", "-t", "5", "batch", "/dev/null"])
+            .err()
+            .unwrap();
+        assert_eq!(e.kind(), ErrorKind::ArgumentConflict);
     }
 }
@@ -0,0 +1,281 @@
+use std::{io, net::SocketAddr};
+
+use once_cell::sync::Lazy;
+
+use prometheus::{
+    exponential_buckets, register_histogram_vec, register_int_counter_vec, register_int_gauge_vec,
+    Encoder, HistogramVec, IntCounterVec, IntGaugeVec, TextEncoder,
+};
+
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    time::Duration,
+};
+
+use near_primitives::network::PeerId;
+
+use crate::{
+    connection::Direction,
+    network_protocol::{message_type_name, Handshake, NetworkError, PeerMessage},
+};
+
+/// Stage of the handshake the latency is measured for
+#[derive(Clone, Copy)]
+pub enum Stage {
+    /// Establishing the TCP connection
+    TcpConnect,
+    /// From sending the first handshake request to receiving the response
+    FirstResponse,
+    /// The preliminary handshake round with an empty genesis
+    GenesisDiscovery,
+}
+
+impl Stage {
+    fn name(self) -> &'static str {
+        match self {
+            Self::TcpConnect => "tcp_connect",
+            Self::FirstResponse => "first_response",
+            Self::GenesisDiscovery => "genesis_discovery",
+        }
+    }
+}
+
+static HANDSHAKE_LATENCY: Lazy<HistogramVec> = Lazy::new(|| {
+    register_histogram_vec!(
+        "near_handshake_latency_seconds",
+        "Latency of the handshake stages",
+        &["stage"],
+        exponential_buckets(0.001, 2.0, 14).unwrap()
+    )
+    .unwrap()
+});
+
+static HANDSHAKES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "near_handshake_total",
+        "Number of the performed handshakes by result (ok or error kind)",
+        &["result"]
+    )
+    .unwrap()
+});
+
+static PEER_HEIGHT: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "near_handshake_peer_height",
+        "Head height reported by the peer in the last handshake",
+        &["peer_id", "addr"]
+    )
+    .unwrap()
+});
+
+static PEER_PROTOCOL_VERSION: Lazy<IntGaugeVec> = Lazy::new(|| {
+    register_int_gauge_vec!(
+        "near_handshake_peer_protocol_version",
+        "Protocol version reported by the peer in the last handshake",
+        &["peer_id", "addr"]
+    )
+    .unwrap()
+});
+
+static MESSAGES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "near_handshake_messages_total",
+        "Number of the sent (out) and received (in) messages by message type",
+        &["direction", "type"]
+    )
+    .unwrap()
+});
+
+static BYTES: Lazy<IntCounterVec> = Lazy::new(|| {
+    register_int_counter_vec!(
+        "near_handshake_bytes_total",
+        "Number of the sent (out) and received (in) bytes by message type",
+        &["direction", "type"]
+    )
+    .unwrap()
+});
+
+pub fn observe_latency(stage: Stage, latency: Duration) {
+    HANDSHAKE_LATENCY
+        .with_label_values(&[stage.name()])
+        .observe(latency.as_secs_f64());
+}
+
+/// Records the result of the handshake with the peer (and the chain info the peer reported)
+pub fn record_handshake(
+    peer_id: &PeerId,
+    addr: &SocketAddr,
+    result: Result<&Handshake, &NetworkError>,
+) {
+    match result {
+        Ok(handshake) => {
+            HANDSHAKES.with_label_values(&["ok"]).inc();
+
+            let labels = [peer_id.to_string(), addr.to_string()];
+            let labels = [labels[0].as_str(), labels[1].as_str()];
+            PEER_HEIGHT
+                .with_label_values(&labels)
+                .set(handshake.sender_chain_info.height as i64);
+            PEER_PROTOCOL_VERSION
+                .with_label_values(&labels)
+                .set(handshake.protocol_version.into());
+        }
+        Err(e) => HANDSHAKES.with_label_values(&[e.kind()]).inc(),
+    }
+}
+
+/// Records the message sent or received, `size` is the size of the message on the wire
+pub fn record_message(direction: Direction, msg: &PeerMessage, size: usize) {
+    let labels = [direction.as_str(), message_type_name(msg)];
+    MESSAGES.with_label_values(&labels).inc();
+    BYTES.with_label_values(&labels).inc_by(size as u64);
+}
+
+/// All the metrics in the Prometheus text format
+pub fn encode() -> String {
+    let mut buffer = Vec::new();
+    TextEncoder::new()
+        .encode(&prometheus::gather(), &mut buffer)
+        .unwrap();
+    String::from_utf8(buffer).unwrap()
+}
+
+/// Maximum size of the request line and headers
+const MAX_REQUEST_HEAD_LEN: usize = 8192;
+
+/// Reads the request line and headers (up to the empty line ending them)
+async fn read_request_head(stream: &mut TcpStream) -> io::Result<String> {
+    let mut head = Vec::new();
+    let mut buffer = [0u8; 1024];
+    while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+        if head.len() > MAX_REQUEST_HEAD_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "request headers too long",
+            ));
+        }
+        let len = stream.read(&mut buffer).await?;
+        if len == 0 {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        head.extend_from_slice(&buffer[..len]);
+    }
+    Ok(String::from_utf8_lossy(&head).into_owned())
+}
+
+async fn serve_request(mut stream: TcpStream) -> io::Result<()> {
+    // Only the request line is needed, the headers are ignored
+    let request = read_request_head(&mut stream).await?;
+
+    let (status, content_type, body) =
+        match request.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+            ["GET", "/metrics"] => ("200 OK", prometheus::TEXT_FORMAT, encode()),
+            _ => ("404 Not Found", "text/plain", "Not found\n".into()),
+        };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        content_type,
+        body.len(),
+        body
+    );
+    stream.write_all(response.as_bytes()).await
+}
+
+/// Starts the HTTP server exposing the metrics at /metrics path (in the background),
+/// returns the address the server listens on
+pub async fn serve(addr: SocketAddr) -> io::Result<SocketAddr> {
+    let listener = TcpListener::bind(addr).await?;
+    let local_addr = listener.local_addr()?;
+
+    tokio::spawn(async move {
+        while let Ok((stream, _)) = listener.accept().await {
+            tokio::spawn(serve_request(stream));
+        }
+    });
+
+    Ok(local_addr)
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{ED25519PublicKey, PublicKey};
+
+    use crate::network_protocol::PeerChainInfo;
+
+    use super::*;
+
+    /// Sends the request in the given parts (with a pause in between), returns the response
+    async fn request(addr: SocketAddr, parts: &[&str]) -> String {
+        let mut stream = TcpStream::connect(addr).await.unwrap();
+        for part in parts {
+            stream.write_all(part.as_bytes()).await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        response
+    }
+
+    #[tokio::test]
+    async fn test_serve() {
+        let peer_id = PeerId::new(PublicKey::ED25519(ED25519PublicKey([7; 32])));
+        let peer_addr: SocketAddr = "10.0.0.7:24567".parse().unwrap();
+        let handshake = Handshake {
+            protocol_version: 57,
+            oldest_supported_version: 55,
+            sender_peer_id: peer_id.clone(),
+            target_peer_id: peer_id.clone(),
+            sender_listen_port: None,
+            sender_chain_info: PeerChainInfo {
+                height: 1234,
+                ..Default::default()
+            },
+            partial_edge_info: Default::default(),
+        };
+        record_handshake(&peer_id, &peer_addr, Ok(&handshake));
+        record_handshake(&peer_id, &peer_addr, Err(&NetworkError::ConnectTimeout));
+        observe_latency(Stage::GenesisDiscovery, Duration::from_millis(3));
+
+        let addr = serve("127.0.0.1:0".parse().unwrap()).await.unwrap();
+
+        // The request line split across the reads
+        let response = request(
+            addr,
+            &["GE", "T /metrics HTTP/1.1\r\nHost: localhost\r\n", "\r\n"],
+        )
+        .await;
+        let (head, body) = response.split_once("\r\n\r\n").unwrap();
+        assert!(head.starts_with("HTTP/1.1 200 OK"));
+        assert!(head.contains(&format!("Content-Length: {}", body.len())));
+
+        let labels = format!("addr=\"{}\",peer_id=\"{}\"", peer_addr, peer_id);
+        let lines: Vec<&str> = body.lines().collect();
+        assert!(lines.contains(&format!("near_handshake_peer_height{{{}}} 1234", labels).as_str()));
+        assert!(lines
+            .contains(&format!("near_handshake_peer_protocol_version{{{}}} 57", labels).as_str()));
+
+        // The counters and the histograms are shared with the other tests, so only their
+        // presence is checked
+        let value = |prefix: &str| -> f64 {
+            let line = lines
+                .iter()
+                .find(|line| line.starts_with(prefix))
+                .unwrap_or_else(|| panic!("{} not found", prefix));
+            line.rsplit(' ').next().unwrap().parse().unwrap()
+        };
+        assert!(value("near_handshake_total{result=\"ok\"}") >= 1.0);
+        assert!(value("near_handshake_total{result=\"connect_timeout\"}") >= 1.0);
+        assert!(
+            value(
+                "near_handshake_latency_seconds_bucket{stage=\"genesis_discovery\",le=\"0.004\"}"
+            ) >= 1.0
+        );
+        assert!(value("near_handshake_latency_seconds_count{stage=\"genesis_discovery\"}") >= 1.0);
+
+        let response = request(addr, &["GET / HTTP/1.1\r\n\r\n"]).await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found"));
+    }
+}
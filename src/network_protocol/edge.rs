@@ -1,12 +1,14 @@
 use borsh::{BorshDeserialize, BorshSerialize};
 
-use near_crypto::{SecretKey, Signature};
+use near_crypto::{PublicKey, SecretKey, Signature};
+
+use serde::{Deserialize, Serialize};
 
 use near_primitives::{hash::CryptoHash, network::PeerId};
 
 use super::proto;
 
-#[derive(BorshSerialize, BorshDeserialize, Debug, Default)]
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug, Default)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct PartialEdgeInfo {
     pub nonce: u64,
@@ -15,21 +17,27 @@ pub struct PartialEdgeInfo {
 
 impl PartialEdgeInfo {
     fn build_hash(peer0: &PeerId, peer1: &PeerId, nonce: u64) -> CryptoHash {
-        CryptoHash::hash_borsh(&(peer0, peer1, nonce))
+        if peer0 < peer1 {
+            CryptoHash::hash_borsh(&(peer0, peer1, nonce))
+        } else {
+            CryptoHash::hash_borsh(&(peer1, peer0, nonce))
+        }
     }
 
     pub fn new(peer0: &PeerId, peer1: &PeerId, nonce: u64, secret_key: &SecretKey) -> Self {
-        let hash = if peer0 < peer1 {
-            Self::build_hash(peer0, peer1, nonce)
-        } else {
-            Self::build_hash(peer1, peer0, nonce)
-        };
+        let hash = Self::build_hash(peer0, peer1, nonce);
 
         Self {
             nonce,
             signature: secret_key.sign(hash.as_ref()),
         }
     }
+
+    /// Checks that the edge (peer0, peer1) was signed with the given public key
+    pub fn verify(&self, peer0: &PeerId, peer1: &PeerId, public_key: &PublicKey) -> bool {
+        let hash = Self::build_hash(peer0, peer1, self.nonce);
+        self.signature.verify(hash.as_ref(), public_key)
+    }
 }
 
 impl From<&PartialEdgeInfo> for proto::PartialEdgeInfo {
@@ -50,3 +58,18 @@ impl TryFrom<&proto::PartialEdgeInfo> for PartialEdgeInfo {
         Self::try_from_slice(&value.borsh)
     }
 }
+
+// *** Edge ***
+
+/// Edge of the network graph signed by both peers (the same borsh layout as in nearcore)
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug)]
+pub struct Edge {
+    pub peer0: PeerId,
+    pub peer1: PeerId,
+    pub nonce: u64,
+    pub signature0: Signature,
+    pub signature1: Signature,
+    /// Set if the edge is removed: the peer who removed it (false - peer0, true - peer1)
+    /// and its signature
+    pub removal_info: Option<(bool, Signature)>,
+}
@@ -1,3 +1,5 @@
+use std::fmt;
+
 use protobuf::MessageField;
 
 use near_primitives::{block::GenesisId, network::PeerId, version::ProtocolVersion};
@@ -91,6 +93,44 @@ pub enum HandshakeFailure {
     ParseHandshakeError(ParseHandshakeError),
 }
 
+impl fmt::Display for HandshakeFailure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::ProtocolVersionMismatch {
+                version,
+                oldest_supported_version,
+            } => write!(
+                f,
+                "protocol version mismatch (node supports versions {}..={})",
+                oldest_supported_version, version
+            ),
+            Self::GenesisMismatch(genesis_id) => write!(
+                f,
+                "genesis mismatch (node genesis: {} {})",
+                genesis_id.chain_id, genesis_id.hash
+            ),
+            Self::InvalidTarget => write!(f, "invalid target peer id"),
+            Self::UnknownReason => write!(f, "unknown reason"),
+            Self::ParseHandshakeError(e) => write!(f, "error parsing handshake: {}", e),
+        }
+    }
+}
+
+impl HandshakeFailure {
+    /// Short name of the failure kind (e.g. for metric labels)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::ProtocolVersionMismatch { .. } => "protocol_version_mismatch",
+            Self::GenesisMismatch(_) => "genesis_mismatch",
+            Self::InvalidTarget => "invalid_target",
+            Self::UnknownReason => "unknown_reason",
+            Self::ParseHandshakeError(_) => "parse_handshake_error",
+        }
+    }
+}
+
+impl std::error::Error for HandshakeFailure {}
+
 impl From<&HandshakeFailure> for MessageType {
     fn from(value: &HandshakeFailure) -> Self {
         Self::HandshakeFailure(match value {
@@ -156,6 +196,61 @@ impl TryFrom<&proto::HandshakeFailure> for HandshakeFailure {
 #[derive(Debug)]
 pub struct HandshakeResponse(pub Handshake);
 
+impl HandshakeResponse {
+    /// Checks that the response is the counterpart of the request: it comes from the peer
+    /// the request was sent to, it is addressed to us, it is properly signed and the node
+    /// agrees on the genesis and the protocol version
+    pub fn verify(&self, request: &Handshake) -> Result<(), NetworkError> {
+        self.verify_peer(request)?;
+
+        if !(request.oldest_supported_version..=request.protocol_version)
+            .contains(&self.0.protocol_version)
+        {
+            return Err(NetworkError::UnsupportedProtocolVersion(
+                self.0.protocol_version,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Checks the response as `verify` does, except for the protocol version: the node
+    /// accepting the handshake may respond with a version outside of the range advertised
+    pub fn verify_peer(&self, request: &Handshake) -> Result<(), NetworkError> {
+        let response = &self.0;
+
+        if response.sender_peer_id != request.target_peer_id {
+            return Err(NetworkError::SenderMismatch(
+                response.sender_peer_id.clone(),
+            ));
+        }
+
+        if response.target_peer_id != request.sender_peer_id {
+            return Err(NetworkError::TargetMismatch(
+                response.target_peer_id.clone(),
+            ));
+        }
+
+        // The node signs the same edge with its own key, so the signature must match
+        // the public key of the sender
+        if !response.partial_edge_info.verify(
+            &response.sender_peer_id,
+            &response.target_peer_id,
+            response.sender_peer_id.public_key(),
+        ) {
+            return Err(NetworkError::InvalidSignature);
+        }
+
+        if response.sender_chain_info.genesis_id != request.sender_chain_info.genesis_id {
+            return Err(NetworkError::GenesisMismatch(
+                response.sender_chain_info.genesis_id.clone(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
 impl TryFrom<&proto::PeerMessage> for HandshakeResponse {
     type Error = NetworkError;
 
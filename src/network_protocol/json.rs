@@ -0,0 +1,385 @@
+use std::str::FromStr;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use protobuf::{
+    reflect::{ReflectFieldRef, ReflectValueBox, ReflectValueRef, RuntimeFieldType, RuntimeType},
+    MessageDyn,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use near_crypto::{PublicKey, Signature};
+
+use near_primitives::{
+    block::{Block, BlockHeader},
+    hash::CryptoHash,
+    network::{AnnounceAccount, PeerId},
+    transaction::SignedTransaction,
+    types::{AccountId, EpochId},
+    views::{BlockHeaderView, ChunkHeaderView, SignedTransactionView},
+};
+
+use super::{Edge, PartialEdgeInfo, PeerInfo, PeerMessage, RoutedMessage};
+
+/// Converts the protobuf message to JSON (the same way as the protobuf JSON mapping, with
+/// the default values omitted), expanding the borsh-wrapped payloads (the wrappers of the
+/// types not known here are left as hex encoded borsh). The bytes are hex encoded, prefixed
+/// with "hex:".
+pub fn message_to_json(msg: &dyn MessageDyn) -> Value {
+    let descriptor = msg.descriptor_dyn();
+
+    if descriptor.name() == "CryptoHash" {
+        if let Some(ReflectValueRef::Bytes(hash)) = descriptor
+            .field_by_name("hash")
+            .and_then(|field| field.get_singular(msg))
+        {
+            if let Ok(hash) = CryptoHash::try_from(hash) {
+                return json!(hash);
+            }
+        }
+    }
+
+    if let Some(ReflectValueRef::Bytes(data)) = descriptor
+        .field_by_name("borsh")
+        .and_then(|field| field.get_singular(msg))
+    {
+        return borsh_to_json(descriptor.name(), data);
+    }
+
+    let mut object = Map::new();
+
+    for field in descriptor.fields() {
+        let value = match field.get_reflect(msg) {
+            ReflectFieldRef::Optional(value) => match value.value() {
+                Some(value) => value_to_json(value),
+                None => continue,
+            },
+            ReflectFieldRef::Repeated(values) if values.is_empty() => continue,
+            ReflectFieldRef::Repeated(values) => Value::Array(
+                (0..values.len())
+                    .map(|i| value_to_json(values.get(i)))
+                    .collect(),
+            ),
+            // There are no map fields in the protocol
+            ReflectFieldRef::Map(_) => continue,
+        };
+        object.insert(field.name().into(), value);
+    }
+
+    Value::Object(object)
+}
+
+fn value_to_json(value: ReflectValueRef) -> Value {
+    match value {
+        ReflectValueRef::U32(v) => json!(v),
+        ReflectValueRef::U64(v) => json!(v),
+        ReflectValueRef::I32(v) => json!(v),
+        ReflectValueRef::I64(v) => json!(v),
+        ReflectValueRef::F32(v) => json!(v),
+        ReflectValueRef::F64(v) => json!(v),
+        ReflectValueRef::Bool(v) => json!(v),
+        ReflectValueRef::String(v) => json!(v),
+        ReflectValueRef::Bytes(v) => bytes_to_json(v),
+        ReflectValueRef::Enum(descriptor, v) => match descriptor.value_by_number(v) {
+            Some(value) => json!(value.name()),
+            None => json!(v),
+        },
+        ReflectValueRef::Message(msg) => message_to_json(&*msg),
+    }
+}
+
+/// Prefix of the hex encoded bytes (base64 strings can't contain ':', so the bytes given as
+/// either of them are decoded unambiguously)
+const HEX_PREFIX: &str = "hex:";
+
+fn bytes_to_json(data: &[u8]) -> Value {
+    json!(format!("{}{}", HEX_PREFIX, hex::encode(data)))
+}
+
+fn decode<T: BorshDeserialize, V: Serialize>(data: &[u8], view: impl FnOnce(T) -> V) -> Value {
+    match T::try_from_slice(data) {
+        Ok(value) => json!(view(value)),
+        Err(e) => json!({
+            "borsh": bytes_to_json(data),
+            "error": e.to_string(),
+        }),
+    }
+}
+
+/// Converts the block to JSON (the header and the headers of the chunks)
+pub fn block_to_json(block: &Block) -> Value {
+    json!({
+        "header": BlockHeaderView::from(block.header().clone()),
+        "chunks": block
+            .chunks()
+            .iter()
+            .cloned()
+            .map(ChunkHeaderView::from)
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Decodes the borsh payload of the protobuf wrapper message with the given name
+fn borsh_to_json(name: &str, data: &[u8]) -> Value {
+    match name {
+        "PublicKey" => decode(data, |key: PublicKey| key),
+        "Signature" => decode(data, |signature: Signature| signature),
+        "PeerInfo" => decode(data, |peer_info: PeerInfo| peer_info),
+        "Edge" => decode(data, |edge: Edge| edge),
+        "PartialEdgeInfo" => decode(data, |edge_info: PartialEdgeInfo| edge_info),
+        "AnnounceAccount" => decode(data, |announce: AnnounceAccount| {
+            json!({
+                "account_id": announce.account_id,
+                "peer_id": announce.peer_id,
+                "epoch_id": announce.epoch_id.0,
+                "signature": announce.signature,
+            })
+        }),
+        "BlockHeader" => decode(data, |header: BlockHeader| BlockHeaderView::from(header)),
+        "Block" => decode(data, |block: Block| block_to_json(&block)),
+        "RoutedMessage" => decode(data, |msg: RoutedMessage| msg),
+        "SignedTransaction" => decode(data, |tx: SignedTransaction| {
+            SignedTransactionView::from(tx)
+        }),
+        _ => json!({ "borsh": bytes_to_json(data) }),
+    }
+}
+
+/// Builds the PeerMessage from JSON, the reverse of [`message_to_json`] (the protobuf JSON
+/// mapping is accepted too: the field names may be in lowerCamelCase, the integers may be
+/// strings and the bytes may be base64 encoded instead of "hex:" prefixed hex)
+pub fn message_from_json(value: &Value) -> Result<PeerMessage, String> {
+    let mut msg = PeerMessage::new();
+    fill_message(&mut msg, value)?;
+    Ok(msg)
+}
+
+fn fill_message(msg: &mut dyn MessageDyn, value: &Value) -> Result<(), String> {
+    let descriptor = msg.descriptor_dyn();
+
+    if descriptor.name() == "CryptoHash" {
+        if let Value::String(hash) = value {
+            let hash = CryptoHash::from_str(hash).map_err(|e| e.to_string())?;
+            descriptor
+                .field_by_name("hash")
+                .unwrap()
+                .set_singular_field(msg, ReflectValueBox::Bytes(hash.0.to_vec()));
+            return Ok(());
+        }
+    }
+
+    if let Some(field) = descriptor.field_by_name("borsh") {
+        // The payload is either given as is, or encoded from its JSON representation
+        let data = match value.get("borsh") {
+            Some(data) => bytes_from_json(data)?,
+            None => borsh_from_json(descriptor.name(), value)?,
+        };
+        field.set_singular_field(msg, ReflectValueBox::Bytes(data));
+        return Ok(());
+    }
+
+    let object = value
+        .as_object()
+        .ok_or_else(|| format!("object expected for {}", descriptor.name()))?;
+
+    for (name, value) in object {
+        let field = descriptor
+            .field_by_name_or_json_name(name)
+            .ok_or_else(|| format!("unknown field of {}: {}", descriptor.name(), name))?;
+
+        if value.is_null() {
+            continue;
+        }
+
+        let result = match field.runtime_field_type() {
+            RuntimeFieldType::Singular(value_type) => value_from_json(&value_type, value)
+                .map(|value| field.set_singular_field(msg, value)),
+            RuntimeFieldType::Repeated(value_type) => match value.as_array() {
+                Some(values) => values
+                    .iter()
+                    .map(|value| value_from_json(&value_type, value))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map(|values| {
+                        let mut repeated = field.mut_repeated(msg);
+                        values.into_iter().for_each(|value| repeated.push(value));
+                    }),
+                None => Err("array expected".into()),
+            },
+            // There are no map fields in the protocol
+            RuntimeFieldType::Map(..) => Err("map fields are not supported".into()),
+        };
+
+        result.map_err(|e| format!("{}: {}", name, e))?;
+    }
+
+    Ok(())
+}
+
+/// Parses the number given as a JSON number or a string
+fn number_from_json<T: FromStr>(value: &Value) -> Result<T, String>
+where
+    T::Err: ToString,
+{
+    match value {
+        Value::Number(number) => number.to_string().parse(),
+        Value::String(number) => number.parse(),
+        _ => return Err("number expected".into()),
+    }
+    .map_err(|e: T::Err| e.to_string())
+}
+
+fn bytes_from_json(value: &Value) -> Result<Vec<u8>, String> {
+    let data = value.as_str().ok_or("hex or base64 string expected")?;
+    match data.strip_prefix(HEX_PREFIX) {
+        Some(hex) => hex::decode(hex).map_err(|_| format!("invalid hex string: {}", data)),
+        None => base64::decode(data).map_err(|_| format!("invalid base64 string: {}", data)),
+    }
+}
+
+fn value_from_json(value_type: &RuntimeType, value: &Value) -> Result<ReflectValueBox, String> {
+    Ok(match value_type {
+        RuntimeType::I32 => ReflectValueBox::I32(number_from_json(value)?),
+        RuntimeType::I64 => ReflectValueBox::I64(number_from_json(value)?),
+        RuntimeType::U32 => ReflectValueBox::U32(number_from_json(value)?),
+        RuntimeType::U64 => ReflectValueBox::U64(number_from_json(value)?),
+        RuntimeType::F32 => ReflectValueBox::F32(number_from_json(value)?),
+        RuntimeType::F64 => ReflectValueBox::F64(number_from_json(value)?),
+        RuntimeType::Bool => ReflectValueBox::Bool(value.as_bool().ok_or("boolean expected")?),
+        RuntimeType::String => {
+            ReflectValueBox::String(value.as_str().ok_or("string expected")?.into())
+        }
+        RuntimeType::VecU8 => ReflectValueBox::Bytes(bytes_from_json(value)?),
+        RuntimeType::Enum(descriptor) => {
+            let number = match value {
+                Value::String(name) => descriptor
+                    .value_by_name(name)
+                    .ok_or_else(|| format!("unknown value of {}: {}", descriptor.name(), name))?
+                    .value(),
+                value => number_from_json(value)?,
+            };
+            ReflectValueBox::Enum(descriptor.clone(), number)
+        }
+        RuntimeType::Message(descriptor) => {
+            let mut msg = descriptor.new_instance();
+            fill_message(&mut *msg, value)?;
+            ReflectValueBox::Message(msg)
+        }
+    })
+}
+
+fn encode<T: DeserializeOwned + BorshSerialize>(value: &Value) -> Result<Vec<u8>, String> {
+    T::deserialize(value)
+        .map(|value| value.try_to_vec().unwrap())
+        .map_err(|e| e.to_string())
+}
+
+/// Encodes the borsh payload of the protobuf wrapper message with the given name from its
+/// JSON representation (the one printed by [`message_to_json`])
+fn borsh_from_json(name: &str, value: &Value) -> Result<Vec<u8>, String> {
+    match name {
+        "PublicKey" => encode::<PublicKey>(value),
+        "Signature" => encode::<Signature>(value),
+        "PeerInfo" => encode::<PeerInfo>(value),
+        "Edge" => encode::<Edge>(value),
+        "PartialEdgeInfo" => encode::<PartialEdgeInfo>(value),
+        "RoutedMessage" => encode::<RoutedMessage>(value),
+        "AnnounceAccount" => {
+            #[derive(Deserialize)]
+            struct Announce {
+                account_id: AccountId,
+                peer_id: PeerId,
+                epoch_id: CryptoHash,
+                signature: Signature,
+            }
+
+            let announce = Announce::deserialize(value).map_err(|e| e.to_string())?;
+            Ok(AnnounceAccount {
+                account_id: announce.account_id,
+                peer_id: announce.peer_id,
+                epoch_id: EpochId(announce.epoch_id),
+                signature: announce.signature,
+            }
+            .try_to_vec()
+            .unwrap())
+        }
+        // The views of the blocks and transactions can't be converted back
+        _ => Err(format!(
+            "only the borsh payload ({{\"borsh\": \"hex:<hex>\"}}) is supported for {}",
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{KeyType, SecretKey};
+
+    use near_primitives::block::GenesisId;
+
+    use crate::network_protocol::{Handshake, HandshakeFailure, PeerChainInfo};
+
+    use super::*;
+
+    #[test]
+    fn test_message_from_json() {
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let peer_id = PeerId::new(secret_key.public_key());
+
+        let handshake = Handshake {
+            protocol_version: 57,
+            oldest_supported_version: 55,
+            sender_peer_id: peer_id.clone(),
+            target_peer_id: peer_id.clone(),
+            sender_listen_port: Some(24567),
+            sender_chain_info: PeerChainInfo {
+                genesis_id: GenesisId {
+                    chain_id: "localnet".into(),
+                    hash: CryptoHash::hash_bytes(b"genesis"),
+                },
+                height: 100,
+                ..Default::default()
+            },
+            partial_edge_info: PartialEdgeInfo::new(&peer_id, &peer_id, 1, &secret_key),
+        };
+
+        // The messages printed by message_to_json are encoded back as they were
+        let msg = PeerMessage::from(&handshake);
+        assert_eq!(message_from_json(&message_to_json(&msg)).unwrap(), msg);
+
+        // The protobuf JSON mapping is accepted too
+        let msg = message_from_json(&json!({
+            "handshakeFailure": {
+                "reason": "GenesisMismatch",
+                "genesisId": {
+                    "chainId": "testnet",
+                    "hash": { "hash": "AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=" },
+                },
+                "version": "0",
+            },
+        }))
+        .unwrap();
+        assert_eq!(
+            msg,
+            PeerMessage::from(&HandshakeFailure::GenesisMismatch(GenesisId {
+                chain_id: "testnet".into(),
+                hash: CryptoHash::default(),
+            }))
+        );
+
+        // The base64 string which is valid hex too is decoded as base64
+        assert_eq!(
+            bytes_from_json(&json!("abcdef12")).unwrap(),
+            base64::decode("abcdef12").unwrap()
+        );
+        assert_eq!(
+            bytes_from_json(&json!("hex:abcdef12")).unwrap(),
+            [0xab, 0xcd, 0xef, 0x12]
+        );
+        assert!(bytes_from_json(&json!("hex:abcdefgh")).is_err());
+
+        assert!(message_from_json(&json!({ "handshake": { "unknown": 1 } })).is_err());
+        assert!(message_from_json(&json!({ "block_response": { "block": {} } })).is_err());
+    }
+}
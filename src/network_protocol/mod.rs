@@ -1,4 +1,10 @@
+use std::{fmt, io};
+
+use near_primitives::{block::GenesisId, network::PeerId, version::ProtocolVersion};
+
 mod _proto {
+    // The generated code allows lints that were removed from newer compilers
+    #![allow(renamed_and_removed_lints)]
     include!(concat!(env!("OUT_DIR"), "/proto/mod.rs"));
 }
 
@@ -10,22 +16,137 @@ pub(crate) use proto::peer_message::Message_type as MessageType;
 
 mod edge;
 mod handshake;
+mod json;
 mod peer;
+mod requests;
+mod responses;
+mod routed;
 
-pub use edge::PartialEdgeInfo;
+pub use edge::{Edge, PartialEdgeInfo};
 pub use handshake::{Handshake, HandshakeFailure, HandshakeResponse};
-pub use peer::PeerChainInfo;
+pub use json::{block_to_json, message_from_json, message_to_json};
+pub use peer::{PeerChainInfo, PeerInfo};
+pub use requests::{BlockHeadersRequest, BlockRequest, Disconnect, EpochSyncRequest, PeersRequest};
+pub use responses::{
+    BlockHeadersResponse, BlockResponse, EpochSyncResponse, ExpectedResponse, PeersResponse,
+    ReceivedMessage, ResponseContent, ResponseMatch,
+};
+pub use routed::{PeerIdOrHash, Ping, RoutedMessage, RoutedMessageBody};
 
 #[derive(Debug)]
 pub enum NetworkError {
     IO(std::io::Error),
+    ConnectTimeout,
     InvalidResponse,
     UnexpectedResponse,
+    InvalidSignature,
+    /// The response is signed by a peer other than the dialled one
+    SenderMismatch(PeerId),
+    /// The response is addressed to a peer other than us
+    TargetMismatch(PeerId),
+    /// The response advertises a genesis other than the one sent in the request
+    GenesisMismatch(GenesisId),
+    /// The response protocol version is out of the range advertised in the request
+    UnsupportedProtocolVersion(ProtocolVersion),
     HandshakeFailure(HandshakeFailure),
 }
 
+impl fmt::Display for NetworkError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::IO(e) => write!(f, "IO error: {}", e),
+            Self::ConnectTimeout => write!(f, "connection timed out"),
+            Self::InvalidResponse => write!(f, "invalid response"),
+            Self::UnexpectedResponse => write!(f, "unexpected response"),
+            Self::InvalidSignature => write!(f, "edge signature verification failed"),
+            Self::SenderMismatch(peer_id) => {
+                write!(f, "response sent by unexpected peer: {}", peer_id)
+            }
+            Self::TargetMismatch(peer_id) => {
+                write!(f, "response addressed to unexpected peer: {}", peer_id)
+            }
+            Self::GenesisMismatch(genesis_id) => write!(
+                f,
+                "response genesis mismatch (node genesis: {} {})",
+                genesis_id.chain_id, genesis_id.hash
+            ),
+            Self::UnsupportedProtocolVersion(version) => {
+                write!(f, "unsupported protocol version in response: {}", version)
+            }
+            Self::HandshakeFailure(failure) => write!(f, "handshake failure: {}", failure),
+        }
+    }
+}
+
+impl NetworkError {
+    /// Error of connecting to the node (the timeout reported by the OS is the connect
+    /// timeout, same as the one elapsed while connecting)
+    pub fn connect(e: io::Error) -> Self {
+        match e.kind() {
+            io::ErrorKind::TimedOut => Self::ConnectTimeout,
+            _ => Self::IO(e),
+        }
+    }
+
+    /// Short name of the error kind (e.g. for metric labels)
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::IO(_) => "io",
+            Self::ConnectTimeout => "connect_timeout",
+            Self::InvalidResponse => "invalid_response",
+            Self::UnexpectedResponse => "unexpected_response",
+            Self::InvalidSignature => "invalid_signature",
+            Self::SenderMismatch(_) => "sender_mismatch",
+            Self::TargetMismatch(_) => "target_mismatch",
+            Self::GenesisMismatch(_) => "response_genesis_mismatch",
+            Self::UnsupportedProtocolVersion(_) => "unsupported_protocol_version",
+            Self::HandshakeFailure(failure) => failure.kind(),
+        }
+    }
+}
+
+impl std::error::Error for NetworkError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::IO(e) => Some(e),
+            Self::HandshakeFailure(failure) => Some(failure),
+            _ => None,
+        }
+    }
+}
+
 type DynError = Box<dyn std::error::Error + Send + Sync>;
 
+/// Name of the message type (the name of the message_type field of the protobuf message)
+pub fn message_type_name(msg: &PeerMessage) -> &'static str {
+    match &msg.message_type {
+        None => "none",
+        Some(message_type) => match message_type {
+            MessageType::Handshake(_) => "handshake",
+            MessageType::HandshakeFailure(_) => "handshake_failure",
+            MessageType::LastEdge(_) => "last_edge",
+            MessageType::SyncRoutingTable(_) => "sync_routing_table",
+            MessageType::UpdateNonceRequest(_) => "update_nonce_request",
+            MessageType::UpdateNonceResponse(_) => "update_nonce_response",
+            MessageType::SyncAccountsData(_) => "sync_accounts_data",
+            MessageType::PeersRequest(_) => "peers_request",
+            MessageType::PeersResponse(_) => "peers_response",
+            MessageType::BlockHeadersRequest(_) => "block_headers_request",
+            MessageType::BlockHeadersResponse(_) => "block_headers_response",
+            MessageType::BlockRequest(_) => "block_request",
+            MessageType::BlockResponse(_) => "block_response",
+            MessageType::Transaction(_) => "transaction",
+            MessageType::Routed(_) => "routed",
+            MessageType::Disconnect(_) => "disconnect",
+            MessageType::Challenge(_) => "challenge",
+            MessageType::EpochSyncRequest(_) => "epoch_sync_request",
+            MessageType::EpochSyncResponse(_) => "epoch_sync_response",
+            MessageType::EpochSyncFinalizationRequest(_) => "epoch_sync_finalization_request",
+            MessageType::EpochSyncFinalizationResponse(_) => "epoch_sync_finalization_response",
+        },
+    }
+}
+
 impl<T> From<T> for proto::PeerMessage
 where
     MessageType: From<T>,
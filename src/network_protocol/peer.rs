@@ -1,14 +1,18 @@
+use std::net::SocketAddr;
+
 use protobuf::MessageField;
 
 use borsh::{BorshDeserialize, BorshSerialize};
 
+use serde::{Deserialize, Serialize};
+
 use super::{proto, DynError};
 
 use near_primitives::{
     block::GenesisId,
     hash::CryptoHash,
     network::PeerId,
-    types::{BlockHeight, ShardId},
+    types::{AccountId, BlockHeight, ShardId},
 };
 
 // *** CryptoHash ***
@@ -80,7 +84,7 @@ impl TryFrom<&proto::GenesisId> for GenesisId {
 
 // *** PeerChainInfo ***
 
-#[derive(Debug, Default)]
+#[derive(Clone, Debug, Default)]
 #[cfg_attr(test, derive(PartialEq))]
 pub struct PeerChainInfo {
     pub genesis_id: GenesisId,
@@ -119,3 +123,23 @@ impl TryFrom<&proto::PeerChainInfo> for PeerChainInfo {
         })
     }
 }
+
+// *** PeerInfo ***
+
+/// Peer address information (the same borsh layout as in nearcore)
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Debug)]
+pub struct PeerInfo {
+    pub id: PeerId,
+    pub addr: Option<SocketAddr>,
+    pub account_id: Option<AccountId>,
+}
+
+type ParsePeerInfoError = borsh::maybestd::io::Error;
+
+impl TryFrom<&proto::PeerInfo> for PeerInfo {
+    type Error = ParsePeerInfoError;
+
+    fn try_from(value: &proto::PeerInfo) -> Result<Self, Self::Error> {
+        Self::try_from_slice(&value.borsh)
+    }
+}
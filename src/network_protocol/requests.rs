@@ -0,0 +1,71 @@
+use protobuf::MessageField;
+
+use near_primitives::{hash::CryptoHash, types::EpochId};
+
+use super::{proto, MessageType};
+
+// *** PeersRequest ***
+
+/// Request of the peers known to the node (answered with PeersResponse)
+pub struct PeersRequest;
+
+impl From<&PeersRequest> for MessageType {
+    fn from(_: &PeersRequest) -> Self {
+        Self::PeersRequest(proto::PeersRequest::default())
+    }
+}
+
+// *** BlockHeadersRequest ***
+
+/// Request of the headers of the blocks following the first known of the given ones
+/// (answered with BlockHeadersResponse)
+pub struct BlockHeadersRequest(pub Vec<CryptoHash>);
+
+impl From<&BlockHeadersRequest> for MessageType {
+    fn from(value: &BlockHeadersRequest) -> Self {
+        Self::BlockHeadersRequest(proto::BlockHeadersRequest {
+            block_hashes: value.0.iter().map(Into::into).collect(),
+            ..Default::default()
+        })
+    }
+}
+
+// *** BlockRequest ***
+
+/// Request of the block with the given hash (answered with BlockResponse)
+pub struct BlockRequest(pub CryptoHash);
+
+impl From<&BlockRequest> for MessageType {
+    fn from(value: &BlockRequest) -> Self {
+        Self::BlockRequest(proto::BlockRequest {
+            block_hash: MessageField::some((&value.0).into()),
+            ..Default::default()
+        })
+    }
+}
+
+// *** EpochSyncRequest ***
+
+/// Request of the data to sync the epoch following the given one (answered with
+/// EpochSyncResponse)
+pub struct EpochSyncRequest(pub EpochId);
+
+impl From<&EpochSyncRequest> for MessageType {
+    fn from(value: &EpochSyncRequest) -> Self {
+        Self::EpochSyncRequest(proto::EpochSyncRequest {
+            epoch_id: MessageField::some((&value.0 .0).into()),
+            ..Default::default()
+        })
+    }
+}
+
+// *** Disconnect ***
+
+/// Sent before closing the connection
+pub struct Disconnect;
+
+impl From<&Disconnect> for MessageType {
+    fn from(_: &Disconnect) -> Self {
+        Self::Disconnect(proto::Disconnect::default())
+    }
+}
@@ -0,0 +1,216 @@
+use borsh::BorshDeserialize;
+
+use near_primitives::{
+    block::{Block, BlockHeader},
+    hash::CryptoHash,
+    syncing,
+};
+
+use super::{message_type_name, proto, MessageType, NetworkError, PeerInfo};
+
+// *** PeersResponse ***
+
+/// Peers known to the node (the response to PeersRequest)
+pub struct PeersResponse(pub Vec<PeerInfo>);
+
+impl TryFrom<&proto::PeerMessage> for PeersResponse {
+    type Error = NetworkError;
+
+    fn try_from(value: &proto::PeerMessage) -> Result<Self, Self::Error> {
+        match &value.message_type {
+            Some(MessageType::PeersResponse(response)) => Ok(Self(
+                response
+                    .peers
+                    .iter()
+                    .map(PeerInfo::try_from)
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| NetworkError::InvalidResponse)?,
+            )),
+            _ => Err(NetworkError::UnexpectedResponse),
+        }
+    }
+}
+
+// *** BlockHeadersResponse ***
+
+/// Headers of the blocks following the first known of the requested ones (the response to
+/// BlockHeadersRequest)
+pub struct BlockHeadersResponse(pub Vec<BlockHeader>);
+
+impl TryFrom<&proto::PeerMessage> for BlockHeadersResponse {
+    type Error = NetworkError;
+
+    fn try_from(value: &proto::PeerMessage) -> Result<Self, Self::Error> {
+        match &value.message_type {
+            Some(MessageType::BlockHeadersResponse(response)) => Ok(Self(
+                response
+                    .block_headers
+                    .iter()
+                    .map(|header| BlockHeader::try_from_slice(&header.borsh))
+                    .collect::<Result<_, _>>()
+                    .map_err(|_| NetworkError::InvalidResponse)?,
+            )),
+            _ => Err(NetworkError::UnexpectedResponse),
+        }
+    }
+}
+
+// *** BlockResponse ***
+
+/// The requested block (the response to BlockRequest)
+pub struct BlockResponse(pub Block);
+
+impl TryFrom<&proto::PeerMessage> for BlockResponse {
+    type Error = NetworkError;
+
+    fn try_from(value: &proto::PeerMessage) -> Result<Self, Self::Error> {
+        match &value.message_type {
+            Some(MessageType::BlockResponse(response)) => Ok(Self(
+                response
+                    .block
+                    .as_ref()
+                    .and_then(|block| Block::try_from_slice(&block.borsh).ok())
+                    .ok_or(NetworkError::InvalidResponse)?,
+            )),
+            _ => Err(NetworkError::UnexpectedResponse),
+        }
+    }
+}
+
+// *** EpochSyncResponse ***
+
+/// The response to EpochSyncRequest
+pub struct EpochSyncResponse(pub syncing::EpochSyncResponse);
+
+impl TryFrom<&proto::PeerMessage> for EpochSyncResponse {
+    type Error = NetworkError;
+
+    fn try_from(value: &proto::PeerMessage) -> Result<Self, Self::Error> {
+        match &value.message_type {
+            Some(MessageType::EpochSyncResponse(response)) => Ok(Self(
+                syncing::EpochSyncResponse::try_from_slice(&response.borsh)
+                    .map_err(|_| NetworkError::InvalidResponse)?,
+            )),
+            _ => Err(NetworkError::UnexpectedResponse),
+        }
+    }
+}
+
+// *** ReceivedMessage ***
+
+/// Content of the responses matched by content, decoded
+#[derive(Debug)]
+pub enum ResponseContent {
+    BlockHeaders(Vec<BlockHeader>),
+    Block(Block),
+}
+
+/// Message received from the node, with the content of the response decoded once (if it's
+/// matched by content and can be decoded): for matching it against all the expected
+/// responses, and for the request it answers
+#[derive(Debug)]
+pub struct ReceivedMessage {
+    pub msg: proto::PeerMessage,
+    pub content: Option<ResponseContent>,
+}
+
+impl ReceivedMessage {
+    pub fn new(msg: proto::PeerMessage) -> Self {
+        let content = match &msg.message_type {
+            Some(MessageType::BlockHeadersResponse(_)) => BlockHeadersResponse::try_from(&msg)
+                .ok()
+                .map(|response| ResponseContent::BlockHeaders(response.0)),
+            Some(MessageType::BlockResponse(_)) => BlockResponse::try_from(&msg)
+                .ok()
+                .map(|response| ResponseContent::Block(response.0)),
+            _ => None,
+        };
+
+        Self { msg, content }
+    }
+}
+
+// *** ExpectedResponse ***
+
+/// How the message matches the expected response
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ResponseMatch {
+    No,
+    /// The type matches, but the content can't be checked
+    Type,
+    /// Both the type and the content match
+    Content,
+}
+
+/// Response expected to the request. The requests have no ids, the response is just a later
+/// message of the matching type, so its content is checked where possible to tell the
+/// responses to the concurrent requests apart.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ExpectedResponse {
+    Peers,
+    /// Headers following one of the given blocks
+    BlockHeaders(Vec<CryptoHash>),
+    Block(CryptoHash),
+    EpochSync,
+    EpochSyncFinalization,
+}
+
+impl ExpectedResponse {
+    /// Response expected to the message, None if the message is not a request
+    pub fn of(request: &proto::PeerMessage) -> Option<Self> {
+        let hash = |hash: &protobuf::MessageField<proto::CryptoHash>| {
+            hash.as_ref()
+                .and_then(|hash| CryptoHash::try_from(hash).ok())
+                .unwrap_or_default()
+        };
+
+        Some(match request.message_type.as_ref()? {
+            MessageType::PeersRequest(_) => Self::Peers,
+            MessageType::BlockHeadersRequest(request) => Self::BlockHeaders(
+                request
+                    .block_hashes
+                    .iter()
+                    .filter_map(|hash| CryptoHash::try_from(hash).ok())
+                    .collect(),
+            ),
+            MessageType::BlockRequest(request) => Self::Block(hash(&request.block_hash)),
+            MessageType::EpochSyncRequest(_) => Self::EpochSync,
+            MessageType::EpochSyncFinalizationRequest(_) => Self::EpochSyncFinalization,
+            _ => return None,
+        })
+    }
+
+    /// Name of the type of the response message (as printed by "decode" command)
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Self::Peers => "peers_response",
+            Self::BlockHeaders(_) => "block_headers_response",
+            Self::Block(_) => "block_response",
+            Self::EpochSync => "epoch_sync_response",
+            Self::EpochSyncFinalization => "epoch_sync_finalization_response",
+        }
+    }
+
+    /// Checks the type of the message, and its content where possible: the hash of the block,
+    /// or the previous hash of the first header (an empty list of headers or a block that
+    /// can't be decoded match by type only)
+    pub fn matches(&self, received: &ReceivedMessage) -> ResponseMatch {
+        if message_type_name(&received.msg) != self.type_name() {
+            return ResponseMatch::No;
+        }
+
+        let content_matches = match (self, &received.content) {
+            (Self::BlockHeaders(hashes), Some(ResponseContent::BlockHeaders(headers))) => headers
+                .first()
+                .map(|header| hashes.contains(header.prev_hash())),
+            (Self::Block(hash), Some(ResponseContent::Block(block))) => Some(block.hash() == hash),
+            _ => None,
+        };
+
+        match content_matches {
+            Some(true) => ResponseMatch::Content,
+            Some(false) => ResponseMatch::No,
+            None => ResponseMatch::Type,
+        }
+    }
+}
@@ -0,0 +1,182 @@
+use std::io;
+
+use borsh::{BorshDeserialize, BorshSerialize};
+
+use protobuf::{well_known_types::timestamp::Timestamp, MessageField};
+
+use serde::{Deserialize, Serialize};
+
+use near_crypto::{SecretKey, Signature};
+
+use near_primitives::{hash::CryptoHash, network::PeerId};
+
+use super::{proto, MessageType};
+
+/// Time-to-live of the routed messages (the number of hops, the same as in nearcore)
+pub const ROUTED_MESSAGE_TTL: u8 = 100;
+
+// Indexes of the variants of nearcore RoutedMessageBody enum
+const PING_VARIANT: u8 = 14;
+const PONG_VARIANT: u8 = 15;
+
+// *** PeerIdOrHash ***
+
+/// Target of the routed message: a peer, or the hash of the message it responds to
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum PeerIdOrHash {
+    PeerId(PeerId),
+    Hash(CryptoHash),
+}
+
+// *** RoutedMessageBody ***
+
+/// Ping routed through the network to the target peer, which responds with Pong
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Ping {
+    pub nonce: u64,
+    pub source: PeerId,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Pong {
+    pub nonce: u64,
+    pub source: PeerId,
+}
+
+/// Body of the routed message. Only Ping and Pong are supported (the other bodies of nearcore
+/// RoutedMessageBody enum can't be decoded).
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub enum RoutedMessageBody {
+    Ping(Ping),
+    Pong(Pong),
+}
+
+impl BorshSerialize for RoutedMessageBody {
+    fn serialize<W: io::Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self {
+            Self::Ping(ping) => {
+                writer.write_all(&[PING_VARIANT])?;
+                BorshSerialize::serialize(ping, writer)
+            }
+            Self::Pong(pong) => {
+                writer.write_all(&[PONG_VARIANT])?;
+                BorshSerialize::serialize(pong, writer)
+            }
+        }
+    }
+}
+
+impl BorshDeserialize for RoutedMessageBody {
+    fn deserialize(buf: &mut &[u8]) -> io::Result<Self> {
+        match <u8 as BorshDeserialize>::deserialize(buf)? {
+            PING_VARIANT => Ok(Self::Ping(BorshDeserialize::deserialize(buf)?)),
+            PONG_VARIANT => Ok(Self::Pong(BorshDeserialize::deserialize(buf)?)),
+            variant => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("unsupported routed message body: {}", variant),
+            )),
+        }
+    }
+}
+
+// *** RoutedMessage ***
+
+/// Message routed through the network, signed by its author (the same borsh layout as in
+/// nearcore)
+#[derive(BorshSerialize, BorshDeserialize, Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct RoutedMessage {
+    pub target: PeerIdOrHash,
+    pub author: PeerId,
+    pub signature: Signature,
+    pub ttl: u8,
+    pub body: RoutedMessageBody,
+}
+
+impl RoutedMessage {
+    fn build_hash(target: &PeerIdOrHash, author: &PeerId, body: &RoutedMessageBody) -> CryptoHash {
+        CryptoHash::hash_borsh(&(target, author, body))
+    }
+
+    pub fn new(target: PeerIdOrHash, body: RoutedMessageBody, secret_key: &SecretKey) -> Self {
+        let author = PeerId::new(secret_key.public_key());
+        let hash = Self::build_hash(&target, &author, &body);
+
+        Self {
+            target,
+            author,
+            signature: secret_key.sign(hash.as_ref()),
+            ttl: ROUTED_MESSAGE_TTL,
+            body,
+        }
+    }
+
+    /// Checks that the message was signed by its author
+    pub fn verify(&self) -> bool {
+        let hash = Self::build_hash(&self.target, &self.author, &self.body);
+        self.signature
+            .verify(hash.as_ref(), self.author.public_key())
+    }
+}
+
+impl From<&RoutedMessage> for MessageType {
+    fn from(value: &RoutedMessage) -> Self {
+        Self::Routed(proto::RoutedMessage {
+            borsh: value.try_to_vec().unwrap(),
+            created_at: MessageField::some(Timestamp::now()),
+            ..Default::default()
+        })
+    }
+}
+
+pub type ParseRoutedMessageError = borsh::maybestd::io::Error;
+
+impl TryFrom<&proto::RoutedMessage> for RoutedMessage {
+    type Error = ParseRoutedMessageError;
+
+    fn try_from(value: &proto::RoutedMessage) -> Result<Self, Self::Error> {
+        Self::try_from_slice(&value.borsh)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::KeyType;
+
+    use super::*;
+
+    #[test]
+    fn test_routed_message() {
+        let secret_key = SecretKey::from_random(KeyType::ED25519);
+        let target = PeerId::new(SecretKey::from_random(KeyType::ED25519).public_key());
+
+        let msg = RoutedMessage::new(
+            PeerIdOrHash::PeerId(target),
+            RoutedMessageBody::Ping(Ping {
+                nonce: 1,
+                source: PeerId::new(secret_key.public_key()),
+            }),
+            &secret_key,
+        );
+        assert!(msg.verify());
+
+        let proto_msg = match MessageType::from(&msg) {
+            MessageType::Routed(proto_msg) => proto_msg,
+            _ => unreachable!(),
+        };
+        assert_eq!(RoutedMessage::try_from(&proto_msg).unwrap(), msg);
+
+        // The body is encoded as the Ping variant of nearcore RoutedMessageBody
+        let body = msg.body.try_to_vec().unwrap();
+        assert_eq!(body[0], PING_VARIANT);
+        assert_eq!(&body[1..9], &1u64.to_le_bytes());
+
+        let mut tampered = msg;
+        tampered.ttl = 1;
+        assert!(tampered.verify());
+        tampered.body = RoutedMessageBody::Pong(Pong {
+            nonce: 1,
+            source: tampered.author.clone(),
+        });
+        assert!(!tampered.verify());
+    }
+}
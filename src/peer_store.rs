@@ -0,0 +1,439 @@
+use std::{
+    cmp::Ordering,
+    collections::{BTreeMap, VecDeque},
+    fs, io,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+};
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Serialize};
+
+use tokio::time::Duration;
+
+use near_primitives::{
+    hash::CryptoHash,
+    network::PeerId,
+    types::{AccountId, BlockHeight, ShardId},
+    version::ProtocolVersion,
+};
+
+use crate::{
+    error::Error,
+    network_protocol::{Handshake, NetworkError, PeerInfo},
+};
+
+/// Number of the last handshake outcomes kept for each peer
+const HISTORY_LEN: usize = 20;
+
+/// Handshake latency costing one point of the score, and the maximum latency penalty
+const LATENCY_MS_PER_POINT: f64 = 10.0;
+const MAX_LATENCY_PENALTY: f64 = 50.0;
+
+/// Outcome of the handshake with the peer
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Outcome {
+    pub time: DateTime<Utc>,
+    /// "ok" or the error kind
+    pub result: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub latency_ms: Option<u64>,
+}
+
+impl Outcome {
+    fn is_ok(&self) -> bool {
+        self.result == "ok"
+    }
+}
+
+/// Chain info reported by the peer in the last successful handshake
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct StoredChainInfo {
+    pub chain_id: String,
+    pub genesis_hash: CryptoHash,
+    pub height: BlockHeight,
+    pub tracked_shards: Vec<ShardId>,
+    pub archival: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Ban {
+    pub time: DateTime<Utc>,
+    pub reason: String,
+}
+
+/// Everything known about the peer
+#[derive(Serialize, Deserialize, Default, Debug)]
+#[serde(default)]
+pub struct PeerRecord {
+    pub addr: Option<SocketAddr>,
+    pub account_id: Option<AccountId>,
+    /// Time of the last successful handshake
+    pub last_seen: Option<DateTime<Utc>>,
+    pub protocol_version: Option<ProtocolVersion>,
+    pub chain_info: Option<StoredChainInfo>,
+    /// The last handshake outcomes, the oldest first
+    pub history: VecDeque<Outcome>,
+    pub ban: Option<Ban>,
+}
+
+impl PeerRecord {
+    /// Score of the peer (higher is better): the share of the successful handshakes (a peer
+    /// with no handshakes counts as half successful) scaled to 100, minus a point for each
+    /// 10 ms of the average handshake latency (50 points at most)
+    pub fn score(&self) -> f64 {
+        let success = (self.successes() + 1) as f64 / (self.history.len() + 2) as f64;
+
+        let latency_penalty = self.latency().map_or(0.0, |latency| {
+            (latency.as_secs_f64() * 1000.0 / LATENCY_MS_PER_POINT).min(MAX_LATENCY_PENALTY)
+        });
+
+        100.0 * success - latency_penalty
+    }
+
+    /// Number of the successful handshakes of the history
+    pub fn successes(&self) -> usize {
+        self.history
+            .iter()
+            .filter(|outcome| outcome.is_ok())
+            .count()
+    }
+
+    /// Average latency of the successful handshakes of the history
+    pub fn latency(&self) -> Option<Duration> {
+        let latencies: Vec<u64> = self
+            .history
+            .iter()
+            .filter(|outcome| outcome.is_ok())
+            .filter_map(|outcome| outcome.latency_ms)
+            .collect();
+
+        match latencies.len() {
+            0 => None,
+            n => Some(Duration::from_millis(
+                latencies.iter().sum::<u64>() / n as u64,
+            )),
+        }
+    }
+}
+
+/// Reason to ban the peer the handshake failed with the error: the peer signs its messages
+/// with a key other than the one of its peer id. The errors caused by the configuration of
+/// the tool (e.g. a wrong genesis) are not a reason, nor is another node responding at the
+/// address (the address or the peer id given may be stale, the peer itself didn't misbehave).
+fn ban_reason(e: &NetworkError) -> Option<String> {
+    match e {
+        NetworkError::InvalidSignature => Some(e.to_string()),
+        _ => None,
+    }
+}
+
+/// Persistent store of the known peers (JSON file), keyed by the peer id: the handshake
+/// outcomes and the state reported by the peers, and the peers banned for misbehaving
+pub struct PeerStore {
+    path: PathBuf,
+    peers: BTreeMap<String, PeerRecord>,
+    modified: bool,
+}
+
+impl PeerStore {
+    /// Default location of the store file (~/.cache/near-handshake/peer_store.json)
+    pub fn default_path() -> Result<PathBuf, Error> {
+        Ok(PathBuf::from(std::env::var("HOME").map_err(|_| {
+            Error::Config(
+                "HOME environment variable not set (required to locate peer store file)".into(),
+            )
+        })?)
+        .join(".cache/near-handshake/peer_store.json"))
+    }
+
+    /// Loads the store from the file (a missing file means an empty store, so does a corrupt
+    /// one, with a warning)
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let peers = match fs::File::open(path) {
+            Ok(file) => serde_json::from_reader(io::BufReader::new(file)).unwrap_or_else(|e| {
+                tracing::warn!(
+                    "Error parsing peer store file {}, starting with an empty store: {}",
+                    path.display(),
+                    e
+                );
+                BTreeMap::new()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(_) => {
+                return Err(Error::Config(format!(
+                    "Error opening peer store file: {}",
+                    path.display()
+                )))
+            }
+        };
+
+        Ok(Self {
+            path: path.into(),
+            peers,
+            modified: false,
+        })
+    }
+
+    /// Writes the store to the file (if it was modified). The file is replaced atomically, by
+    /// renaming a temporary file written next to it.
+    pub fn save(&mut self) -> Result<(), Error> {
+        if !self.modified {
+            return Ok(());
+        }
+
+        let error = || {
+            Error::Config(format!(
+                "Error writing peer store file: {}",
+                self.path.display()
+            ))
+        };
+
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir).map_err(|_| error())?;
+        }
+        let tmp_path = self.path.with_extension("json.tmp");
+        fs::write(
+            &tmp_path,
+            serde_json::to_string_pretty(&self.peers).unwrap(),
+        )
+        .map_err(|_| error())?;
+        fs::rename(&tmp_path, &self.path).map_err(|_| error())?;
+
+        self.modified = false;
+
+        Ok(())
+    }
+
+    pub fn get(&self, peer_id: &PeerId) -> Option<&PeerRecord> {
+        self.peers.get(&peer_id.to_string())
+    }
+
+    /// Score of the peer, the peer not in the store has the score of a peer with no
+    /// handshakes
+    pub fn score(&self, peer_id: &PeerId) -> f64 {
+        self.get(peer_id)
+            .map_or_else(|| PeerRecord::default().score(), PeerRecord::score)
+    }
+
+    /// The ban of the peer, to be checked before connecting to it
+    pub fn ban(&self, peer_id: &PeerId) -> Option<&Ban> {
+        self.get(peer_id).and_then(|peer| peer.ban.as_ref())
+    }
+
+    /// Lifts the ban of the peer, returns false if the peer is not banned
+    pub fn unban(&mut self, peer_id: &PeerId) -> bool {
+        let ban = self
+            .peers
+            .get_mut(&peer_id.to_string())
+            .and_then(|peer| peer.ban.take());
+        self.modified |= ban.is_some();
+        ban.is_some()
+    }
+
+    /// Records the outcome of the handshake with the peer (banning the peer if the error is
+    /// a reason to), and the state the peer reported
+    pub fn record_handshake(
+        &mut self,
+        peer_id: &PeerId,
+        addr: &SocketAddr,
+        result: Result<&Handshake, &NetworkError>,
+        latency: Duration,
+    ) {
+        let now = Utc::now();
+        let peer = self.peers.entry(peer_id.to_string()).or_default();
+        peer.addr = Some(*addr);
+
+        let outcome = match result {
+            Ok(handshake) => {
+                let chain_info = &handshake.sender_chain_info;
+                peer.last_seen = Some(now);
+                peer.protocol_version = Some(handshake.protocol_version);
+                peer.chain_info = Some(StoredChainInfo {
+                    chain_id: chain_info.genesis_id.chain_id.clone(),
+                    genesis_hash: chain_info.genesis_id.hash,
+                    height: chain_info.height,
+                    tracked_shards: chain_info.tracked_shards.clone(),
+                    archival: chain_info.archival,
+                });
+
+                Outcome {
+                    time: now,
+                    result: "ok".into(),
+                    latency_ms: Some(latency.as_millis() as u64),
+                }
+            }
+            Err(e) => {
+                if let Some(reason) = ban_reason(e) {
+                    peer.ban = Some(Ban { time: now, reason });
+                }
+
+                Outcome {
+                    time: now,
+                    result: e.kind().into(),
+                    latency_ms: None,
+                }
+            }
+        };
+
+        peer.history.push_back(outcome);
+        while peer.history.len() > HISTORY_LEN {
+            peer.history.pop_front();
+        }
+
+        self.modified = true;
+    }
+
+    /// Records the peers reported by a node (their addresses and accounts)
+    pub fn record_peers(&mut self, peers: &[PeerInfo]) {
+        for info in peers {
+            let peer = self.peers.entry(info.id.to_string()).or_default();
+            if info.addr.is_some() {
+                peer.addr = info.addr;
+            }
+            if info.account_id.is_some() {
+                peer.account_id = info.account_id.clone();
+            }
+        }
+
+        self.modified |= !peers.is_empty();
+    }
+
+    /// All the peers (by peer id), the best scored first and the banned ones last
+    pub fn peers(&self) -> Vec<(&str, &PeerRecord)> {
+        let mut peers: Vec<_> = self
+            .peers
+            .iter()
+            .map(|(peer_id, peer)| (peer_id.as_str(), peer))
+            .collect();
+
+        peers.sort_by(|(_, a), (_, b)| {
+            a.ban
+                .is_some()
+                .cmp(&b.ban.is_some())
+                .then(b.score().partial_cmp(&a.score()).unwrap_or(Ordering::Equal))
+        });
+
+        peers
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use near_crypto::{ED25519PublicKey, PublicKey};
+
+    use crate::network_protocol::{HandshakeFailure, PeerChainInfo};
+
+    use super::*;
+
+    #[test]
+    fn test_peer_store() {
+        let dir = std::env::temp_dir().join(format!(
+            "near-handshake-peer-store-test-{}",
+            std::process::id()
+        ));
+        let path = dir.join("peer_store.json");
+
+        let peer_id = |i| PeerId::new(PublicKey::ED25519(ED25519PublicKey([i; 32])));
+        let (fast, slow, failing, impostor) = (peer_id(1), peer_id(2), peer_id(3), peer_id(4));
+        let addr = "127.0.0.1:24567".parse().unwrap();
+
+        let handshake = Handshake {
+            protocol_version: 57,
+            oldest_supported_version: 34,
+            sender_peer_id: fast.clone(),
+            target_peer_id: slow.clone(),
+            sender_listen_port: None,
+            sender_chain_info: PeerChainInfo {
+                height: 100,
+                ..Default::default()
+            },
+            partial_edge_info: Default::default(),
+        };
+
+        // A missing file means an empty store
+        let mut store = PeerStore::load(&path).unwrap();
+        assert!(store.peers().is_empty());
+
+        for _ in 0..3 {
+            let latency = Duration::from_millis(10);
+            store.record_handshake(&fast, &addr, Ok(&handshake), latency);
+            let latency = Duration::from_millis(200);
+            store.record_handshake(&slow, &addr, Ok(&handshake), latency);
+            let error = NetworkError::ConnectTimeout;
+            store.record_handshake(&failing, &addr, Err(&error), Duration::ZERO);
+        }
+        // The genesis mismatch is not a reason to ban the peer (the genesis of the tool may
+        // be wrong)
+        let error =
+            NetworkError::HandshakeFailure(HandshakeFailure::GenesisMismatch(Default::default()));
+        store.record_handshake(&failing, &addr, Err(&error), Duration::ZERO);
+        // Nor is another node responding at the address of the peer
+        let error = NetworkError::SenderMismatch(peer_id(7));
+        store.record_handshake(&failing, &addr, Err(&error), Duration::ZERO);
+        let error = NetworkError::InvalidSignature;
+        store.record_handshake(&impostor, &addr, Ok(&handshake), Duration::ZERO);
+        store.record_handshake(&impostor, &addr, Err(&error), Duration::ZERO);
+
+        store.record_peers(&[PeerInfo {
+            id: peer_id(5),
+            addr: Some(addr),
+            account_id: Some("node.near".parse().unwrap()),
+        }]);
+
+        store.save().unwrap();
+
+        let mut store = PeerStore::load(&path).unwrap();
+
+        let fast_peer = store.get(&fast).unwrap();
+        assert_eq!(fast_peer.protocol_version, Some(57));
+        assert_eq!(fast_peer.chain_info.as_ref().unwrap().height, 100);
+        assert_eq!(fast_peer.successes(), 3);
+        assert_eq!(fast_peer.latency(), Some(Duration::from_millis(10)));
+        assert_eq!(store.score(&peer_id(6)), 50.0);
+        assert!(store.get(&peer_id(5)).unwrap().history.is_empty());
+
+        // The successful peers first (the faster first), then the unknown one, then the
+        // failing one, the banned one last
+        let order: Vec<_> = store.peers().into_iter().map(|(id, _)| id).collect();
+        let expected: Vec<_> = [&fast, &slow, &peer_id(5), &failing, &impostor]
+            .map(|id| id.to_string())
+            .to_vec();
+        assert_eq!(order, expected);
+
+        assert!(store.ban(&fast).is_none());
+        assert!(store.ban(&failing).is_none());
+        assert_eq!(
+            store.ban(&impostor).unwrap().reason,
+            NetworkError::InvalidSignature.to_string()
+        );
+        assert!(store.unban(&impostor));
+        assert!(!store.unban(&impostor));
+        assert!(store.ban(&impostor).is_none());
+
+        // The history is limited
+        for _ in 0..HISTORY_LEN {
+            let error = NetworkError::ConnectTimeout;
+            store.record_handshake(&fast, &addr, Err(&error), Duration::ZERO);
+        }
+        let fast_peer = store.get(&fast).unwrap();
+        assert_eq!(fast_peer.history.len(), HISTORY_LEN);
+        assert_eq!(fast_peer.successes(), 0);
+
+        // A corrupt file means an empty store
+        fs::write(&path, "{\"truncated").unwrap();
+        let mut store = PeerStore::load(&path).unwrap();
+        assert!(store.peers().is_empty());
+        store.record_peers(&[PeerInfo {
+            id: peer_id(5),
+            addr: Some(addr),
+            account_id: None,
+        }]);
+        store.save().unwrap();
+        assert!(!path.with_extension("json.tmp").exists());
+        assert_eq!(PeerStore::load(&path).unwrap().peers().len(), 1);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,91 @@
+use std::{fmt, net::SocketAddr, str::FromStr};
+
+use tokio::net::lookup_host;
+
+use near_crypto::PublicKey;
+
+use near_primitives::network::PeerId;
+
+use crate::error::Error;
+
+/// Node to connect to, in the form "peer_id@host:port" or "host:port"
+#[derive(Clone, Debug, PartialEq)]
+pub struct Target {
+    pub peer_id: Option<PeerId>,
+    pub addr: String,
+}
+
+impl Target {
+    /// Resolves the address of the target (host may be a domain name)
+    pub async fn resolve(&self) -> Result<SocketAddr, Error> {
+        lookup_host(&self.addr)
+            .await
+            .ok()
+            .and_then(|mut addrs| addrs.next())
+            .ok_or_else(|| Error::Config(format!("Error resolving network address: {}", self.addr)))
+    }
+}
+
+impl FromStr for Target {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+
+        let (peer_id, addr) = match s.split_once('@') {
+            Some((peer_id, addr)) => (
+                Some(PeerId::new(PublicKey::from_str(peer_id).map_err(|_| {
+                    Error::Config(format!("Error parsing peer id of target: {}", s))
+                })?)),
+                addr,
+            ),
+            None => (None, s),
+        };
+
+        match addr.rsplit_once(':') {
+            Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => Ok(Self {
+                peer_id,
+                addr: addr.into(),
+            }),
+            _ => Err(Error::Config(format!(
+                "Error parsing network address of target (host:port expected): {}",
+                s
+            ))),
+        }
+    }
+}
+
+impl fmt::Display for Target {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.peer_id {
+            Some(peer_id) => write!(f, "{}@{}", peer_id, self.addr),
+            None => write!(f, "{}", self.addr),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_target() {
+        let target: Target = "ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX@10.0.0.1:24567"
+            .parse()
+            .unwrap();
+        assert!(target.peer_id.is_some());
+        assert_eq!(target.addr, "10.0.0.1:24567");
+        assert_eq!(
+            target.to_string(),
+            "ed25519:7PGseFbWxvYVgZ89K1uTJKYoKetWs7BJtbyXDzfbAcqX@10.0.0.1:24567"
+        );
+
+        let target: Target = "example.com:24567".parse().unwrap();
+        assert_eq!(target.peer_id, None);
+        assert_eq!(target.addr, "example.com:24567");
+
+        assert!("example.com".parse::<Target>().is_err());
+        assert!(":24567".parse::<Target>().is_err());
+        assert!("bad_key@127.0.0.1:24567".parse::<Target>().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, Write},
+    path::Path,
+};
+
+use chrono::{DateTime, Utc};
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{connection::Direction, error::Error};
+
+/// Frame of the wire transcript (one JSON object per line of the transcript file). The data
+/// is the hex encoded protobuf PeerMessage, without the 4-byte length prefix.
+#[derive(Serialize, Deserialize, Debug, PartialEq)]
+pub struct Frame {
+    pub timestamp: DateTime<Utc>,
+    /// The node the frame was exchanged with ("peer_id@host:port")
+    pub peer: String,
+    pub direction: Direction,
+    #[serde(serialize_with = "to_hex", deserialize_with = "from_hex")]
+    pub data: Vec<u8>,
+}
+
+fn to_hex<S: Serializer>(data: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_str(&hex::encode(data))
+}
+
+fn from_hex<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+    hex::decode(String::deserialize(deserializer)?).map_err(serde::de::Error::custom)
+}
+
+/// Appends the frames exchanged with a single node to the transcript file
+pub struct Recorder {
+    file: File,
+    peer: String,
+}
+
+impl Recorder {
+    pub fn open(path: &Path, peer: String) -> Result<Self, Error> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).ok();
+        }
+
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|_| {
+                Error::Config(format!("Error opening transcript file: {}", path.display()))
+            })?;
+
+        Ok(Self { file, peer })
+    }
+
+    pub fn record(&mut self, direction: Direction, data: &[u8]) -> io::Result<()> {
+        let frame = Frame {
+            timestamp: Utc::now(),
+            peer: self.peer.clone(),
+            direction,
+            data: data.to_vec(),
+        };
+
+        // The line is written at once, so the frames of the concurrent connections
+        // recorded to the same file are not mixed up
+        let mut line = serde_json::to_vec(&frame)?;
+        line.push(b'\n');
+        self.file.write_all(&line)
+    }
+}
+
+/// Reads all the frames of the transcript file
+pub fn read(path: &Path) -> Result<Vec<Frame>, Error> {
+    let file = File::open(path)
+        .map_err(|_| Error::Config(format!("Error opening transcript file: {}", path.display())))?;
+
+    io::BufReader::new(file)
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .map(|(i, line)| {
+            line.ok()
+                .and_then(|line| serde_json::from_str(&line).ok())
+                .ok_or_else(|| {
+                    Error::Config(format!(
+                        "Error parsing transcript file: {} (line {})",
+                        path.display(),
+                        i + 1
+                    ))
+                })
+        })
+        .collect()
+}